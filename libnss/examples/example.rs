@@ -0,0 +1,121 @@
+//! A small module for `libnss_example.so.2`, also used by the loader tests.
+//!
+//! Build with `cargo build --example nss_example` and load it with
+//! `libnss::loader::Module::open`.
+
+extern crate libc;
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate libnss;
+
+use libnss::database::Database;
+use libnss::group::{Group, GroupHooks};
+use libnss::init::{InitHooks, Trigger};
+use libnss::initgroups::InitgroupsHooks;
+use libnss::interop::Response;
+use libnss::passwd::{Passwd, PasswdHooks};
+
+fn test_user() -> Passwd {
+    Passwd {
+        name: "test".to_string(),
+        passwd: "x".to_string(),
+        uid: 1005,
+        gid: 1005,
+        gecos: "Test Account".to_string(),
+        dir: "/home/test".to_string(),
+        shell: "/bin/bash".to_string(),
+    }
+}
+
+/// A group too big for the buffer glibc starts with.
+fn big_group() -> Group {
+    Group {
+        name: "everyone".to_string(),
+        passwd: "x".to_string(),
+        gid: 1100,
+        members: (0..500).map(|i| format!("member{}", i)).collect(),
+    }
+}
+
+fn test_group() -> Group {
+    Group {
+        name: "test".to_string(),
+        passwd: "x".to_string(),
+        gid: 1005,
+        members: vec!["test".to_string()],
+    }
+}
+
+struct ExamplePasswd;
+libnss_passwd_hooks!(example, ExamplePasswd);
+
+impl PasswdHooks for ExamplePasswd {
+    fn get_all_entries() -> Response<Vec<Passwd>> {
+        Response::Success(vec![test_user()])
+    }
+
+    fn get_entry_by_uid(uid: libc::uid_t) -> Response<Passwd> {
+        match uid {
+            1005 => Response::Success(test_user()),
+            _ => Response::NotFound,
+        }
+    }
+
+    fn get_entry_by_name(name: String) -> Response<Passwd> {
+        match name.as_str() {
+            "test" => Response::Success(test_user()),
+            _ => Response::NotFound,
+        }
+    }
+}
+
+struct ExampleGroup;
+libnss_group_hooks!(example, ExampleGroup);
+
+impl GroupHooks for ExampleGroup {
+    fn get_all_entries() -> Response<Vec<Group>> {
+        Response::Success(vec![test_group(), big_group()])
+    }
+
+    fn get_entry_by_gid(gid: libc::gid_t) -> Response<Group> {
+        match gid {
+            1005 => Response::Success(test_group()),
+            1100 => Response::Success(big_group()),
+            _ => Response::NotFound,
+        }
+    }
+
+    fn get_entry_by_name(name: String) -> Response<Group> {
+        match name.as_str() {
+            "test" => Response::Success(test_group()),
+            "everyone" => Response::Success(big_group()),
+            _ => Response::NotFound,
+        }
+    }
+}
+
+struct ExampleInitgroups;
+libnss_initgroups_hooks!(example, ExampleInitgroups);
+
+impl InitgroupsHooks for ExampleInitgroups {
+    fn get_entries_by_user(user: String) -> Response<Vec<Group>> {
+        match user.as_str() {
+            "test" => Response::Success(vec![test_group(), big_group()]),
+            _ => Response::NotFound,
+        }
+    }
+}
+
+struct ExampleInit;
+libnss_init_hooks!(example, ExampleInit);
+
+/// nscd drops its cached users and groups when the backend touches these.
+impl InitHooks for ExampleInit {
+    fn triggers() -> Vec<Trigger> {
+        vec![
+            Trigger::new(Database::Passwd, "/var/lib/example/passwd.changed"),
+            Trigger::new(Database::Group, "/var/lib/example/group.changed"),
+        ]
+    }
+}
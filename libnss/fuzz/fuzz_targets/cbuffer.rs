@@ -0,0 +1,13 @@
+#![no_main]
+
+use arbitrary::Unstructured;
+use libfuzzer_sys::fuzz_target;
+
+use libnss_fuzz::{check, input};
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    if let (Ok(layout), Ok(ops)) = (input::layout(&mut u), input::ops(&mut u)) {
+        check::cbuffer(&ops, &layout);
+    }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use arbitrary::Unstructured;
+use libfuzzer_sys::fuzz_target;
+
+use libnss::group::CGroup;
+use libnss_fuzz::{check, input};
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    if let (Ok(layout), Ok(group)) = (input::layout(&mut u), input::group(&mut u)) {
+        check::entry::<CGroup, _>(group, &layout);
+    }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use arbitrary::Unstructured;
+use libfuzzer_sys::fuzz_target;
+
+use libnss::host::CHost;
+use libnss_fuzz::{check, input};
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    if let (Ok(layout), Ok(host)) = (input::layout(&mut u), input::host(&mut u)) {
+        check::entry::<CHost, _>(host, &layout);
+    }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use arbitrary::Unstructured;
+use libfuzzer_sys::fuzz_target;
+
+use libnss::passwd::CPasswd;
+use libnss_fuzz::{check, input};
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    if let (Ok(layout), Ok(passwd)) = (input::layout(&mut u), input::passwd(&mut u)) {
+        check::entry::<CPasswd, _>(passwd, &layout);
+    }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use arbitrary::Unstructured;
+use libfuzzer_sys::fuzz_target;
+
+use libnss::shadow::CShadow;
+use libnss_fuzz::{check, input};
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    if let (Ok(layout), Ok(shadow)) = (input::layout(&mut u), input::shadow(&mut u)) {
+        check::entry::<CShadow, _>(shadow, &layout);
+    }
+});
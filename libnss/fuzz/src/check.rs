@@ -0,0 +1,437 @@
+//! Marshals entries into guarded buffers and panics on anything glibc would
+//! trip over.
+
+use std::io;
+use std::mem;
+
+use libc::{c_char, c_int, c_void};
+
+use libnss::group::{CGroup, Group};
+use libnss::host::{Addresses, CHost, Host};
+use libnss::interop::{CBuffer, NssStatus, Response, ToC};
+use libnss::passwd::{CPasswd, Passwd};
+use libnss::shadow::{CShadow, Shadow};
+use libnss::testing::FromC;
+
+/// Canary bytes on either side of the buffer.
+const GUARD: usize = 64;
+const CANARY: u8 = 0xa5;
+const PTR: usize = mem::size_of::<*mut c_char>();
+
+/// The buffer glibc hands over: `buflen` bytes, starting `offset` bytes past
+/// an arbitrary address so that alignment varies.
+#[derive(Debug)]
+pub struct Layout {
+    pub buflen: usize,
+    pub offset: usize,
+}
+
+/// A buffer surrounded by canaries that every write must leave alone.
+pub struct Guarded {
+    data: Vec<u8>,
+    start: usize,
+    len: usize,
+}
+
+impl Guarded {
+    pub fn new(layout: &Layout) -> Self {
+        let start = GUARD + layout.offset;
+        Guarded {
+            data: vec![CANARY; start + layout.buflen + GUARD],
+            start,
+            len: layout.buflen,
+        }
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut c_char {
+        self.data[self.start..].as_mut_ptr() as *mut c_char
+    }
+
+    fn addr(&self) -> usize {
+        self.data.as_ptr() as usize + self.start
+    }
+
+    pub fn assert_intact(&self) {
+        let before = &self.data[..self.start];
+        let after = &self.data[self.start + self.len..];
+        assert!(
+            before.iter().chain(after).all(|byte| *byte == CANARY),
+            "wrote outside the buffer"
+        );
+    }
+
+    /// Whether the `len` bytes at `addr` are all inside the buffer.
+    fn contains(&self, addr: usize, len: usize) -> bool {
+        addr >= self.addr()
+            && addr
+                .checked_add(len)
+                .map_or(false, |end| end <= self.addr() + self.len)
+    }
+
+    /// Asserts `ptr` is a string terminated inside the buffer.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must be initialized.
+    pub unsafe fn assert_string(&self, ptr: *const c_char) {
+        assert!(self.contains(ptr as usize, 1), "string outside the buffer");
+        let end = self.addr() + self.len;
+        let mut pos = ptr as usize;
+        while pos < end && *(pos as *const u8) != 0 {
+            pos += 1;
+        }
+        assert!(pos < end, "string not terminated inside the buffer");
+    }
+
+    /// Asserts `ptr` is an aligned, null terminated array inside the buffer
+    /// and returns its items.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must be initialized.
+    pub unsafe fn assert_array(&self, ptr: *const *mut c_char) -> Vec<*mut c_char> {
+        assert_eq!(
+            ptr as usize % mem::align_of::<*mut c_char>(),
+            0,
+            "unaligned array"
+        );
+        let mut items = Vec::new();
+        let mut pos = ptr;
+        loop {
+            assert!(
+                self.contains(pos as usize, PTR),
+                "array runs outside the buffer"
+            );
+            if (*pos).is_null() {
+                return items;
+            }
+            items.push(*pos);
+            pos = pos.add(1);
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The buffer must be initialized.
+    pub unsafe fn assert_strings(&self, ptr: *const *mut c_char) {
+        for item in self.assert_array(ptr) {
+            self.assert_string(item);
+        }
+    }
+}
+
+/// Counts the bytes `CBuffer` uses for a sequence of writes.
+pub struct Size {
+    addr: usize,
+    pub len: usize,
+}
+
+impl Size {
+    pub fn new(addr: usize) -> Self {
+        Size { addr, len: 0 }
+    }
+
+    pub fn string(&mut self, string: &str) {
+        self.len += string.len() + 1;
+    }
+
+    pub fn strings<S: AsRef<str>>(&mut self, strings: &[S]) {
+        self.align(PTR);
+        self.reserve(PTR * (strings.len() + 1));
+        for string in strings {
+            self.string(string.as_ref());
+        }
+    }
+
+    pub fn align(&mut self, align: usize) {
+        let addr = self.addr + self.len;
+        self.len += (align - addr % align) % align;
+    }
+
+    pub fn reserve(&mut self, len: usize) {
+        self.len += len;
+    }
+}
+
+/// An entry whose marshalling can be checked.
+pub trait Model<C>: ToC<C> + FromC<C> {
+    /// Counts the bytes the entry needs, in the order `to_c` writes them.
+    fn size(&self, size: &mut Size);
+
+    fn strings(&self) -> Vec<&str>;
+
+    /// Asserts every pointer in `c` lands inside `buffer`.
+    ///
+    /// # Safety
+    ///
+    /// `c` must have been written into `buffer`.
+    unsafe fn assert_inside(c: &C, buffer: &Guarded);
+
+    /// Asserts `decoded` holds what `self` was marshalled from.
+    fn assert_decoded(&self, decoded: &Self);
+}
+
+impl Model<CPasswd> for Passwd {
+    fn size(&self, size: &mut Size) {
+        for string in self.strings() {
+            size.string(string);
+        }
+    }
+
+    fn strings(&self) -> Vec<&str> {
+        vec![
+            &self.name,
+            &self.passwd,
+            &self.gecos,
+            &self.dir,
+            &self.shell,
+        ]
+    }
+
+    unsafe fn assert_inside(c: &CPasswd, buffer: &Guarded) {
+        for ptr in [c.name, c.passwd, c.gecos, c.dir, c.shell] {
+            buffer.assert_string(ptr);
+        }
+    }
+
+    fn assert_decoded(&self, decoded: &Self) {
+        assert_eq!(self.strings(), decoded.strings());
+        assert_eq!((self.uid, self.gid), (decoded.uid, decoded.gid));
+    }
+}
+
+impl Model<CGroup> for Group {
+    fn size(&self, size: &mut Size) {
+        size.string(&self.name);
+        size.string(&self.passwd);
+        size.strings(&self.members);
+    }
+
+    fn strings(&self) -> Vec<&str> {
+        let names = [self.name.as_str(), self.passwd.as_str()];
+        names
+            .iter()
+            .copied()
+            .chain(self.members.iter().map(String::as_str))
+            .collect()
+    }
+
+    unsafe fn assert_inside(c: &CGroup, buffer: &Guarded) {
+        buffer.assert_string(c.name);
+        buffer.assert_string(c.passwd);
+        buffer.assert_strings(c.members);
+    }
+
+    fn assert_decoded(&self, decoded: &Self) {
+        assert_eq!(self.strings(), decoded.strings());
+        assert_eq!(self.gid, decoded.gid);
+    }
+}
+
+impl Model<CShadow> for Shadow {
+    fn size(&self, size: &mut Size) {
+        size.string(&self.name);
+        size.string(&self.passwd);
+    }
+
+    fn strings(&self) -> Vec<&str> {
+        vec![&self.name, &self.passwd]
+    }
+
+    unsafe fn assert_inside(c: &CShadow, buffer: &Guarded) {
+        buffer.assert_string(c.name);
+        buffer.assert_string(c.passwd);
+    }
+
+    fn assert_decoded(&self, decoded: &Self) {
+        assert_eq!(self.strings(), decoded.strings());
+        let days = |s: &Shadow| {
+            [
+                s.last_change,
+                s.change_min_days,
+                s.change_max_days,
+                s.change_warn_days,
+                s.change_inactive_days,
+                s.expire_date,
+            ]
+        };
+        assert_eq!(days(self), days(decoded));
+        assert_eq!(self.reserved, decoded.reserved);
+    }
+}
+
+fn address_len(addresses: &Addresses) -> (usize, usize) {
+    match addresses {
+        Addresses::V4(addrs) => (4, addrs.len()),
+        Addresses::V6(addrs) => (16, addrs.len()),
+    }
+}
+
+impl Model<CHost> for Host {
+    fn size(&self, size: &mut Size) {
+        let (len, count) = address_len(&self.addresses);
+        size.string(&self.name);
+        size.strings(&self.aliases);
+        size.align(PTR);
+        size.reserve(PTR * (count + 1));
+        size.reserve(len * count);
+    }
+
+    fn strings(&self) -> Vec<&str> {
+        std::iter::once(&self.name)
+            .chain(&self.aliases)
+            .map(String::as_str)
+            .collect()
+    }
+
+    unsafe fn assert_inside(c: &CHost, buffer: &Guarded) {
+        buffer.assert_string(c.name);
+        buffer.assert_strings(c.h_aliases);
+        let len = match c.h_addrtype {
+            libc::AF_INET => 4,
+            libc::AF_INET6 => 16,
+            family => panic!("unexpected address family {}", family),
+        };
+        assert_eq!(c.h_length, len as c_int);
+        for addr in buffer.assert_array(c.h_addr_list) {
+            assert!(
+                buffer.contains(addr as usize, len),
+                "address outside the buffer"
+            );
+        }
+    }
+
+    fn assert_decoded(&self, decoded: &Self) {
+        assert_eq!(self.strings(), decoded.strings());
+        match (&self.addresses, &decoded.addresses) {
+            (Addresses::V4(a), Addresses::V4(b)) => assert_eq!(a, b),
+            (Addresses::V6(a), Addresses::V6(b)) => assert_eq!(a, b),
+            _ => panic!("address family changed"),
+        }
+    }
+}
+
+/// Marshals `entry` the way the generated `_nss_*` functions do and checks
+/// that nothing lands outside the buffer, that the result decodes back to
+/// `entry`, and that `ERANGE` comes back exactly when it does not fit,
+/// which is when `required_size` says so.
+pub fn entry<C, T: Model<C>>(entry: T, layout: &Layout) {
+    let mut buffer = Guarded::new(layout);
+    let mut size = Size::new(buffer.addr());
+    entry.size(&mut size);
+    let fits = size.len <= layout.buflen;
+    let nul = entry.strings().iter().any(|string| string.contains('\0'));
+    match entry.required_size(buffer.as_mut_ptr()) {
+        Ok(required) => assert_eq!(required, size.len, "required_size is off"),
+        Err(_) => assert!(nul, "required_size failed"),
+    }
+
+    let response = Response::Success(entry);
+    let mut result: C = unsafe { mem::zeroed() };
+    let mut errno: c_int = 0;
+    let status =
+        unsafe { response.to_c(&mut result, buffer.as_mut_ptr(), layout.buflen, &mut errno) };
+    buffer.assert_intact();
+
+    let unavail = (NssStatus::Unavail, libc::ENOENT);
+    let erange = (NssStatus::TryAgain, libc::ERANGE);
+    match (nul, fits) {
+        (true, true) => assert_eq!((status, errno), unavail),
+        // Whichever of the two comes first
+        (true, false) => assert!((status, errno) == unavail || (status, errno) == erange),
+        (false, false) => assert_eq!(
+            (status, errno),
+            erange,
+            "{} bytes do not fit in {}",
+            size.len,
+            layout.buflen
+        ),
+        (false, true) => {
+            assert_eq!(
+                (status, errno),
+                (NssStatus::Success, 0),
+                "{} bytes fit in {}",
+                size.len,
+                layout.buflen
+            );
+            let entry = match response {
+                Response::Success(entry) => entry,
+                _ => unreachable!(),
+            };
+            unsafe {
+                T::assert_inside(&result, &buffer);
+                entry.assert_decoded(&T::from_c(&result));
+            }
+        }
+    }
+}
+
+/// A single `CBuffer` call.
+#[derive(Debug)]
+pub enum Op {
+    String(String),
+    Strings(Vec<String>),
+    /// A power of two.
+    Align(usize),
+    Reserve(usize),
+}
+
+/// Runs `ops` against one `CBuffer` until the first of them fails, which
+/// must be exactly the first one that does not fit.
+pub fn cbuffer(ops: &[Op], layout: &Layout) {
+    let mut buffer = Guarded::new(layout);
+    let mut size = Size::new(buffer.addr());
+    let mut cbuffer = CBuffer::new(buffer.as_mut_ptr() as *mut c_void, layout.buflen);
+    unsafe { cbuffer.clear() };
+    buffer.assert_intact();
+
+    for op in ops {
+        let (result, nul) = unsafe {
+            match op {
+                Op::String(string) => {
+                    size.string(string);
+                    let result = cbuffer.write_str(string).map(|ptr| {
+                        buffer.assert_string(ptr);
+                    });
+                    (result, string.contains('\0'))
+                }
+                Op::Strings(strings) => {
+                    size.strings(strings);
+                    let result = cbuffer.write_strs(strings).map(|ptr| {
+                        buffer.assert_strings(ptr);
+                    });
+                    (result, strings.iter().any(|string| string.contains('\0')))
+                }
+                Op::Align(align) => {
+                    size.align(*align);
+                    (cbuffer.align(*align), false)
+                }
+                Op::Reserve(len) => {
+                    size.reserve(*len);
+                    let result = cbuffer.reserve(*len as isize).map(|ptr| {
+                        assert!(
+                            buffer.contains(ptr as usize, *len),
+                            "reservation outside the buffer"
+                        );
+                    });
+                    (result, false)
+                }
+            }
+        };
+        buffer.assert_intact();
+
+        let fits = size.len <= layout.buflen;
+        match result {
+            Ok(()) => {
+                assert!(!nul, "{:?} was written", op);
+                assert!(fits, "{} bytes were written to {}", size.len, layout.buflen);
+            }
+            Err(err) if nul && err.kind() == io::ErrorKind::InvalidInput => return,
+            Err(err) => {
+                assert_eq!(err.raw_os_error(), Some(libc::ERANGE), "{:?} failed", op);
+                assert!(!fits, "{} bytes did not fit in {}", size.len, layout.buflen);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Builds entries and buffers from fuzzer input.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use arbitrary::{Result, Unstructured};
+
+use libnss::group::Group;
+use libnss::host::{Addresses, Host};
+use libnss::passwd::Passwd;
+use libnss::shadow::Shadow;
+
+use crate::check::{Layout, Op};
+
+/// Calls a target builds at most, the buffer fills up long before.
+const MAX_OPS: usize = 64;
+
+pub fn layout(u: &mut Unstructured) -> Result<Layout> {
+    Ok(Layout {
+        buflen: u.int_in_range(0..=4096)?,
+        offset: u.int_in_range(0..=15)?,
+    })
+}
+
+pub fn passwd(u: &mut Unstructured) -> Result<Passwd> {
+    Ok(Passwd {
+        name: u.arbitrary()?,
+        passwd: u.arbitrary()?,
+        uid: u.arbitrary()?,
+        gid: u.arbitrary()?,
+        gecos: u.arbitrary()?,
+        dir: u.arbitrary()?,
+        shell: u.arbitrary()?,
+    })
+}
+
+pub fn group(u: &mut Unstructured) -> Result<Group> {
+    Ok(Group {
+        name: u.arbitrary()?,
+        passwd: u.arbitrary()?,
+        gid: u.arbitrary()?,
+        members: u.arbitrary()?,
+    })
+}
+
+pub fn shadow(u: &mut Unstructured) -> Result<Shadow> {
+    Ok(Shadow {
+        name: u.arbitrary()?,
+        passwd: u.arbitrary()?,
+        last_change: u.arbitrary()?,
+        change_min_days: u.arbitrary()?,
+        change_max_days: u.arbitrary()?,
+        change_warn_days: u.arbitrary()?,
+        change_inactive_days: u.arbitrary()?,
+        expire_date: u.arbitrary()?,
+        reserved: u.arbitrary()?,
+    })
+}
+
+pub fn host(u: &mut Unstructured) -> Result<Host> {
+    let name = u.arbitrary()?;
+    let aliases = u.arbitrary()?;
+    let addresses = match u.arbitrary()? {
+        true => Addresses::V6(
+            u.arbitrary::<Vec<[u8; 16]>>()?
+                .into_iter()
+                .map(Ipv6Addr::from)
+                .collect(),
+        ),
+        false => Addresses::V4(
+            u.arbitrary::<Vec<[u8; 4]>>()?
+                .into_iter()
+                .map(Ipv4Addr::from)
+                .collect(),
+        ),
+    };
+    Ok(Host {
+        name,
+        aliases,
+        addresses,
+        ttl: u.arbitrary()?,
+    })
+}
+
+pub fn ops(u: &mut Unstructured) -> Result<Vec<Op>> {
+    let mut ops = Vec::new();
+    while !u.is_empty() && ops.len() < MAX_OPS {
+        ops.push(match u.int_in_range(0..=3)? {
+            0 => Op::String(u.arbitrary()?),
+            1 => Op::Strings(u.arbitrary()?),
+            2 => Op::Align(1 << u.int_in_range(0..=6)?),
+            _ => Op::Reserve(u.int_in_range(0..=512)?),
+        });
+    }
+    Ok(ops)
+}
@@ -0,0 +1,9 @@
+//! Shared code of the fuzz targets, run with `cargo +nightly fuzz run <target>`
+//! from the `libnss` directory.
+//!
+//! Every target marshals what the fuzzer built into a buffer of a random size
+//! and alignment, surrounded by canaries, and compares the result with a
+//! model of how `CBuffer` lays data out.
+
+pub mod check;
+pub mod input;
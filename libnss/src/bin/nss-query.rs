@@ -0,0 +1,745 @@
+//! `getent` for a single NSS module: loads the `.so` directly, without
+//! `/etc/nsswitch.conf`, and reports what every call returned.
+//!
+//! ```text
+//! nss-query target/debug/libnss_nya.so passwd alice 1000
+//! nss-query --json target/debug/libnss_nya.so group
+//! ```
+
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::process;
+use std::ptr;
+use std::time::{Duration, Instant};
+
+use libc::{c_int, gid_t, uid_t};
+use serde_json::{json, Value};
+
+use libnss::group::{CGroup, Group};
+use libnss::host::{Addresses, CHost, Host};
+use libnss::interop::{NssStatus, ToC};
+use libnss::loader::{Module, MAX_BUFLEN};
+use libnss::passwd::{CPasswd, Passwd};
+use libnss::shadow::{CShadow, Shadow};
+use libnss::testing::{
+    self, Call, FromC, GetByIdFn, GetByNameFn, GetEntFn, GetHostByAddrFn, GetHostByName2Fn,
+    InitgroupsDynFn, SetEntFn, DEFAULT_BUFLEN,
+};
+
+const USAGE: &str = "\
+usage: nss-query [OPTIONS] MODULE.so DATABASE [KEY...]
+
+Looks KEYs up in DATABASE (passwd, group, shadow, hosts or initgroups) of the
+module, or enumerates the database when no KEY is given. Enumerations run a
+second time starting every call with a 1 byte buffer, and are checked for
+duplicate entries and for entries lost when a module reports ERANGE.
+
+Options:
+  --json            print one JSON object per lookup
+  --name NAME       the module name in _nss_NAME_*, by default taken from
+                    libnss_NAME.so
+  --buflen BYTES    the buffer lookups start with (default 1024)
+  --family FAMILY   inet or inet6, for hosts looked up by name (default inet)
+  --gid GID         the group initgroups skips, usually the primary group
+
+Exit status: 0 on success, 1 on usage or load errors, 2 when a lookup did not
+succeed and 3 when an enumeration check failed.";
+
+/// Enumerations stop here, in case a module starts over instead of ending.
+const MAX_ENTRIES: usize = 1 << 20;
+
+struct Options {
+    json: bool,
+    name: Option<String>,
+    buflen: usize,
+    family: c_int,
+    skipgroup: gid_t,
+    path: String,
+    database: String,
+    keys: Vec<String>,
+}
+
+impl Options {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut options = Options {
+            json: false,
+            name: None,
+            buflen: DEFAULT_BUFLEN,
+            family: libc::AF_INET,
+            skipgroup: gid_t::MAX,
+            path: String::new(),
+            database: String::new(),
+            keys: Vec::new(),
+        };
+        let mut positional = Vec::new();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--json" => options.json = true,
+                "--name" => options.name = Some(value()?),
+                "--buflen" => {
+                    options.buflen = match value()?.parse() {
+                        Ok(buflen) if buflen > 0 && buflen <= MAX_BUFLEN => buflen,
+                        _ => return Err(format!("--buflen must be 1 to {}", MAX_BUFLEN)),
+                    }
+                }
+                "--family" => {
+                    options.family = match value()?.as_str() {
+                        "inet" => libc::AF_INET,
+                        "inet6" => libc::AF_INET6,
+                        family => return Err(format!("unknown family {}", family)),
+                    }
+                }
+                "--gid" => {
+                    options.skipgroup = value()?
+                        .parse()
+                        .map_err(|_| "--gid must be a number".to_string())?
+                }
+                "-h" | "--help" => return Err(String::new()),
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ => positional.push(arg),
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        options.path = positional.next().ok_or("missing MODULE.so")?;
+        options.database = positional.next().ok_or("missing DATABASE")?;
+        options.keys = positional.collect();
+        match options.database.as_str() {
+            "passwd" | "group" | "shadow" | "hosts" => {}
+            "initgroups" if !options.keys.is_empty() => {}
+            "initgroups" => return Err("initgroups needs a user".to_string()),
+            database => return Err(format!("unknown database {}", database)),
+        }
+        Ok(options)
+    }
+}
+
+/// How an entry is printed and what must be unique across an enumeration.
+trait Entry {
+    /// The entry the way `getent` prints it.
+    fn line(&self) -> String;
+
+    fn json(&self) -> Value;
+
+    /// Values no two entries of a database may share, e.g. `uid 1000`.
+    fn keys(&self) -> Vec<String>;
+
+    /// The buffer the entry needs, when it is marshalled into one.
+    fn needs(&self) -> Option<usize> {
+        None
+    }
+}
+
+impl Entry for Passwd {
+    fn line(&self) -> String {
+        format!(
+            "{}:{}:{}:{}:{}:{}:{}",
+            self.name, self.passwd, self.uid, self.gid, self.gecos, self.dir, self.shell
+        )
+    }
+
+    fn json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+
+    fn keys(&self) -> Vec<String> {
+        vec![format!("name {}", self.name), format!("uid {}", self.uid)]
+    }
+
+    fn needs(&self) -> Option<usize> {
+        self.required_size(ptr::null()).ok()
+    }
+}
+
+impl Entry for Group {
+    fn line(&self) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            self.name,
+            self.passwd,
+            self.gid,
+            self.members.join(",")
+        )
+    }
+
+    fn json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+
+    fn keys(&self) -> Vec<String> {
+        vec![format!("name {}", self.name), format!("gid {}", self.gid)]
+    }
+
+    fn needs(&self) -> Option<usize> {
+        self.required_size(ptr::null()).ok()
+    }
+}
+
+impl Entry for Shadow {
+    fn line(&self) -> String {
+        // Like putspent, which leaves unset fields empty
+        let days = |days: Option<u32>| days.map_or_else(String::new, |days| days.to_string());
+        format!(
+            "{}:{}:{}:{}:{}:{}:{}:{}:",
+            self.name,
+            self.passwd,
+            days(self.last_change),
+            days(self.change_min_days),
+            days(self.change_max_days),
+            days(self.change_warn_days),
+            days(self.change_inactive_days),
+            days(self.expire_date),
+        )
+    }
+
+    fn json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+
+    fn keys(&self) -> Vec<String> {
+        vec![format!("name {}", self.name)]
+    }
+
+    fn needs(&self) -> Option<usize> {
+        self.required_size(ptr::null()).ok()
+    }
+}
+
+fn addresses(host: &Host) -> Vec<String> {
+    match &host.addresses {
+        Addresses::V4(addrs) => addrs.iter().map(|addr| addr.to_string()).collect(),
+        Addresses::V6(addrs) => addrs.iter().map(|addr| addr.to_string()).collect(),
+    }
+}
+
+impl Entry for Host {
+    /// One line per address, like `getent ahosts` without the socket types.
+    fn line(&self) -> String {
+        let names = std::iter::once(&self.name)
+            .chain(&self.aliases)
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" ");
+        addresses(self)
+            .iter()
+            .map(|addr| format!("{:<15} {}", addr, names))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+
+    fn keys(&self) -> Vec<String> {
+        vec![format!("name {}", self.name)]
+    }
+
+    fn needs(&self) -> Option<usize> {
+        self.required_size(ptr::null()).ok()
+    }
+}
+
+/// The groups `initgroups_dyn` found for a user.
+struct Initgroups {
+    user: String,
+    groups: Vec<gid_t>,
+}
+
+impl Entry for Initgroups {
+    fn line(&self) -> String {
+        let groups = self.groups.iter().map(|gid| gid.to_string());
+        std::iter::once(self.user.clone())
+            .chain(groups)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn json(&self) -> Value {
+        json!({ "user": self.user, "groups": self.groups })
+    }
+
+    fn keys(&self) -> Vec<String> {
+        vec![format!("user {}", self.user)]
+    }
+}
+
+/// A call repeated with a doubled buffer for as long as it reported `ERANGE`.
+struct Lookup<T> {
+    call: Call<T>,
+    /// The buffer the final call was made with, if it took one.
+    buflen: Option<usize>,
+    retries: usize,
+    elapsed: Duration,
+}
+
+fn lookup<T, F: FnMut(usize) -> Call<T>>(buflen: usize, mut call: F) -> Lookup<T> {
+    let start = Instant::now();
+    let mut buflen = buflen;
+    let mut retries = 0;
+    loop {
+        let result = call(buflen);
+        if erange(&result) && buflen < MAX_BUFLEN {
+            buflen = (buflen * 2).min(MAX_BUFLEN);
+            retries += 1;
+            continue;
+        }
+        return Lookup {
+            call: result,
+            buflen: Some(buflen),
+            retries,
+            elapsed: start.elapsed(),
+        };
+    }
+}
+
+fn erange<T>(call: &Call<T>) -> bool {
+    call.status == NssStatus::TryAgain && call.errno == libc::ERANGE
+}
+
+fn status(code: c_int) -> NssStatus {
+    NssStatus::from_c(code).unwrap_or(NssStatus::Unavail)
+}
+
+fn status_name(status: NssStatus) -> &'static str {
+    match status {
+        NssStatus::TryAgain => "NSS_STATUS_TRYAGAIN",
+        NssStatus::Unavail => "NSS_STATUS_UNAVAIL",
+        NssStatus::NotFound => "NSS_STATUS_NOTFOUND",
+        NssStatus::Success => "NSS_STATUS_SUCCESS",
+        NssStatus::Return => "NSS_STATUS_RETURN",
+    }
+}
+
+fn errno_text(errno: c_int) -> String {
+    match errno {
+        0 => "0".to_string(),
+        errno => format!("{} ({})", errno, io::Error::from_raw_os_error(errno)),
+    }
+}
+
+fn millis(elapsed: Duration) -> f64 {
+    elapsed.as_secs_f64() * 1000.0
+}
+
+struct Query<'a> {
+    options: &'a Options,
+    module: Module,
+}
+
+impl<'a> Query<'a> {
+    /// Looks `function` up, reporting it when the module does not export it.
+    fn function<F: Copy>(&self, function: &str) -> Option<F> {
+        // Every caller asks for the type the hook macros define the function with
+        let f = unsafe { self.module.function(function) };
+        if f.is_none() {
+            eprintln!(
+                "nss-query: {} does not export _nss_{}_{}",
+                self.options.path,
+                self.module.name(),
+                function
+            );
+        }
+        f
+    }
+
+    /// Prints a lookup and returns whether it succeeded.
+    fn report<T: Entry>(&self, key: &str, lookup: Lookup<T>) -> bool {
+        let call = &lookup.call;
+        let needs = call.entry.as_ref().and_then(Entry::needs);
+        if self.options.json {
+            let output = json!({
+                "database": self.options.database,
+                "key": key,
+                "status": status_name(call.status),
+                "code": call.status as c_int,
+                "errno": call.errno,
+                "h_errno": call.h_errno,
+                "buflen": lookup.buflen,
+                "retries": lookup.retries,
+                "time_ms": millis(lookup.elapsed),
+                "needs": needs,
+                "entry": call.entry.as_ref().map(Entry::json),
+            });
+            println!("{}", output);
+        } else {
+            if let Some(entry) = &call.entry {
+                println!("{}", entry.line());
+            }
+            let h_errno = match call.h_errno {
+                Some(h_errno) => format!(" h_errno={}", h_errno),
+                None => String::new(),
+            };
+            let buflen = match lookup.buflen {
+                Some(buflen) => format!(" buflen={} retries={}", buflen, lookup.retries),
+                None => String::new(),
+            };
+            let needs = match needs {
+                Some(needs) => format!(" needs={}", needs),
+                None => String::new(),
+            };
+            eprintln!(
+                "# {} status={} ({}) errno={}{}{}{} time={:.3}ms",
+                key,
+                status_name(call.status),
+                call.status as c_int,
+                errno_text(call.errno),
+                h_errno,
+                buflen,
+                needs,
+                millis(lookup.elapsed),
+            );
+        }
+        call.status == NssStatus::Success
+    }
+
+    fn by_name<C, T: FromC<C> + Entry>(&self, function: &str, key: &str) -> bool {
+        let f: GetByNameFn<C> = match self.function(function) {
+            Some(f) => f,
+            None => return false,
+        };
+        let lookup = lookup(self.options.buflen, |buflen| {
+            testing::get_by_name::<C, T>(f, key, buflen)
+        });
+        self.report(key, lookup)
+    }
+
+    fn by_id<C, T: FromC<C> + Entry>(&self, function: &str, key: &str, id: uid_t) -> bool {
+        let f: GetByIdFn<C> = match self.function(function) {
+            Some(f) => f,
+            None => return false,
+        };
+        let lookup = lookup(self.options.buflen, |buflen| {
+            testing::get_by_id::<C, T>(f, id, buflen)
+        });
+        self.report(key, lookup)
+    }
+
+    fn host(&self, key: &str) -> bool {
+        let lookup = match key.parse::<IpAddr>() {
+            Ok(addr) => {
+                let f: GetHostByAddrFn = match self.function("gethostbyaddr_r") {
+                    Some(f) => f,
+                    None => return false,
+                };
+                lookup(self.options.buflen, |buflen| {
+                    testing::get_host_by_addr(f, addr, buflen)
+                })
+            }
+            Err(_) => {
+                let f: GetHostByName2Fn = match self.function("gethostbyname2_r") {
+                    Some(f) => f,
+                    None => return false,
+                };
+                lookup(self.options.buflen, |buflen| {
+                    testing::get_host_by_name(f, key, self.options.family, buflen)
+                })
+            }
+        };
+        self.report(key, lookup)
+    }
+
+    fn initgroups(&self, user: &str) -> bool {
+        let f: InitgroupsDynFn = match self.function("initgroups_dyn") {
+            Some(f) => f,
+            None => return false,
+        };
+        let start = Instant::now();
+        let call = testing::initgroups_dyn(f, user, self.options.skipgroup, &[], 16, 0);
+        let elapsed = start.elapsed();
+        let call = Call {
+            status: call.status,
+            errno: call.errno,
+            h_errno: call.h_errno,
+            entry: call.entry.map(|groups| Initgroups {
+                user: user.to_string(),
+                groups,
+            }),
+        };
+        self.report(
+            user,
+            Lookup {
+                call,
+                buflen: None,
+                retries: 0,
+                elapsed,
+            },
+        )
+    }
+
+    fn lookup(&self, key: &str) -> bool {
+        match (self.options.database.as_str(), key.parse::<uid_t>()) {
+            ("passwd", Ok(uid)) => self.by_id::<CPasswd, Passwd>("getpwuid_r", key, uid),
+            ("passwd", Err(_)) => self.by_name::<CPasswd, Passwd>("getpwnam_r", key),
+            ("group", Ok(gid)) => self.by_id::<CGroup, Group>("getgrgid_r", key, gid),
+            ("group", Err(_)) => self.by_name::<CGroup, Group>("getgrnam_r", key),
+            ("shadow", _) => self.by_name::<CShadow, Shadow>("getspnam_r", key),
+            ("hosts", _) => self.host(key),
+            _ => self.initgroups(key),
+        }
+    }
+
+    fn enumerate(&self) -> i32 {
+        match self.options.database.as_str() {
+            "passwd" => self.check::<CPasswd, Passwd>("setpwent", "getpwent_r", "endpwent"),
+            "group" => self.check::<CGroup, Group>("setgrent", "getgrent_r", "endgrent"),
+            "shadow" => self.check::<CShadow, Shadow>("setspent", "getspent_r", "endspent"),
+            _ => self.check::<CHost, Host>("sethostent", "gethostent_r", "endhostent"),
+        }
+    }
+
+    /// Enumerates the database twice, prints the entries of the first pass
+    /// and checks both for the mistakes glibc would not notice.
+    fn check<C, T: FromC<C> + Entry>(&self, set: &str, get: &str, end: &str) -> i32 {
+        let (setent, getent, endent): (SetEntFn, GetEntFn<C>, SetEntFn) =
+            match (self.function(set), self.function(get), self.function(end)) {
+                (Some(setent), Some(getent), Some(endent)) => (setent, getent, endent),
+                _ => return 2,
+            };
+
+        let pass: Pass<T> = Pass::run(setent, getent, endent, self.options.buflen);
+        let failed = pass.problems("enumeration");
+        let mut problems = Vec::new();
+
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for key in pass.entries.iter().flat_map(Entry::keys) {
+            *seen.entry(key).or_default() += 1;
+        }
+        let mut duplicates = seen
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .collect::<Vec<_>>();
+        duplicates.sort();
+        for (key, count) in &duplicates {
+            problems.push(format!("{} is used by {} entries", key, count));
+        }
+
+        let small: Pass<T> = Pass::run(setent, getent, endent, 1);
+        problems.extend(small.problems("enumeration with a 1 byte buffer"));
+        let lines = pass.entries.iter().map(Entry::line).collect::<Vec<_>>();
+        let small_lines = small.entries.iter().map(Entry::line).collect::<Vec<_>>();
+        if lines != small_lines {
+            let at = lines
+                .iter()
+                .zip(&small_lines)
+                .take_while(|(a, b)| a == b)
+                .count();
+            problems.push(format!(
+                "a 1 byte buffer returned {} entries instead of {}, first differing at entry {}",
+                small_lines.len(),
+                lines.len(),
+                at
+            ));
+        }
+        if !small.entries.is_empty() && small.retries == 0 {
+            problems.push("a 1 byte buffer never returned ERANGE".to_string());
+        }
+
+        // Not a problem, but every lookup of these takes a retry
+        let oversized = pass
+            .entries
+            .iter()
+            .filter_map(|entry| match entry.needs() {
+                Some(needs) if needs > DEFAULT_BUFLEN => Some((entry.keys().remove(0), needs)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        if self.options.json {
+            let output = json!({
+                "database": self.options.database,
+                "enumeration": pass.json(),
+                "erange": small.json(),
+                "duplicates": duplicates.iter().map(|(key, _)| key).collect::<Vec<_>>(),
+                "oversized": oversized
+                    .iter()
+                    .map(|(key, needs)| json!({ "entry": key, "needs": needs }))
+                    .collect::<Vec<_>>(),
+                "problems": failed.iter().chain(&problems).collect::<Vec<_>>(),
+                "entries": pass.entries.iter().map(Entry::json).collect::<Vec<_>>(),
+            });
+            println!("{}", output);
+        } else {
+            for line in &lines {
+                println!("{}", line);
+            }
+            eprintln!("# enumeration {}", pass.summary());
+            eprintln!("# 1 byte buffer {}", small.summary());
+            for (key, needs) in &oversized {
+                eprintln!(
+                    "# {} needs {} bytes, more than the {} glibc starts with",
+                    key, needs, DEFAULT_BUFLEN
+                );
+            }
+            for problem in failed.iter().chain(&problems) {
+                eprintln!("# problem: {}", problem);
+            }
+        }
+
+        if !failed.is_empty() {
+            2
+        } else if !problems.is_empty() {
+            3
+        } else {
+            0
+        }
+    }
+}
+
+/// One `set*ent`, `get*ent_r`, `end*ent` sequence.
+struct Pass<T> {
+    setent: NssStatus,
+    entries: Vec<T>,
+    /// The status and errno of the call that ended the enumeration.
+    end: (NssStatus, c_int),
+    calls: usize,
+    retries: usize,
+    endent: NssStatus,
+    elapsed: Duration,
+}
+
+impl<T> Pass<T> {
+    fn run<C>(setent: SetEntFn, getent: GetEntFn<C>, endent: SetEntFn, buflen: usize) -> Pass<T>
+    where
+        T: FromC<C>,
+    {
+        let start = Instant::now();
+        let mut pass = Pass {
+            setent: status(setent()),
+            entries: Vec::new(),
+            end: (NssStatus::Unavail, 0),
+            calls: 0,
+            retries: 0,
+            endent: NssStatus::Unavail,
+            elapsed: Duration::ZERO,
+        };
+        while pass.setent == NssStatus::Success && pass.entries.len() < MAX_ENTRIES {
+            let lookup = lookup(buflen, |buflen| {
+                testing::call::<C, T, _>(buflen, |result, buf, buflen, errnop| unsafe {
+                    getent(result, buf, buflen, errnop)
+                })
+            });
+            pass.calls += lookup.retries + 1;
+            pass.retries += lookup.retries;
+            match lookup.call.entry {
+                Some(entry) => pass.entries.push(entry),
+                None => {
+                    pass.end = (lookup.call.status, lookup.call.errno);
+                    break;
+                }
+            }
+        }
+        pass.endent = status(endent());
+        pass.elapsed = start.elapsed();
+        pass
+    }
+
+    fn problems(&self, what: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.setent != NssStatus::Success {
+            problems.push(format!("{} failed to start", what));
+        } else if self.entries.len() >= MAX_ENTRIES {
+            problems.push(format!(
+                "{} did not end after {} entries",
+                what, MAX_ENTRIES
+            ));
+        } else if self.end.0 != NssStatus::NotFound {
+            problems.push(format!(
+                "{} ended with {} and errno {}",
+                what,
+                status_name(self.end.0),
+                errno_text(self.end.1)
+            ));
+        }
+        if self.endent != NssStatus::Success {
+            problems.push(format!("{} failed to close", what));
+        }
+        problems
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "setent={} entries={} calls={} retries={} end={} errno={} endent={} time={:.3}ms",
+            status_name(self.setent),
+            self.entries.len(),
+            self.calls,
+            self.retries,
+            status_name(self.end.0),
+            errno_text(self.end.1),
+            status_name(self.endent),
+            millis(self.elapsed),
+        )
+    }
+
+    fn json(&self) -> Value {
+        json!({
+            "setent": status_name(self.setent),
+            "entries": self.entries.len(),
+            "calls": self.calls,
+            "retries": self.retries,
+            "status": status_name(self.end.0),
+            "errno": self.end.1,
+            "endent": status_name(self.endent),
+            "time_ms": millis(self.elapsed),
+        })
+    }
+}
+
+/// `example` for `libnss_example.so.2`.
+fn module_name(path: &str) -> Option<String> {
+    let file = Path::new(path).file_name()?.to_str()?;
+    let name = file.strip_prefix("libnss_")?.split(".so").next()?;
+    match name.is_empty() {
+        true => None,
+        false => Some(name.to_string()),
+    }
+}
+
+fn main() {
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            if !err.is_empty() {
+                eprintln!("nss-query: {}", err);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(1);
+        }
+    };
+    let name = match options.name.clone().or_else(|| module_name(&options.path)) {
+        Some(name) => name,
+        None => {
+            eprintln!(
+                "nss-query: cannot tell the module name from {}, pass --name",
+                options.path
+            );
+            process::exit(1);
+        }
+    };
+    let module = match Module::open(&options.path, &name) {
+        Ok(module) => module,
+        Err(err) => {
+            eprintln!("nss-query: {}", err);
+            process::exit(1);
+        }
+    };
+
+    let query = Query {
+        options: &options,
+        module,
+    };
+    if options.keys.is_empty() {
+        process::exit(query.enumerate());
+    }
+    let mut code = 0;
+    for key in &options.keys {
+        if !query.lookup(key) {
+            code = 2;
+        }
+    }
+    process::exit(code);
+}
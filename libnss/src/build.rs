@@ -0,0 +1,520 @@
+//! Build support for modules: call it from `build.rs` with libnss as a
+//! build dependency to keep the built library from exporting anything but
+//! the `_nss_<name>_*` functions glibc looks for.
+//!
+//! ```ignore
+//! // build.rs
+//! fn main() {
+//!     libnss::build::Exports::scan("src")
+//!         .and_then(|exports| exports.emit())
+//!         .expect("failed to set up the NSS exports");
+//! }
+//! ```
+//!
+//! rustc already hands the linker a version script listing every
+//! `#[no_mangle]` function of the crate graph, so [`Exports::emit`] hides the
+//! ones coming from dependencies with `--exclude-libs`. The generated version
+//! script is only passed along with [`Exports::version_script`]: lld merges
+//! it with rustc's and refuses to link when a listed function is missing,
+//! GNU ld refuses a second script altogether.
+//!
+//! [`Exports::check`] then tells from the built library which functions are
+//! missing and which others are exported, e.g. in a test of the module.
+
+use std::collections::BTreeSet;
+use std::convert::TryInto;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::database::Database;
+
+/// The hook macros and the database each of them registers, if any.
+const HOOKS: &[(&str, Option<Database>)] = &[
+    ("libnss_passwd_hooks!", Some(Database::Passwd)),
+    ("libnss_group_hooks!", Some(Database::Group)),
+    ("libnss_shadow_hooks!", Some(Database::Shadow)),
+    ("libnss_host_hooks!", Some(Database::Hosts)),
+    ("libnss_initgroups_hooks!", Some(Database::Initgroups)),
+    ("libnss_init_hooks!", None),
+];
+
+/// The functions a module must export, and nothing else.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exports {
+    name: String,
+    databases: Vec<Database>,
+    init: bool,
+    sources: Option<PathBuf>,
+    version_script: bool,
+}
+
+/// What [`Exports::check`] found in a built library.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Check {
+    /// Functions glibc looks for that the library does not export.
+    pub missing: Vec<String>,
+    /// Symbols the library exports besides the NSS functions.
+    pub unexpected: Vec<String>,
+}
+
+impl Check {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+impl Exports {
+    /// The exports of `libnss_<name>.so.2`, without any database yet.
+    pub fn new(name: &str) -> Self {
+        Exports {
+            name: name.to_string(),
+            databases: Vec::new(),
+            init: false,
+            sources: None,
+            version_script: false,
+        }
+    }
+
+    pub fn database(mut self, database: Database) -> Self {
+        if !self.databases.contains(&database) {
+            self.databases.push(database);
+        }
+        self
+    }
+
+    /// Whether the module exports `_nss_<name>_init` for nscd, see
+    /// [`libnss_init_hooks!`](crate::libnss_init_hooks).
+    pub fn init(mut self, enabled: bool) -> Self {
+        self.init = enabled;
+        self
+    }
+
+    /// Passes the generated version script to the linker too, which needs
+    /// lld (the default of recent Rust on x86_64 Linux).
+    pub fn version_script(mut self, enabled: bool) -> Self {
+        self.version_script = enabled;
+        self
+    }
+
+    /// Finds the `libnss_*_hooks!` invocations in the `.rs` files under `dir`,
+    /// which must all use the same module name.
+    pub fn scan<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let mut files = Vec::new();
+        sources(dir.as_ref(), &mut files)?;
+        files.sort();
+
+        let mut exports: Option<Exports> = None;
+        for file in files {
+            let source = fs::read_to_string(&file)?;
+            let found =
+                hooks(&source).map_err(|err| invalid(format!("{}: {}", file.display(), err)))?;
+            for (name, database) in found {
+                let current = exports.take().unwrap_or_else(|| Exports::new(&name));
+                if current.name != name {
+                    return Err(invalid(format!(
+                        "{} registers module {}, not {}",
+                        file.display(),
+                        name,
+                        current.name
+                    )));
+                }
+                exports = Some(match database {
+                    Some(database) => current.database(database),
+                    None => current.init(true),
+                });
+            }
+        }
+
+        let mut exports = exports.ok_or_else(|| {
+            invalid(format!(
+                "no libnss_*_hooks! invocations under {}",
+                dir.as_ref().display()
+            ))
+        })?;
+        exports.sources = Some(dir.as_ref().to_path_buf());
+        Ok(exports)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn databases(&self) -> &[Database] {
+        &self.databases
+    }
+
+    /// Every function glibc may look up in the module.
+    pub fn symbols(&self) -> Vec<String> {
+        self.databases
+            .iter()
+            .flat_map(|database| database.functions())
+            .chain(self.init.then_some(&"init"))
+            .map(|function| format!("_nss_{}_{}", self.name, function))
+            .collect()
+    }
+
+    /// A linker version script exporting [`symbols`](Self::symbols) only.
+    pub fn render(&self) -> String {
+        let mut script = String::from("{\n  global:\n");
+        for symbol in self.symbols() {
+            script.push_str(&format!("    {};\n", symbol));
+        }
+        script.push_str("  local:\n    *;\n};\n");
+        script
+    }
+
+    /// Writes `libnss_<name>.map` to `OUT_DIR` and tells cargo how to link
+    /// the module. Returns where the script was written.
+    pub fn emit(&self) -> io::Result<PathBuf> {
+        let out_dir = env::var_os("OUT_DIR")
+            .ok_or_else(|| invalid("OUT_DIR is not set, call emit from build.rs".to_string()))?;
+        let path = PathBuf::from(out_dir).join(format!("libnss_{}.map", self.name));
+        fs::write(&path, self.render())?;
+
+        if let Some(sources) = &self.sources {
+            println!("cargo:rerun-if-changed={}", sources.display());
+        }
+        println!("cargo:rustc-cdylib-link-arg=-Wl,--exclude-libs,ALL");
+        if self.version_script {
+            println!(
+                "cargo:rustc-cdylib-link-arg=-Wl,--version-script={}",
+                path.display()
+            );
+        }
+        Ok(path)
+    }
+
+    /// Compares what `library` exports with [`symbols`](Self::symbols).
+    pub fn check<P: AsRef<Path>>(&self, library: P) -> io::Result<Check> {
+        let exported = exported_symbols(library.as_ref())?
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+        let expected = self.symbols();
+        Ok(Check {
+            missing: expected
+                .iter()
+                .filter(|symbol| !exported.contains(*symbol))
+                .cloned()
+                .collect(),
+            unexpected: exported
+                .into_iter()
+                .filter(|symbol| !expected.contains(symbol))
+                .collect(),
+        })
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn sources(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            sources(&path, files)?;
+        } else if path.extension() == Some("rs".as_ref()) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The module name and database of every hook macro invoked in `source`.
+///
+/// Comments and literals are skipped. `#[cfg]` cannot be evaluated here, so
+/// an invocation with a `cfg` attribute is an error rather than a guess.
+fn hooks(source: &str) -> Result<Vec<(String, Option<Database>)>, String> {
+    let code = strip_comments(source);
+    let mut found = Vec::new();
+    for (mac, database) in HOOKS {
+        let mut start = 0;
+        while let Some(at) = code[start..].find(mac) {
+            let at = start + at;
+            start = at + mac.len();
+            let preceded = code[..at].chars().next_back();
+            if preceded.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                continue;
+            }
+            // The definition is `macro_rules! libnss_passwd_hooks {`
+            let args = match code[start..].trim_start().strip_prefix('(') {
+                Some(args) => args.trim_start(),
+                None => continue,
+            };
+            let name = args
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .collect::<String>();
+            if name.is_empty() || name.starts_with('$') {
+                continue;
+            }
+            if cfg_gated(&code[..at]) {
+                return Err(format!(
+                    "{}({}, ...) is behind #[cfg], list its databases with Exports::new",
+                    mac, name
+                ));
+            }
+            found.push((name, *database));
+        }
+    }
+    Ok(found)
+}
+
+/// Whether the attributes right before the end of `code` include a `cfg`.
+fn cfg_gated(code: &str) -> bool {
+    let mut code = code.trim_end();
+    while code.ends_with(']') {
+        let mut depth = 0;
+        let open = code.char_indices().rev().find(|(_, c)| {
+            match c {
+                ']' => depth += 1,
+                '[' => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        });
+        let open = match open {
+            Some((open, _)) => open,
+            None => return false,
+        };
+        let before = code[..open].trim_end();
+        let before = match before.strip_suffix('#') {
+            Some(before) => before,
+            None => return false,
+        };
+        let attribute = code[open + 1..].trim_start();
+        if attribute.starts_with("cfg") {
+            return true;
+        }
+        code = before.trim_end();
+    }
+    false
+}
+
+/// `source` with comments and the contents of string and character
+/// literals blanked out.
+fn strip_comments(source: &str) -> String {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut code = String::with_capacity(source.len());
+    let mut i = 0;
+    while i < chars.len() {
+        match (chars[i], chars.get(i + 1).copied()) {
+            ('/', Some('/')) => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            ('/', Some('*')) => {
+                let mut depth = 0;
+                while i < chars.len() {
+                    match (chars[i], chars.get(i + 1).copied()) {
+                        ('/', Some('*')) => {
+                            depth += 1;
+                            i += 2;
+                        }
+                        ('*', Some('/')) => {
+                            depth -= 1;
+                            i += 2;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => i += 1,
+                    }
+                }
+                code.push(' ');
+            }
+            ('r', Some('"')) | ('r', Some('#')) if !ident_before(&chars, i) => {
+                let hashes = chars[i + 1..].iter().take_while(|c| **c == '#').count();
+                if chars.get(i + 1 + hashes) != Some(&'"') {
+                    code.push('r');
+                    i += 1;
+                    continue;
+                }
+                i += hashes + 2;
+                while i < chars.len() {
+                    let closes = chars[i] == '"'
+                        && chars[i + 1..]
+                            .iter()
+                            .take(hashes)
+                            .filter(|c| **c == '#')
+                            .count()
+                            == hashes;
+                    i += 1;
+                    if closes {
+                        i += hashes;
+                        break;
+                    }
+                }
+                code.push_str("\"\"");
+            }
+            ('"', _) => {
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    i += if chars[i] == '\\' { 2 } else { 1 };
+                }
+                i += 1;
+                code.push_str("\"\"");
+            }
+            // A character literal rather than a lifetime
+            ('\'', Some('\\')) => {
+                i += 2;
+                while i < chars.len() && chars[i] != '\'' {
+                    i += 1;
+                }
+                i += 1;
+                code.push_str("' '");
+            }
+            ('\'', Some(_)) if chars.get(i + 2) == Some(&'\'') => {
+                i += 3;
+                code.push_str("' '");
+            }
+            (c, _) => {
+                code.push(c);
+                i += 1;
+            }
+        }
+    }
+    code
+}
+
+fn ident_before(chars: &[char], i: usize) -> bool {
+    i > 0 && (chars[i - 1].is_alphanumeric() || chars[i - 1] == '_')
+}
+
+/// The defined global and weak symbols in the dynamic symbol table of the
+/// ELF file at `path`.
+pub fn exported_symbols(path: &Path) -> io::Result<Vec<String>> {
+    let data = fs::read(path)?;
+    let elf = Elf::parse(&data).ok_or_else(|| {
+        invalid(format!(
+            "{} is not a little endian ELF file",
+            path.display()
+        ))
+    })?;
+    elf.dynamic_symbols()
+        .ok_or_else(|| invalid(format!("{} has a broken symbol table", path.display())))
+}
+
+/// Just enough of an ELF reader to list `.dynsym`.
+struct Elf<'a> {
+    data: &'a [u8],
+    wide: bool,
+}
+
+const SHT_DYNSYM: u32 = 11;
+const STB_GLOBAL: u8 = 1;
+const STB_WEAK: u8 = 2;
+const STT_SECTION: u8 = 3;
+const STV_HIDDEN: u8 = 2;
+
+impl<'a> Elf<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        if data.get(..4)? != b"\x7fELF" || *data.get(5)? != 1 {
+            return None;
+        }
+        let wide = match data.get(4)? {
+            1 => false,
+            2 => true,
+            _ => return None,
+        };
+        Some(Elf { data, wide })
+    }
+
+    fn u16(&self, at: usize) -> Option<u16> {
+        Some(u16::from_le_bytes(
+            self.data.get(at..at + 2)?.try_into().ok()?,
+        ))
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        Some(u32::from_le_bytes(
+            self.data.get(at..at + 4)?.try_into().ok()?,
+        ))
+    }
+
+    /// A field that is 8 bytes wide in ELF64 and 4 in ELF32.
+    fn word(&self, at: usize) -> Option<usize> {
+        match self.wide {
+            true => u64::from_le_bytes(self.data.get(at..at + 8)?.try_into().ok()?)
+                .try_into()
+                .ok(),
+            false => Some(self.u32(at)? as usize),
+        }
+    }
+
+    /// The offset, size, linked section and entry size of section `index`.
+    fn section(&self, index: usize) -> Option<(u32, usize, usize, usize, usize)> {
+        let (shoff, shentsize) = match self.wide {
+            true => (self.word(0x28)?, self.u16(0x3a)? as usize),
+            false => (self.word(0x20)?, self.u16(0x2e)? as usize),
+        };
+        let header = shoff.checked_add(index.checked_mul(shentsize)?)?;
+        let kind = self.u32(header + 4)?;
+        let (offset, size, link, entsize) = match self.wide {
+            true => (
+                self.word(header + 24)?,
+                self.word(header + 32)?,
+                self.u32(header + 40)? as usize,
+                self.word(header + 56)?,
+            ),
+            false => (
+                self.word(header + 16)?,
+                self.word(header + 20)?,
+                self.u32(header + 24)? as usize,
+                self.word(header + 36)?,
+            ),
+        };
+        Some((kind, offset, size, link, entsize))
+    }
+
+    fn string(&self, table: usize, at: usize) -> Option<String> {
+        let bytes = self.data.get(table.checked_add(at)?..)?;
+        let end = bytes.iter().position(|b| *b == 0)?;
+        Some(String::from_utf8_lossy(&bytes[..end]).into_owned())
+    }
+
+    fn dynamic_symbols(&self) -> Option<Vec<String>> {
+        let shnum = match self.wide {
+            true => self.u16(0x3c)?,
+            false => self.u16(0x30)?,
+        };
+        let mut symbols = Vec::new();
+        for index in 0..shnum as usize {
+            let (kind, offset, size, link, entsize) = self.section(index)?;
+            if kind != SHT_DYNSYM || entsize == 0 {
+                continue;
+            }
+            let strtab = self.section(link)?.1;
+            for sym in (offset..offset.checked_add(size)?).step_by(entsize) {
+                let (info, other, shndx) = match self.wide {
+                    true => (
+                        *self.data.get(sym + 4)?,
+                        *self.data.get(sym + 5)?,
+                        self.u16(sym + 6)?,
+                    ),
+                    false => (
+                        *self.data.get(sym + 12)?,
+                        *self.data.get(sym + 13)?,
+                        self.u16(sym + 14)?,
+                    ),
+                };
+                let binding = info >> 4;
+                let defined = shndx != 0;
+                let visible = other & 3 != STV_HIDDEN;
+                if defined
+                    && visible
+                    && (binding == STB_GLOBAL || binding == STB_WEAK)
+                    && info & 0xf != STT_SECTION
+                {
+                    symbols.push(self.string(strtab, self.u32(sym)? as usize)?);
+                }
+            }
+        }
+        symbols.sort();
+        symbols.dedup();
+        Some(symbols)
+    }
+}
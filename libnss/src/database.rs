@@ -0,0 +1,46 @@
+//! The databases a module can serve, shared by [`build`](crate::build),
+//! [`init`](crate::init) and [`loader`](crate::loader).
+
+/// The databases a module can implement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Database {
+    Passwd,
+    Group,
+    Shadow,
+    Hosts,
+    Initgroups,
+}
+
+impl Database {
+    /// The functions glibc may look up for this database, without the
+    /// `_nss_<name>_` prefix.
+    pub fn functions(self) -> &'static [&'static str] {
+        match self {
+            Database::Passwd => &[
+                "setpwent",
+                "endpwent",
+                "getpwent_r",
+                "getpwuid_r",
+                "getpwnam_r",
+            ],
+            Database::Group => &[
+                "setgrent",
+                "endgrent",
+                "getgrent_r",
+                "getgrgid_r",
+                "getgrnam_r",
+            ],
+            Database::Shadow => &["setspent", "endspent", "getspent_r", "getspnam_r"],
+            Database::Hosts => &[
+                "sethostent",
+                "endhostent",
+                "gethostent_r",
+                "gethostbyname_r",
+                "gethostbyname2_r",
+                "gethostbyname3_r",
+                "gethostbyaddr_r",
+            ],
+            Database::Initgroups => &["initgroups_dyn"],
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Keeping module state usable in the child of a `fork()`.
+//!
+//! Only the thread that called `fork()` lives on in the child, so a lock held
+//! by any other thread at that moment stays locked forever and the child
+//! deadlocks the first time it calls e.g. `getpwent`. libnss registers
+//! `pthread_atfork` handlers that, in the child:
+//!
+//! * put every [`Mutex`] back to a fresh, unlocked value,
+//! * bump [`generation`], for state that is easier to check lazily,
+//! * run the hooks registered with [`on_child`], e.g. to drop connections
+//!   that are shared with the parent.
+//!
+//! The hook macros keep their iterators in a [`Mutex`], so an enumeration
+//! the parent had open is simply gone in the child.
+
+use std::cell::UnsafeCell;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{self, Arc, LockResult, MutexGuard, Once};
+
+type Hook = Arc<dyn Fn() + Send + Sync>;
+
+/// A mutex that holds a value only for the process that created it: in the
+/// child of a `fork()` it is unlocked and its value is replaced by a fresh
+/// one, whoever held it in the parent.
+///
+/// The value is dropped nowhere in the child, since another thread may have
+/// been changing it. The thread calling `fork()` must not hold the lock.
+pub struct Mutex<T> {
+    inner: Box<Inner<T>>,
+}
+
+struct Inner<T> {
+    mutex: UnsafeCell<sync::Mutex<T>>,
+    reset: Box<dyn Fn() -> T + Send + Sync>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T: Default + Send + 'static> Mutex<T> {
+    /// A mutex holding `value` that starts over from `T::default()` after a
+    /// fork.
+    pub fn new(value: T) -> Self {
+        Self::with_reset(value, T::default)
+    }
+}
+
+impl<T: Send + 'static> Mutex<T> {
+    /// A mutex holding `value` that holds `reset()` in the child of a fork.
+    pub fn with_reset<F: Fn() -> T + Send + Sync + 'static>(value: T, reset: F) -> Self {
+        let inner = Box::new(Inner {
+            mutex: UnsafeCell::new(sync::Mutex::new(value)),
+            reset: Box::new(reset),
+        });
+        REGISTRY.with(|registry| {
+            registry
+                .mutexes
+                .push((&*inner as *const Inner<T> as usize, reset_mutex::<T>))
+        });
+        Mutex { inner }
+    }
+
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        unsafe { (*self.inner.mutex.get()).lock() }
+    }
+}
+
+impl<T: Default + Send + 'static> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> Drop for Mutex<T> {
+    fn drop(&mut self) {
+        let address = &*self.inner as *const Inner<T> as usize;
+        REGISTRY.with(|registry| registry.mutexes.retain(|(at, _)| *at != address));
+    }
+}
+
+/// Replaces the mutex at `address` without dropping the old one, which may
+/// be locked by a thread that does not exist anymore.
+unsafe fn reset_mutex<T>(address: usize) {
+    let inner = &*(address as *const Inner<T>);
+    ptr::write(inner.mutex.get(), sync::Mutex::new((inner.reset)()));
+}
+
+/// Runs `hook` in the child after every `fork()`, once the [`Mutex`]es are
+/// reset. Hooks run in the order they were registered; a panic in one is
+/// ignored.
+pub fn on_child<F: Fn() + Send + Sync + 'static>(hook: F) {
+    REGISTRY.with(|registry| registry.hooks.push(Arc::new(hook)));
+}
+
+/// How many `fork()`s separate this process from the one that first used
+/// libnss. State stamped with an older generation was created by a parent.
+pub fn generation() -> usize {
+    GENERATION.load(Ordering::SeqCst)
+}
+
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+static INSTALL: Once = Once::new();
+static REGISTRY: Registry = Registry {
+    locked: AtomicBool::new(false),
+    entries: UnsafeCell::new(Entries {
+        mutexes: Vec::new(),
+        hooks: Vec::new(),
+    }),
+};
+
+struct Entries {
+    mutexes: Vec<(usize, unsafe fn(usize))>,
+    hooks: Vec<Hook>,
+}
+
+/// What to do in the child, behind a spin lock rather than a `Mutex` so the
+/// fork handlers can hold it across `fork()`.
+struct Registry {
+    locked: AtomicBool,
+    entries: UnsafeCell<Entries>,
+}
+
+unsafe impl Sync for Registry {}
+
+impl Registry {
+    fn with<R, F: FnOnce(&mut Entries) -> R>(&self, f: F) -> R {
+        INSTALL.call_once(|| unsafe {
+            libc::pthread_atfork(Some(prepare), Some(parent), Some(child));
+        });
+        self.acquire();
+        let result = f(unsafe { &mut *self.entries.get() });
+        self.release();
+        result
+    }
+
+    fn acquire(&self) {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            std::thread::yield_now();
+        }
+    }
+
+    fn release(&self) {
+        self.locked.store(false, Ordering::Release);
+    }
+}
+
+/// Keeps other threads from registering while the process forks.
+extern "C" fn prepare() {
+    REGISTRY.acquire();
+}
+
+extern "C" fn parent() {
+    REGISTRY.release();
+}
+
+extern "C" fn child() {
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+
+    // The child is single threaded until it starts new threads, and the
+    // registry is still held from `prepare`.
+    let entries = unsafe { &mut *REGISTRY.entries.get() };
+    for (address, reset) in &entries.mutexes {
+        unsafe { reset(*address) };
+    }
+    let hooks = entries.hooks.clone();
+    REGISTRY.release();
+
+    for hook in hooks {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| hook()));
+    }
+}
@@ -1,7 +1,9 @@
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 use crate::interop::{CBuffer, Response, ToC};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Group {
     pub name: String,
     pub passwd: String,
@@ -47,7 +49,8 @@ macro_rules! libnss_group_hooks {
             use libc::c_int;
             use std::ffi::CStr;
             use std::str;
-            use std::sync::{Mutex, MutexGuard};
+            use std::sync::MutexGuard;
+            use $crate::fork::Mutex;
             use $crate::interop::{CBuffer, Iterator, Response};
             use $crate::group::{CGroup, GroupHooks, Group};
 
@@ -56,7 +59,7 @@ macro_rules! libnss_group_hooks {
             }
 
             #[no_mangle]
-            extern "C" fn [<_nss_ $mod_ident _setgrent>]() -> c_int {
+            pub extern "C" fn [<_nss_ $mod_ident _setgrent>]() -> c_int {
                 let mut iter: MutexGuard<Iterator<Group>> = [<GROUP_ $mod_ident _ITERATOR>].lock().unwrap();
                 let status = match(super::$hooks_ident::get_all_entries()) {
                     Response::Success(records) => iter.open(records),
@@ -66,24 +69,24 @@ macro_rules! libnss_group_hooks {
             }
 
             #[no_mangle]
-            extern "C" fn [<_nss_ $mod_ident _endgrent>]() -> c_int {
+            pub extern "C" fn [<_nss_ $mod_ident _endgrent>]() -> c_int {
                 let mut iter: MutexGuard<Iterator<Group>> = [<GROUP_ $mod_ident _ITERATOR>].lock().unwrap();
                 iter.close() as c_int
             }
 
             #[no_mangle]
-            unsafe extern "C" fn [<_nss_ $mod_ident _getgrent_r>](
+            pub unsafe extern "C" fn [<_nss_ $mod_ident _getgrent_r>](
                 result: *mut CGroup,
                 buf: *mut libc::c_char,
                 buflen: libc::size_t,
                 errnop: *mut c_int
             ) -> c_int {
                 let mut iter: MutexGuard<Iterator<Group>> = [<GROUP_ $mod_ident _ITERATOR>].lock().unwrap();
-                iter.next().to_c(result, buf, buflen, errnop) as c_int
+                iter.next_to_c(result, buf, buflen, errnop) as c_int
             }
 
             #[no_mangle]
-            unsafe extern "C" fn [<_nss_ $mod_ident _getgrgid_r>](
+            pub unsafe extern "C" fn [<_nss_ $mod_ident _getgrgid_r>](
                 uid: libc::gid_t,
                 result: *mut CGroup,
                 buf: *mut libc::c_char,
@@ -99,7 +102,7 @@ macro_rules! libnss_group_hooks {
             }
 
             #[no_mangle]
-            unsafe extern "C" fn [<_nss_ $mod_ident _getgrnam_r>](
+            pub unsafe extern "C" fn [<_nss_ $mod_ident _getgrnam_r>](
                 name_: *const libc::c_char,
                 result: *mut CGroup,
                 buf: *mut libc::c_char,
@@ -1,22 +1,44 @@
 use crate::interop::{CBuffer, Response, ToC};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::mem;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
+/// A host entry. In JSON the addresses are flattened into the entry:
+///
+/// ```json
+/// {"name": "example.test", "aliases": [], "family": "ipv4", "addresses": ["10.0.0.1"], "ttl": 300}
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Host {
     pub name: String,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub aliases: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(flatten))]
     pub addresses: Addresses,
+    /// How long, in seconds, the entry may be cached. Reported to callers of
+    /// `gethostbyname3_r`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ttl: Option<u32>,
 }
 
-#[derive(PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum AddressFamily {
     IPv4,
     IPv6,
     Unspecified,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "family", content = "addresses"))]
 pub enum Addresses {
+    #[cfg_attr(feature = "serde", serde(rename = "ipv4"))]
     V4(Vec<Ipv4Addr>),
+    #[cfg_attr(feature = "serde", serde(rename = "ipv6"))]
     V6(Vec<Ipv6Addr>),
 }
 
@@ -41,6 +63,7 @@ impl ToC<CHost> for Host {
         };
 
         let ptr_size = mem::size_of::<*mut libc::c_char>() as isize;
+        buffer.align(mem::align_of::<*mut libc::c_char>())?;
         let mut array_pos =
             buffer.reserve(ptr_size * (count as isize + 1))? as *mut *mut libc::c_char;
         (*hostent).h_addr_list = array_pos;
@@ -50,36 +73,42 @@ impl ToC<CHost> for Host {
                 for a in addrs {
                     let ptr = buffer.reserve(addr_len)?;
 
-                    let o = a.octets();
-                    libc::memcpy(
-                        ptr as *mut libc::c_void,
-                        o.as_ptr() as *mut libc::c_void,
-                        addr_len as usize,
-                    );
+                    if !buffer.is_dry_run() {
+                        let o = a.octets();
+                        libc::memcpy(
+                            ptr as *mut libc::c_void,
+                            o.as_ptr() as *mut libc::c_void,
+                            addr_len as usize,
+                        );
 
-                    *array_pos = ptr;
-                    array_pos = array_pos.offset(1);
+                        *array_pos = ptr;
+                    }
+                    array_pos = array_pos.wrapping_offset(1);
                 }
             }
             Addresses::V6(addrs) => {
                 for a in addrs {
                     let ptr = buffer.reserve(addr_len)?;
 
-                    let o = a.octets();
-                    libc::memcpy(
-                        ptr as *mut libc::c_void,
-                        o.as_ptr() as *mut libc::c_void,
-                        addr_len as usize,
-                    );
+                    if !buffer.is_dry_run() {
+                        let o = a.octets();
+                        libc::memcpy(
+                            ptr as *mut libc::c_void,
+                            o.as_ptr() as *mut libc::c_void,
+                            addr_len as usize,
+                        );
 
-                    *array_pos = ptr;
-                    array_pos = array_pos.offset(1);
+                        *array_pos = ptr;
+                    }
+                    array_pos = array_pos.wrapping_offset(1);
                 }
             }
         }
 
         // Write null termination
-        libc::memset(array_pos as *mut libc::c_void, 0, 1);
+        if !buffer.is_dry_run() {
+            *array_pos = std::ptr::null_mut();
+        }
         Ok(())
     }
 }
@@ -116,7 +145,8 @@ macro_rules! libnss_host_hooks {
             use libc::c_int;
             use std::ffi::CStr;
             use std::str;
-            use std::sync::{Mutex, MutexGuard};
+            use std::sync::MutexGuard;
+            use $crate::fork::Mutex;
             use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
             use $crate::host::{CHost, HostHooks, Host, AddressFamily};
             use $crate::interop::{CBuffer, Response, NssStatus, Iterator};
@@ -135,7 +165,7 @@ macro_rules! libnss_host_hooks {
             }
 
             #[no_mangle]
-            extern "C" fn [<_nss_ $mod_ident _sethostent>]() -> c_int {
+            pub extern "C" fn [<_nss_ $mod_ident _sethostent>]() -> c_int {
                 let mut iter: MutexGuard<Iterator<Host>> = [<HOST_ $mod_ident _ITERATOR>].lock().unwrap();
                 let status = match(super::$hooks_ident::get_all_entries()) {
                     Response::Success(entries) => iter.open(entries),
@@ -145,20 +175,20 @@ macro_rules! libnss_host_hooks {
             }
 
             #[no_mangle]
-            extern "C" fn [<_nss_ $mod_ident _endhostent>]() -> c_int {
+            pub extern "C" fn [<_nss_ $mod_ident _endhostent>]() -> c_int {
                 let mut iter: MutexGuard<Iterator<Host>> = [<HOST_ $mod_ident _ITERATOR>].lock().unwrap();
                 iter.close() as c_int
             }
 
             #[no_mangle]
-            unsafe extern "C" fn [<_nss_ $mod_ident _gethostent_r>](result: *mut CHost, buf: *mut libc::c_char, buflen: libc::size_t,
+            pub unsafe extern "C" fn [<_nss_ $mod_ident _gethostent_r>](result: *mut CHost, buf: *mut libc::c_char, buflen: libc::size_t,
                                                                   errnop: *mut c_int) -> c_int {
                 let mut iter: MutexGuard<Iterator<Host>> = [<HOST_ $mod_ident _ITERATOR>].lock().unwrap();
-                iter.next().to_c(result, buf, buflen, errnop) as c_int
+                iter.next_to_c(result, buf, buflen, errnop) as c_int
             }
 
             #[no_mangle]
-            unsafe extern "C" fn [<_nss_ $mod_ident _gethostbyaddr_r>](
+            pub unsafe extern "C" fn [<_nss_ $mod_ident _gethostbyaddr_r>](
                 addr: *const libc::c_char,
                 len: libc::size_t,
                 format: c_int,
@@ -198,7 +228,7 @@ macro_rules! libnss_host_hooks {
             }
 
             #[no_mangle]
-            unsafe extern "C" fn [<_nss_ $mod_ident _gethostbyname_r>](
+            pub unsafe extern "C" fn [<_nss_ $mod_ident _gethostbyname_r>](
                 name: *const libc::c_char,
                 result: *mut CHost,
                 buf: *mut libc::c_char,
@@ -210,7 +240,7 @@ macro_rules! libnss_host_hooks {
             }
 
             #[no_mangle]
-            unsafe extern "C" fn [<_nss_ $mod_ident _gethostbyname3_r>](
+            pub unsafe extern "C" fn [<_nss_ $mod_ident _gethostbyname3_r>](
                 name: *const libc::c_char,
                 family: libc::c_int,
                 result: *mut CHost,
@@ -221,21 +251,25 @@ macro_rules! libnss_host_hooks {
                 ttlp: *mut i32,
                 canonp: *mut *const libc::c_char
             ) -> libc::c_int {
-                let name2_res = [<_nss_ $mod_ident _gethostbyname2_r>](name, family, result, buf, buflen, errnop, h_errnop);
+                let (status, ttl) = get_host_by_name(name, family, result, buf, buflen, errnop, h_errnop);
 
                 if ! ttlp.is_null() {
-                    *ttlp = 0;
+                    *ttlp = ttl.map_or(0, |ttl| ttl.min(i32::MAX as u32) as i32);
                 }
 
                 if ! canonp.is_null() {
-                    *canonp = name;
+                    // Point at the copy in the caller's buffer, not at their argument
+                    *canonp = match status {
+                        NssStatus::Success => (*result).name,
+                        _ => name,
+                    };
                 }
 
-                name2_res
+                status as c_int
             }
 
             #[no_mangle]
-            unsafe extern "C" fn [<_nss_ $mod_ident _gethostbyname2_r>](
+            pub unsafe extern "C" fn [<_nss_ $mod_ident _gethostbyname2_r>](
                 name: *const libc::c_char,
                 family: libc::c_int,
                 result: *mut CHost,
@@ -244,13 +278,27 @@ macro_rules! libnss_host_hooks {
                 errnop: *mut libc::c_int,
                 h_errnop: *mut libc::c_int
             ) -> libc::c_int {
+                get_host_by_name(name, family, result, buf, buflen, errnop, h_errnop).0 as c_int
+            }
+
+            /// Looks up `name` and writes it to `result`, returning the
+            /// status together with the TTL of the entry.
+            unsafe fn get_host_by_name(
+                name: *const libc::c_char,
+                family: libc::c_int,
+                result: *mut CHost,
+                buf: *mut libc::c_char,
+                buflen: libc::size_t,
+                errnop: *mut libc::c_int,
+                h_errnop: *mut libc::c_int
+            ) -> (NssStatus, Option<u32>) {
 
                 let cstr = CStr::from_ptr(name);
 
-                let status = match str::from_utf8(cstr.to_bytes()) {
+                match str::from_utf8(cstr.to_bytes()) {
                     Ok(name) => {
                         use super::$hooks_ident as hooks;
-                        let status = match family {
+                        let response = match family {
                             libc::AF_INET => hooks::get_host_by_name(&name.to_string(), AddressFamily::IPv4),
                             libc::AF_INET6 => hooks::get_host_by_name(&name.to_string(), AddressFamily::IPv6),
 
@@ -264,7 +312,12 @@ macro_rules! libnss_host_hooks {
                                 *h_errnop = Herrno::NoRecovery as i32;
                                 Response::Unavail
                             },
-                        }.to_c(result, buf, buflen, errnop);
+                        };
+                        let ttl = match &response {
+                            Response::Success(host) => host.ttl,
+                            _ => None,
+                        };
+                        let status = response.to_c(result, buf, buflen, errnop);
 
                         match status {
                             NssStatus::Success => {
@@ -284,13 +337,11 @@ macro_rules! libnss_host_hooks {
                             }
                         };
 
-                        status
+                        (status, ttl)
                     }
 
-                    Err(_) => NssStatus::NotFound
-                };
-
-                status as c_int
+                    Err(_) => (NssStatus::NotFound, None)
+                }
             }
 
         }
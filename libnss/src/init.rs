@@ -0,0 +1,167 @@
+//! Telling nscd when a module's data changed.
+//!
+//! nscd calls `_nss_<name>_init` once per module with a callback to register
+//! files it should watch: whenever one of them is written, replaced or its
+//! modification time changes, nscd drops everything it cached for the
+//! database the file belongs to. A module lists its [`Trigger`]s in
+//! [`InitHooks`] and calls [`Trigger::fire`] when its data changed, e.g.
+//! after refreshing a local snapshot of the backend.
+
+use std::ffi::CString;
+use std::fs::OpenOptions;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use crate::database::Database;
+
+/// A file whose modification invalidates the cached entries of a database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trigger {
+    database: Database,
+    path: PathBuf,
+}
+
+impl Trigger {
+    /// `path` should be absolute; it does not need to exist yet, nscd also
+    /// notices when it is created.
+    pub fn new<P: Into<PathBuf>>(database: Database, path: P) -> Self {
+        Trigger {
+            database,
+            path: path.into(),
+        }
+    }
+
+    pub fn database(&self) -> Database {
+        self.database
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Marks the data as changed by setting the modification time of the
+    /// file to now. The file is neither created nor followed if it is a
+    /// symlink: the module creates it, with whatever owner and mode suit it,
+    /// before the first change.
+    pub fn fire(&self) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .append(true)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(&self.path)?;
+        // Closing a file opened for writing also wakes nscd's inotify watch
+        match unsafe { libc::futimens(file.as_raw_fd(), ptr::null()) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+pub trait InitHooks {
+    fn triggers() -> Vec<Trigger>;
+}
+
+/// nscd's cache for `database`, if it has one. Shadow entries are never
+/// cached and initgroups answers live in the group cache.
+pub fn nscd_database(database: Database) -> Option<libc::size_t> {
+    match database {
+        Database::Passwd => Some(0),
+        Database::Group | Database::Initgroups => Some(1),
+        Database::Hosts => Some(2),
+        Database::Shadow => None,
+    }
+}
+
+const PATH_MAX: usize = libc::PATH_MAX as usize;
+
+/// `struct traced_file` of nscd (glibc 2.21 and later), followed by the NUL
+/// terminated path.
+#[repr(C)]
+#[allow(missing_copy_implementations)]
+pub struct CTracedFile {
+    pub mtime: libc::time_t,
+    pub next: *mut CTracedFile,
+    pub call_res_init: libc::c_int,
+    pub inotify_descr: [libc::c_int; 2],
+    /// The directory of `fname`, watched for the file being created.
+    pub dname: [libc::c_char; PATH_MAX],
+    /// The file name part of `fname`.
+    pub sfname: *mut libc::c_char,
+    pub fname: [libc::c_char; 0],
+}
+
+/// The callback nscd passes to `_nss_<name>_init`.
+pub type InitCallback = extern "C" fn(libc::size_t, *mut CTracedFile);
+
+/// Registers `triggers` with nscd through `cb`. Triggers without an nscd
+/// cache, or with a path nscd cannot hold, are skipped.
+///
+/// # Safety
+///
+/// `cb` must be the callback nscd passed to `_nss_<name>_init`. It keeps the
+/// registered files, so they are never freed.
+pub unsafe fn register(triggers: &[Trigger], cb: Option<InitCallback>) {
+    let cb = match cb {
+        Some(cb) => cb,
+        None => return,
+    };
+    for trigger in triggers {
+        let database = match nscd_database(trigger.database) {
+            Some(database) => database,
+            None => continue,
+        };
+        if let Some(file) = traced_file(&trigger.path) {
+            cb(database, file);
+        }
+    }
+}
+
+/// Allocates a `struct traced_file` for `path` like nscd's
+/// `init_traced_file` fills it in.
+unsafe fn traced_file(path: &Path) -> Option<*mut CTracedFile> {
+    if !path.is_absolute() {
+        return None;
+    }
+    let fname = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let dname = path.parent()?.as_os_str().as_bytes();
+    if dname.len() >= PATH_MAX {
+        return None;
+    }
+
+    let size = mem::size_of::<CTracedFile>() + fname.as_bytes_with_nul().len();
+    let file = libc::calloc(1, size) as *mut CTracedFile;
+    if file.is_null() {
+        return None;
+    }
+    (*file).inotify_descr = [-1, -1];
+    ptr::copy_nonoverlapping(
+        dname.as_ptr() as *const libc::c_char,
+        ptr::addr_of_mut!((*file).dname) as *mut libc::c_char,
+        dname.len(),
+    );
+    let fname_ptr = ptr::addr_of_mut!((*file).fname) as *mut libc::c_char;
+    ptr::copy_nonoverlapping(fname.as_ptr(), fname_ptr, fname.as_bytes_with_nul().len());
+    (*file).sfname = fname_ptr.add(dname.len() + 1);
+    Some(file)
+}
+
+#[macro_export]
+macro_rules! libnss_init_hooks {
+    ($mod_ident:ident, $hooks_ident:ident) => {
+        paste::item! {
+            pub use self::[<libnss_init_ $mod_ident _hooks_impl>]::*;
+            mod [<libnss_init_ $mod_ident _hooks_impl>] {
+                use $crate::init::{InitCallback, InitHooks};
+
+                #[no_mangle]
+                pub unsafe extern "C" fn [<_nss_ $mod_ident _init>](cb: Option<InitCallback>) {
+                    $crate::init::register(&super::$hooks_ident::triggers(), cb)
+                }
+            }
+        }
+    };
+}
@@ -13,7 +13,7 @@ macro_rules! libnss_initgroups_hooks {
         mod [<libnss_initgroups_ $mod_ident _hooks_impl>] {
             #![allow(non_upper_case_globals)]
 
-            use libc::{c_int, ENOENT};
+            use libc::{c_int, ENOENT, ENOMEM};
             use std::ffi::CStr;
             use std::mem;
             use std::slice;
@@ -22,13 +22,13 @@ macro_rules! libnss_initgroups_hooks {
             use $crate::initgroups::InitgroupsHooks;
 
             #[no_mangle]
-            unsafe extern "C" fn [<_nss_ $mod_ident _initgroups_dyn>](
+            pub unsafe extern "C" fn [<_nss_ $mod_ident _initgroups_dyn>](
                 name: *const libc::c_char,
                 skipgroup: libc::gid_t,
-                start: *mut libc::size_t,
-                size: *mut libc::size_t,
-                mut groupsp: *mut *mut libc::gid_t,
-                limit: libc::size_t,
+                start: *mut libc::c_long,
+                size: *mut libc::c_long,
+                groupsp: *mut *mut libc::gid_t,
+                limit: libc::c_long,
                 errnop: *mut c_int,
             ) -> c_int {
                 let user = match std::str::from_utf8(CStr::from_ptr(name).to_bytes()) {
@@ -46,33 +46,49 @@ macro_rules! libnss_initgroups_hooks {
                         return response.to_status() as c_int;
                     }
                 };
-                let groups = groups
-                    .into_iter()
-                    .filter_map(|x| {
-                        if x.gid == skipgroup {
-                            None
-                        } else {
-                            Some(x.gid as libc::gid_t)
-                        }
-                    })
-                    .take(limit - *start)
-                    .collect::<Vec<libc::gid_t>>();
-                if groups.is_empty() {
+
+                // glibc passes a limit <= 0 when the number of groups is unbounded
+                let filled = *start as usize;
+                let limit = match limit {
+                    limit if limit > 0 => limit as usize,
+                    _ => usize::MAX,
+                };
+                let existing = match filled {
+                    0 => Vec::new(),
+                    _ => slice::from_raw_parts(*groupsp, filled).to_vec(),
+                };
+                let mut new_groups: Vec<libc::gid_t> = Vec::new();
+                for gid in groups.into_iter().map(|x| x.gid as libc::gid_t) {
+                    if filled + new_groups.len() >= limit {
+                        break;
+                    }
+                    if gid == skipgroup || existing.contains(&gid) || new_groups.contains(&gid) {
+                        continue;
+                    }
+                    new_groups.push(gid);
+                }
+                if new_groups.is_empty() {
                     return NssStatus::Success as c_int;
                 }
 
-                if *start + groups.len() != *size {
-                    let new_size = *start + groups.len();
-                    *groupsp = libc::realloc(
+                let needed = filled + new_groups.len();
+                if needed > *size as usize {
+                    let new_size = needed.max(*size as usize * 2).min(limit);
+                    let grown = libc::realloc(
                         *groupsp as *mut libc::c_void,
                         new_size * mem::size_of::<libc::gid_t>(),
                     ) as *mut libc::gid_t;
-                    *size = new_size;
+                    if grown.is_null() {
+                        *errnop = ENOMEM;
+                        return NssStatus::TryAgain as c_int;
+                    }
+                    *groupsp = grown;
+                    *size = new_size as libc::c_long;
                 }
 
-                let group_array: &mut [libc::gid_t] = slice::from_raw_parts_mut(*groupsp, *size);
-                group_array[*start..*size].copy_from_slice(&groups);
-                *start = group_array.len();
+                let group_array: &mut [libc::gid_t] = slice::from_raw_parts_mut(*groupsp, needed);
+                group_array[filled..].copy_from_slice(&new_groups);
+                *start = needed as libc::c_long;
 
                 NssStatus::Success as i32
             }
@@ -1,9 +1,28 @@
 use std::collections::VecDeque;
 use std::ffi::CString;
 use std::io;
+use std::mem::MaybeUninit;
 
 pub trait ToC<C> {
+    /// Writes `self` into `result`, placing any referenced data inside `buffer`.
+    ///
+    /// # Safety
+    ///
+    /// `result` must point to a valid, writable `C` and `buffer` must wrap memory owned by the caller.
     unsafe fn to_c(&self, result: *mut C, buffer: &mut CBuffer) -> std::io::Result<()>;
+
+    /// The exact bytes `to_c` uses in a buffer starting at `buf`, alignment
+    /// padding and pointer arrays included. A null `buf` stands for a buffer
+    /// aligned like the ones glibc allocates.
+    ///
+    /// Runs `to_c` against a [`CBuffer::dry_run`], so it fails the same way
+    /// for data C cannot hold, such as a NUL byte inside a string.
+    fn required_size(&self, buf: *const libc::c_char) -> io::Result<usize> {
+        let mut result = MaybeUninit::<C>::uninit();
+        let mut buffer = CBuffer::dry_run(buf as *mut libc::c_void);
+        unsafe { self.to_c(result.as_mut_ptr(), &mut buffer)? };
+        Ok(buffer.used())
+    }
 }
 
 #[allow(dead_code)]
@@ -16,6 +35,21 @@ pub enum NssStatus {
     Return = 2,
 }
 
+impl NssStatus {
+    /// The status an NSS function returned, if `code` is one.
+    pub fn from_c(code: libc::c_int) -> Option<NssStatus> {
+        use NssStatus::*;
+        match code {
+            -2 => Some(TryAgain),
+            -1 => Some(Unavail),
+            0 => Some(NotFound),
+            1 => Some(Success),
+            2 => Some(Return),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Response<R> {
     TryAgain,
@@ -37,6 +71,11 @@ impl<R> Response<R> {
         }
     }
 
+    /// Marshals a successful response into the glibc supplied `result` and `buf`.
+    ///
+    /// # Safety
+    ///
+    /// `result`, `buf` (of `buflen` bytes) and `errnop` must be valid pointers supplied by glibc.
     pub unsafe fn to_c<C>(
         &self,
         result: *mut C,
@@ -58,6 +97,10 @@ impl<R> Response<R> {
                 }
                 Err(e) => match e.raw_os_error() {
                     Some(e) => {
+                        // Leave nothing of an entry that did not fit behind
+                        if e == libc::ERANGE {
+                            buffer.clear();
+                        }
                         *errnop = e;
                         Self::TryAgain.to_status()
                     }
@@ -77,6 +120,12 @@ pub struct Iterator<T> {
     items: Option<VecDeque<T>>,
 }
 
+impl<T> Default for Iterator<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Iterator<T> {
     pub fn new() -> Self {
         Iterator { items: None }
@@ -86,6 +135,7 @@ impl<T> Iterator<T> {
         NssStatus::Success
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Response<T> {
         match self.items {
             Some(ref mut items) => match items.pop_front() {
@@ -96,6 +146,32 @@ impl<T> Iterator<T> {
         }
     }
 
+    /// Writes the next entry to `result`. An entry that does not fit in `buf`
+    /// is kept, so glibc can retry it with a bigger buffer.
+    ///
+    /// # Safety
+    ///
+    /// `result`, `buf` (of `buflen` bytes) and `errnop` must be valid pointers supplied by glibc.
+    pub unsafe fn next_to_c<C>(
+        &mut self,
+        result: *mut C,
+        buf: *mut libc::c_char,
+        buflen: libc::size_t,
+        errnop: *mut libc::c_int,
+    ) -> NssStatus
+    where
+        T: ToC<C>,
+    {
+        let response = self.next();
+        let status = response.to_c(result, buf, buflen, errnop);
+        if status == NssStatus::TryAgain && *errnop == libc::ERANGE {
+            if let (Response::Success(entity), Some(items)) = (response, self.items.as_mut()) {
+                items.push_front(entity);
+            }
+        }
+        status
+    }
+
     pub fn close(&mut self) -> NssStatus {
         self.items = None;
         NssStatus::Success
@@ -107,6 +183,7 @@ pub struct CBuffer {
     pos: *mut libc::c_void,
     free: libc::size_t,
     len: libc::size_t,
+    dry_run: bool,
 }
 
 impl CBuffer {
@@ -116,19 +193,62 @@ impl CBuffer {
             pos: ptr,
             free: len,
             len,
+            dry_run: false,
+        }
+    }
+
+    /// A buffer that only counts what would be written to one starting at
+    /// `ptr`, see [`used`](Self::used). Nothing is ever written, and `ptr`
+    /// may be null or dangling.
+    pub fn dry_run(ptr: *mut libc::c_void) -> Self {
+        CBuffer {
+            start: ptr,
+            pos: ptr,
+            free: isize::MAX as libc::size_t,
+            len: 0,
+            dry_run: true,
         }
     }
 
+    /// Whether this is a [`dry_run`](Self::dry_run) buffer, in which the
+    /// memory `reserve` hands out must not be written.
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// The bytes written so far, including alignment padding.
+    pub fn used(&self) -> usize {
+        (self.pos as usize).wrapping_sub(self.start as usize)
+    }
+
+    /// # Safety
+    ///
+    /// The buffer must wrap `len` writable bytes.
     pub unsafe fn clear(&mut self) {
-        libc::memset(self.start, 0, self.len);
+        if !self.dry_run {
+            libc::memset(self.start, 0, self.len);
+        }
+    }
+
+    /// Moves past `len` bytes, which may lie outside any allocation in a dry run.
+    fn advance(&mut self, len: usize) {
+        self.pos = (self.pos as *mut u8).wrapping_add(len) as *mut libc::c_void;
+        self.free -= len;
     }
 
+    /// Copies `string` into the buffer, failing with `ERANGE` when it does not
+    /// fit and with `InvalidInput` when it contains a NUL byte.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must wrap `len` writable bytes.
     pub unsafe fn write_str(&mut self, string: &str) -> io::Result<*mut libc::c_char> {
         // Capture start address
         let str_start = self.pos;
 
-        // Convert string
-        let cstr = CString::new(string).expect("Failed to convert string");
+        // Convert string, a NUL inside it cannot be represented in C
+        let cstr =
+            CString::new(string).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
         let ptr = cstr.as_ptr();
         let len = libc::strlen(ptr);
 
@@ -138,35 +258,61 @@ impl CBuffer {
         }
 
         // Copy string
-        libc::memcpy(self.pos, ptr as *mut libc::c_void, len);
-        self.pos = self.pos.offset(len as isize + 1);
-        self.free -= len as usize + 1;
+        if !self.dry_run {
+            libc::memcpy(self.pos, ptr as *mut libc::c_void, len);
+        }
+        self.advance(len + 1);
 
         // Return start of string
         Ok(str_start as *mut libc::c_char)
     }
 
+    /// # Safety
+    ///
+    /// The buffer must wrap `len` writable bytes.
     pub unsafe fn write_strs<S: AsRef<str>>(
         &mut self,
         strings: &[S],
     ) -> io::Result<*mut *mut libc::c_char> {
         let ptr_size = std::mem::size_of::<*mut libc::c_char>() as isize;
 
+        self.align(std::mem::align_of::<*mut libc::c_char>())?;
         let vec_start =
             self.reserve(ptr_size * (strings.len() as isize + 1))? as *mut *mut libc::c_char;
         let mut pos = vec_start;
 
         // Write strings
         for s in strings {
-            *pos = self.write_str(s.as_ref())?;
-            pos = pos.offset(1);
+            let string = self.write_str(s.as_ref())?;
+            if !self.dry_run {
+                *pos = string;
+            }
+            pos = pos.wrapping_offset(1);
         }
 
-        libc::memset(pos as *mut libc::c_void, 0, ptr_size as usize);
+        if !self.dry_run {
+            libc::memset(pos as *mut libc::c_void, 0, ptr_size as usize);
+        }
 
         Ok(vec_start)
     }
 
+    /// Skips ahead so the next write starts at a multiple of `align`.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must wrap `len` writable bytes.
+    pub unsafe fn align(&mut self, align: usize) -> io::Result<()> {
+        let padding = (align - self.pos as usize % align) % align;
+        self.reserve(padding as isize)?;
+        Ok(())
+    }
+
+    /// Sets `len` bytes aside for the caller to fill, unless this is a dry run.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must wrap `len` writable bytes.
     pub unsafe fn reserve(&mut self, len: isize) -> io::Result<*mut libc::c_char> {
         let start = self.pos;
 
@@ -176,8 +322,7 @@ impl CBuffer {
         }
 
         // Reserve space
-        self.pos = self.pos.offset(len as isize);
-        self.free -= len as usize;
+        self.advance(len as usize);
 
         Ok(start as *mut libc::c_char)
     }
@@ -1,9 +1,20 @@
 extern crate lazy_static;
 extern crate libc;
 
+pub mod build;
+pub mod database;
+pub mod fork;
 pub mod group;
 pub mod host;
+pub mod init;
 pub mod initgroups;
 pub mod interop;
+#[cfg(feature = "loader")]
+pub mod loader;
 pub mod passwd;
 pub mod shadow;
+#[cfg(feature = "testing")]
+pub mod testing;
+
+/// Version of this crate, e.g. for modules that report what they are built on.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -0,0 +1,277 @@
+//! Loads a built NSS module with `dlopen` and calls it the way glibc does,
+//! without editing `/etc/nsswitch.conf` or needing root.
+//!
+//! ```ignore
+//! let module = Module::open("target/release/libnss_example.so", "example")?;
+//! assert!(module.missing(Database::Passwd).is_empty());
+//! match module.getpwnam("test") {
+//!     Response::Success(passwd) => println!("{}", passwd.dir),
+//!     _ => println!("no such user"),
+//! }
+//! ```
+
+use std::ffi::{CStr, CString};
+use std::io;
+use std::mem;
+use std::net::IpAddr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use libc::{c_int, c_void, gid_t, uid_t};
+
+pub use crate::database::Database;
+use crate::group::{CGroup, Group};
+use crate::host::{AddressFamily, CHost, Host};
+use crate::interop::{NssStatus, Response};
+use crate::passwd::{CPasswd, Passwd};
+use crate::shadow::{CShadow, Shadow};
+use crate::testing::{
+    self, Call, FromC, GetByIdFn, GetByNameFn, GetEntFn, GetHostByAddrFn, GetHostByName2Fn,
+    InitgroupsDynFn, SetEntFn, DEFAULT_BUFLEN,
+};
+
+/// Buffers are doubled on `ERANGE` up to this size, as glibc gives up somewhere too.
+pub const MAX_BUFLEN: usize = 16 * 1024 * 1024;
+
+/// A `libnss_<name>.so.2` opened with `dlopen`.
+///
+/// Functions the module does not export answer `Unavail`, which is how glibc
+/// treats them too.
+pub struct Module {
+    handle: *mut c_void,
+    name: String,
+}
+
+// dlsym is thread safe and NSS functions must be callable from any thread
+unsafe impl Send for Module {}
+unsafe impl Sync for Module {}
+
+impl Module {
+    /// Opens the module at `path`, which exports `_nss_<name>_*` functions.
+    pub fn open<P: AsRef<Path>>(path: P, name: &str) -> io::Result<Module> {
+        let path = CString::new(path.as_ref().as_os_str().as_bytes())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            return Err(io::Error::other(dlerror()));
+        }
+        Ok(Module {
+            handle,
+            name: name.to_string(),
+        })
+    }
+
+    /// Opens `libnss_<name>.so.2` from the library search path, like glibc.
+    pub fn open_installed(name: &str) -> io::Result<Module> {
+        Module::open(format!("libnss_{}.so.2", name), name)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the module exports `_nss_<name>_<function>`.
+    pub fn exports(&self, function: &str) -> bool {
+        self.symbol(function).is_some()
+    }
+
+    /// The functions of `database` the module does not export.
+    pub fn missing(&self, database: Database) -> Vec<&'static str> {
+        database
+            .functions()
+            .iter()
+            .copied()
+            .filter(|function| !self.exports(function))
+            .collect()
+    }
+
+    fn symbol(&self, function: &str) -> Option<*mut c_void> {
+        let symbol = CString::new(format!("_nss_{}_{}", self.name, function)).ok()?;
+        let ptr = unsafe { libc::dlsym(self.handle, symbol.as_ptr()) };
+        match ptr.is_null() {
+            true => None,
+            false => Some(ptr),
+        }
+    }
+
+    /// Looks up `_nss_<name>_<function>` as a function pointer of type `F`,
+    /// e.g. one of the aliases in [`testing`], to call it directly.
+    ///
+    /// # Safety
+    ///
+    /// `F` must be the function pointer type the symbol was defined with.
+    pub unsafe fn function<F: Copy>(&self, function: &str) -> Option<F> {
+        assert_eq!(mem::size_of::<F>(), mem::size_of::<*mut c_void>());
+        self.symbol(function)
+            .map(|ptr| mem::transmute_copy::<*mut c_void, F>(&ptr))
+    }
+
+    pub fn getpwnam(&self, name: &str) -> Response<Passwd> {
+        self.get_by_name::<CPasswd, Passwd>("getpwnam_r", name)
+    }
+
+    pub fn getpwuid(&self, uid: uid_t) -> Response<Passwd> {
+        self.get_by_id::<CPasswd, Passwd>("getpwuid_r", uid)
+    }
+
+    pub fn getpwent(&self) -> Response<Vec<Passwd>> {
+        self.enumerate::<CPasswd, Passwd>("setpwent", "getpwent_r", "endpwent")
+    }
+
+    pub fn getgrnam(&self, name: &str) -> Response<Group> {
+        self.get_by_name::<CGroup, Group>("getgrnam_r", name)
+    }
+
+    pub fn getgrgid(&self, gid: gid_t) -> Response<Group> {
+        self.get_by_id::<CGroup, Group>("getgrgid_r", gid)
+    }
+
+    pub fn getgrent(&self) -> Response<Vec<Group>> {
+        self.enumerate::<CGroup, Group>("setgrent", "getgrent_r", "endgrent")
+    }
+
+    pub fn getspnam(&self, name: &str) -> Response<Shadow> {
+        self.get_by_name::<CShadow, Shadow>("getspnam_r", name)
+    }
+
+    pub fn getspent(&self) -> Response<Vec<Shadow>> {
+        self.enumerate::<CShadow, Shadow>("setspent", "getspent_r", "endspent")
+    }
+
+    pub fn gethostbyname2(&self, name: &str, family: AddressFamily) -> Response<Host> {
+        let f: GetHostByName2Fn = match unsafe { self.function("gethostbyname2_r") } {
+            Some(f) => f,
+            None => return Response::Unavail,
+        };
+        if name.contains('\0') {
+            return Response::NotFound;
+        }
+        let family = match family {
+            AddressFamily::IPv4 => libc::AF_INET,
+            AddressFamily::IPv6 => libc::AF_INET6,
+            AddressFamily::Unspecified => libc::AF_UNSPEC,
+        };
+        grow(|buflen| testing::get_host_by_name(f, name, family, buflen))
+    }
+
+    pub fn gethostbyaddr(&self, addr: IpAddr) -> Response<Host> {
+        let f: GetHostByAddrFn = match unsafe { self.function("gethostbyaddr_r") } {
+            Some(f) => f,
+            None => return Response::Unavail,
+        };
+        grow(|buflen| testing::get_host_by_addr(f, addr, buflen))
+    }
+
+    pub fn gethostent(&self) -> Response<Vec<Host>> {
+        self.enumerate::<CHost, Host>("sethostent", "gethostent_r", "endhostent")
+    }
+
+    /// The groups of `name` the way `initgroups(3)` collects them: `group`
+    /// first, followed by every other group the module knows.
+    pub fn initgroups(&self, name: &str, group: gid_t) -> Response<Vec<gid_t>> {
+        let f: InitgroupsDynFn = match unsafe { self.function("initgroups_dyn") } {
+            Some(f) => f,
+            None => return Response::Unavail,
+        };
+        if name.contains('\0') {
+            return Response::NotFound;
+        }
+        testing::initgroups_dyn(f, name, group, &[group], 16, 0).into_response()
+    }
+
+    fn get_by_name<C, T: FromC<C>>(&self, function: &str, name: &str) -> Response<T> {
+        let f: GetByNameFn<C> = match unsafe { self.function(function) } {
+            Some(f) => f,
+            None => return Response::Unavail,
+        };
+        // Nothing can be named like that, and CString would refuse it
+        if name.contains('\0') {
+            return Response::NotFound;
+        }
+        grow(|buflen| testing::get_by_name(f, name, buflen))
+    }
+
+    fn get_by_id<C, T: FromC<C>>(&self, function: &str, id: uid_t) -> Response<T> {
+        let f: GetByIdFn<C> = match unsafe { self.function(function) } {
+            Some(f) => f,
+            None => return Response::Unavail,
+        };
+        grow(|buflen| testing::get_by_id(f, id, buflen))
+    }
+
+    fn enumerate<C, T: FromC<C>>(&self, set: &str, get: &str, end: &str) -> Response<Vec<T>> {
+        let (setent, getent, endent): (SetEntFn, GetEntFn<C>, SetEntFn) = unsafe {
+            match (self.function(set), self.function(get), self.function(end)) {
+                (Some(setent), Some(getent), Some(endent)) => (setent, getent, endent),
+                _ => return Response::Unavail,
+            }
+        };
+
+        match status(setent()) {
+            NssStatus::Success => {}
+            status => return status_response(status),
+        }
+        let mut entries = Vec::new();
+        let response = loop {
+            let call = grow(|buflen| {
+                testing::call::<C, T, _>(buflen, |result, buf, buflen, errnop| unsafe {
+                    getent(result, buf, buflen, errnop)
+                })
+            });
+            match call {
+                Response::Success(entry) => entries.push(entry),
+                Response::NotFound => break Response::Success(entries),
+                Response::TryAgain => break Response::TryAgain,
+                Response::Unavail => break Response::Unavail,
+                Response::Return => break Response::Return,
+            }
+        };
+        endent();
+        response
+    }
+}
+
+impl Drop for Module {
+    fn drop(&mut self) {
+        unsafe {
+            libc::dlclose(self.handle);
+        }
+    }
+}
+
+/// Repeats `call` with a doubled buffer for as long as it reports `ERANGE`.
+fn grow<T, F: FnMut(usize) -> Call<T>>(mut call: F) -> Response<T> {
+    let mut buflen = DEFAULT_BUFLEN;
+    loop {
+        let result = call(buflen);
+        let too_small = result.status == NssStatus::TryAgain && result.errno == libc::ERANGE;
+        if too_small && buflen < MAX_BUFLEN {
+            buflen *= 2;
+            continue;
+        }
+        return result.into_response();
+    }
+}
+
+fn status(code: c_int) -> NssStatus {
+    NssStatus::from_c(code).unwrap_or(NssStatus::Unavail)
+}
+
+fn status_response<T>(status: NssStatus) -> Response<T> {
+    match status {
+        NssStatus::TryAgain => Response::TryAgain,
+        NssStatus::NotFound => Response::NotFound,
+        NssStatus::Return => Response::Return,
+        _ => Response::Unavail,
+    }
+}
+
+fn dlerror() -> String {
+    let message = unsafe { libc::dlerror() };
+    match message.is_null() {
+        true => "dlopen failed".to_string(),
+        false => unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned(),
+    }
+}
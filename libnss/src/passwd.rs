@@ -1,8 +1,10 @@
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 use crate::interop::{CBuffer, Response, ToC};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Passwd {
     pub name: String,
     pub passwd: String,
@@ -57,7 +59,8 @@ macro_rules! libnss_passwd_hooks {
             use libc::c_int;
             use std::ffi::CStr;
             use std::str;
-            use std::sync::{Mutex, MutexGuard};
+            use std::sync::MutexGuard;
+            use $crate::fork::Mutex;
             use $crate::interop::{CBuffer, Iterator, Response};
             use $crate::passwd::{CPasswd, Passwd, PasswdHooks};
 
@@ -66,7 +69,7 @@ macro_rules! libnss_passwd_hooks {
             }
 
             #[no_mangle]
-            extern "C" fn [<_nss_ $mod_ident _setpwent>]() -> c_int {
+            pub extern "C" fn [<_nss_ $mod_ident _setpwent>]() -> c_int {
                 let mut iter: MutexGuard<Iterator<Passwd>> = [<PASSWD_ $mod_ident _ITERATOR>].lock().unwrap();
 
                 let status = match(super::$hooks_ident::get_all_entries()) {
@@ -78,24 +81,24 @@ macro_rules! libnss_passwd_hooks {
             }
 
             #[no_mangle]
-            extern "C" fn [<_nss_ $mod_ident _endpwent>]() -> c_int {
+            pub extern "C" fn [<_nss_ $mod_ident _endpwent>]() -> c_int {
                 let mut iter: MutexGuard<Iterator<Passwd>> = [<PASSWD_ $mod_ident _ITERATOR>].lock().unwrap();
                 iter.close() as c_int
             }
 
             #[no_mangle]
-            unsafe extern "C" fn [<_nss_ $mod_ident _getpwent_r>](
+            pub unsafe extern "C" fn [<_nss_ $mod_ident _getpwent_r>](
                 result: *mut CPasswd,
                 buf: *mut libc::c_char,
                 buflen: libc::size_t,
                 errnop: *mut c_int
             ) -> c_int {
                 let mut iter: MutexGuard<Iterator<Passwd>> = [<PASSWD_ $mod_ident _ITERATOR>].lock().unwrap();
-                iter.next().to_c(result, buf, buflen, errnop) as c_int
+                iter.next_to_c(result, buf, buflen, errnop) as c_int
             }
 
             #[no_mangle]
-            unsafe extern "C" fn [<_nss_ $mod_ident _getpwuid_r>](
+            pub unsafe extern "C" fn [<_nss_ $mod_ident _getpwuid_r>](
                 uid: libc::uid_t,
                 result: *mut CPasswd,
                 buf: *mut libc::c_char,
@@ -106,7 +109,7 @@ macro_rules! libnss_passwd_hooks {
             }
 
             #[no_mangle]
-            unsafe extern "C" fn [<_nss_ $mod_ident _getpwnam_r>](
+            pub unsafe extern "C" fn [<_nss_ $mod_ident _getpwnam_r>](
                 name_: *const libc::c_char,
                 result: *mut CPasswd,
                 buf: *mut libc::c_char,
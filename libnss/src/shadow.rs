@@ -1,29 +1,281 @@
-use serde::{Deserialize, Serialize};
 use crate::interop::{CBuffer, Response, ToC};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
 
-#[derive(Serialize, Deserialize)]
+/// A shadow entry. Days are counted like in `/etc/shadow`: dates as days
+/// since 1970-01-01, periods in days, and `None` where the file leaves a
+/// field empty (`-1` in a `struct spwd`).
+///
+/// In JSON, days are numbers with `-1` or `null` for unset fields; the two
+/// dates may also be given as `YYYY-MM-DD`. Missing fields, and numbers
+/// that are negative or too large for a `u32`, are unset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Shadow {
     pub name: String,
     pub passwd: String,
-    pub last_change: i64,
-    pub change_min_days: i64,
-    pub change_max_days: i64,
-    pub change_warn_days: i64,
-    pub change_inactive_days: i64,
-    pub expire_date: i64,
+    /// The date of the last password change, `Some(0)` forces a change at
+    /// the next login.
+    #[cfg_attr(feature = "serde", serde(default, with = "date"))]
+    pub last_change: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(default, with = "days"))]
+    pub change_min_days: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(default, with = "days"))]
+    pub change_max_days: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(default, with = "days"))]
+    pub change_warn_days: Option<u32>,
+    /// Days after the password expired during which it is still accepted.
+    #[cfg_attr(feature = "serde", serde(default, with = "days"))]
+    pub change_inactive_days: Option<u32>,
+    /// The date the account expires.
+    #[cfg_attr(feature = "serde", serde(default, with = "date"))]
+    pub expire_date: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub reserved: u64,
 }
 
+impl Shadow {
+    /// An entry with every other field unset, to be filled in with the
+    /// methods below.
+    pub fn new<N: Into<String>, P: Into<String>>(name: N, passwd: P) -> Self {
+        Shadow {
+            name: name.into(),
+            passwd: passwd.into(),
+            last_change: None,
+            change_min_days: None,
+            change_max_days: None,
+            change_warn_days: None,
+            change_inactive_days: None,
+            expire_date: None,
+            reserved: 0,
+        }
+    }
+
+    pub fn last_change(mut self, days: u32) -> Self {
+        self.last_change = Some(days);
+        self
+    }
+
+    pub fn change_min_days(mut self, days: u32) -> Self {
+        self.change_min_days = Some(days);
+        self
+    }
+
+    pub fn change_max_days(mut self, days: u32) -> Self {
+        self.change_max_days = Some(days);
+        self
+    }
+
+    pub fn change_warn_days(mut self, days: u32) -> Self {
+        self.change_warn_days = Some(days);
+        self
+    }
+
+    pub fn change_inactive_days(mut self, days: u32) -> Self {
+        self.change_inactive_days = Some(days);
+        self
+    }
+
+    pub fn expire_date(mut self, days: u32) -> Self {
+        self.expire_date = Some(days);
+        self
+    }
+
+    /// Sets the last change to the day `time` falls on.
+    pub fn changed_at(self, time: SystemTime) -> Self {
+        self.last_change(time_to_days(time))
+    }
+
+    /// Lets the account expire at the start of the day `time` falls on.
+    pub fn expires_at(self, time: SystemTime) -> Self {
+        self.expire_date(time_to_days(time))
+    }
+
+    /// The start of the day the password was last changed.
+    pub fn last_changed(&self) -> Option<SystemTime> {
+        self.last_change.map(days_to_time)
+    }
+
+    /// The start of the day the account expires.
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expire_date.map(days_to_time)
+    }
+}
+
+/// The day `time` falls on, in days since 1970-01-01. Earlier times count
+/// as day 0.
+pub fn time_to_days(time: SystemTime) -> u32 {
+    let days = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() / SECONDS_PER_DAY);
+    u32::try_from(days).unwrap_or(u32::MAX)
+}
+
+/// Midnight UTC at the start of `days` since 1970-01-01.
+pub fn days_to_time(days: u32) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(u64::from(days) * SECONDS_PER_DAY)
+}
+
+/// Parses a `YYYY-MM-DD` date on or after 1970-01-01 into days since then.
+pub fn date_to_days(date: &str) -> Option<u32> {
+    let mut parts = date.splitn(3, '-');
+    let mut field = |len: usize| {
+        parts
+            .next()
+            .filter(|part| part.len() == len && part.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|part| part.parse::<u32>().ok())
+    };
+    let (year, month, day) = (field(4)?, field(2)?, field(2)?);
+    if year < 1970 || month == 0 || month > 12 || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    // Days from 1970-01-01 in a calendar starting in March, so leap days
+    // come last in their year
+    let (year, month) = match month {
+        1 | 2 => (year - 1, month + 9),
+        _ => (year, month - 3),
+    };
+    let year_days = year * 365 + year / 4 - year / 100 + year / 400;
+    let days = year_days + (153 * month + 2) / 5 + day - 1;
+    days.checked_sub(719_468)
+}
+
+/// Formats `days` since 1970-01-01 as `YYYY-MM-DD`.
+pub fn days_to_date(days: u32) -> String {
+    let days = u64::from(days) + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let (year, month) = match month {
+        10 | 11 => (era * 400 + year_of_era + 1, month - 9),
+        _ => (era * 400 + year_of_era, month + 3),
+    };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// `u32::is_multiple_of` needs Rust 1.87
+#[allow(unknown_lints, clippy::manual_is_multiple_of)]
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// A day count as a `struct spwd` holds it.
+fn to_c_days(days: Option<u32>) -> i64 {
+    days.map_or(-1, i64::from)
+}
+
+/// A `struct spwd` day count, negative ones meaning unset.
+#[cfg(feature = "testing")]
+pub(crate) fn from_c_days(days: i64) -> Option<u32> {
+    u32::try_from(days).ok()
+}
+
+/// Serde for periods in days: numbers, with `-1`, any other number outside
+/// of `u32` or `null` for unset.
+#[cfg(feature = "serde")]
+mod days {
+    use serde::de::{self, Deserializer, Visitor};
+    use serde::Serializer;
+    use std::convert::TryFrom;
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(days: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(super::to_c_days(*days))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u32>, D::Error> {
+        deserializer.deserialize_any(Days { dates: false })
+    }
+
+    pub(super) struct Days {
+        pub(super) dates: bool,
+    }
+
+    impl<'de> Visitor<'de> for Days {
+        type Value = Option<u32>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self.dates {
+                true => f.write_str("a number of days, -1, null or a YYYY-MM-DD date"),
+                false => f.write_str("a number of days, -1 or null"),
+            }
+        }
+
+        // Like `from_c_days`, numbers a `struct spwd` cannot hold are unset
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+            Ok(u32::try_from(value).ok())
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+            Ok(u32::try_from(value).ok())
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+            match super::date_to_days(value) {
+                Some(days) if self.dates => Ok(Some(days)),
+                _ => Err(E::invalid_value(de::Unexpected::Str(value), &self)),
+            }
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(
+            self,
+            deserializer: D,
+        ) -> Result<Self::Value, D::Error> {
+            deserializer.deserialize_any(self)
+        }
+    }
+}
+
+/// Serde for dates: like [`days`], or a `YYYY-MM-DD` string.
+#[cfg(feature = "serde")]
+mod date {
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(days: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error> {
+        super::days::serialize(days, serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u32>, D::Error> {
+        deserializer.deserialize_any(super::days::Days { dates: true })
+    }
+}
+
 impl ToC<CShadow> for Shadow {
     unsafe fn to_c(&self, result: *mut CShadow, buffer: &mut CBuffer) -> std::io::Result<()> {
         (*result).name = buffer.write_str(&self.name)?;
         (*result).passwd = buffer.write_str(&self.passwd)?;
-        (*result).last_change = self.last_change;
-        (*result).change_min_days = self.change_min_days;
-        (*result).change_max_days = self.change_max_days;
-        (*result).change_warn_days = self.change_warn_days;
-        (*result).change_inactive_days = self.change_inactive_days;
-        (*result).expire_date = self.expire_date;
+        (*result).last_change = to_c_days(self.last_change);
+        (*result).change_min_days = to_c_days(self.change_min_days);
+        (*result).change_max_days = to_c_days(self.change_max_days);
+        (*result).change_warn_days = to_c_days(self.change_warn_days);
+        (*result).change_inactive_days = to_c_days(self.change_inactive_days);
+        (*result).expire_date = to_c_days(self.expire_date);
         (*result).reserved = self.reserved;
         Ok(())
     }
@@ -60,7 +312,8 @@ macro_rules! libnss_shadow_hooks {
             use libc::c_int;
             use std::ffi::CStr;
             use std::str;
-            use std::sync::{Mutex, MutexGuard};
+            use std::sync::MutexGuard;
+            use $crate::fork::Mutex;
             use $crate::interop::{CBuffer, Iterator, Response};
             use $crate::shadow::{CShadow, ShadowHooks, Shadow};
 
@@ -69,7 +322,7 @@ macro_rules! libnss_shadow_hooks {
             }
 
             #[no_mangle]
-            extern "C" fn [<_nss_ $mod_ident _setspent>]() -> c_int {
+            pub extern "C" fn [<_nss_ $mod_ident _setspent>]() -> c_int {
                 let mut iter: MutexGuard<Iterator<Shadow>> = [<SHADOW_ $mod_ident _ITERATOR>].lock().unwrap();
                 let status = match(super::$hooks_ident::get_all_entries()) {
                     Response::Success(entries) => iter.open(entries),
@@ -79,24 +332,24 @@ macro_rules! libnss_shadow_hooks {
             }
 
             #[no_mangle]
-            extern "C" fn [<_nss_ $mod_ident _endspent>]() -> c_int {
+            pub extern "C" fn [<_nss_ $mod_ident _endspent>]() -> c_int {
                 let mut iter: MutexGuard<Iterator<Shadow>> = [<SHADOW_ $mod_ident _ITERATOR>].lock().unwrap();
                 iter.close() as c_int
             }
 
             #[no_mangle]
-            unsafe extern "C" fn [<_nss_ $mod_ident _getspent_r>](
+            pub unsafe extern "C" fn [<_nss_ $mod_ident _getspent_r>](
                 result: *mut CShadow,
                 buf: *mut libc::c_char,
                 buflen: libc::size_t,
                 errnop: *mut c_int
             ) -> c_int {
                 let mut iter: MutexGuard<Iterator<Shadow>> = [<SHADOW_ $mod_ident _ITERATOR>].lock().unwrap();
-                iter.next().to_c(result, buf, buflen, errnop) as c_int
+                iter.next_to_c(result, buf, buflen, errnop) as c_int
             }
 
             #[no_mangle]
-            unsafe extern "C" fn [<_nss_ $mod_ident _getspnam_r>](
+            pub unsafe extern "C" fn [<_nss_ $mod_ident _getspnam_r>](
                 name_: *const libc::c_char,
                 result: *mut CShadow,
                 buf: *mut libc::c_char,
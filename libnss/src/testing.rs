@@ -0,0 +1,490 @@
+//! Calls the `_nss_<module>_*` functions generated by the hook macros the way
+//! glibc does, so modules can test their marshalling from plain Rust tests
+//! without installing anything.
+//!
+//! The hook macros export the generated functions from the crate they are
+//! invoked in, so a test can pass e.g. `_nss_example_getpwnam_r` to
+//! [`get_by_name`] directly.
+//!
+//! ```ignore
+//! let call: Call<Passwd> = testing::get_by_name(_nss_example_getpwnam_r, "test", 1024);
+//! call.assert_status(NssStatus::Success).assert_errno(0);
+//! assert_eq!(call.unwrap().uid, 1005);
+//!
+//! // A buffer that is too small asks glibc to retry with a bigger one
+//! let call: Call<Passwd> = testing::get_by_name(_nss_example_getpwnam_r, "test", 8);
+//! call.assert_status(NssStatus::TryAgain).assert_errno(libc::ERANGE);
+//! ```
+
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use libc::{c_char, c_int, c_long, gid_t, size_t};
+
+use crate::group::{CGroup, Group};
+use crate::host::{Addresses, CHost, Host};
+use crate::interop::{NssStatus, Response};
+use crate::passwd::{CPasswd, Passwd};
+use crate::shadow::{from_c_days, CShadow, Shadow};
+
+/// The buffer size glibc starts most lookups with.
+pub const DEFAULT_BUFLEN: usize = 1024;
+
+pub type SetEntFn = extern "C" fn() -> c_int;
+pub type GetEntFn<C> = unsafe extern "C" fn(*mut C, *mut c_char, size_t, *mut c_int) -> c_int;
+pub type GetByNameFn<C> =
+    unsafe extern "C" fn(*const c_char, *mut C, *mut c_char, size_t, *mut c_int) -> c_int;
+pub type GetByIdFn<C> =
+    unsafe extern "C" fn(libc::uid_t, *mut C, *mut c_char, size_t, *mut c_int) -> c_int;
+pub type GetHostByName2Fn = unsafe extern "C" fn(
+    *const c_char,
+    c_int,
+    *mut CHost,
+    *mut c_char,
+    size_t,
+    *mut c_int,
+    *mut c_int,
+) -> c_int;
+pub type GetHostByAddrFn = unsafe extern "C" fn(
+    *const c_char,
+    size_t,
+    c_int,
+    *mut CHost,
+    *mut c_char,
+    size_t,
+    *mut c_int,
+    *mut c_int,
+) -> c_int;
+pub type InitgroupsDynFn = unsafe extern "C" fn(
+    *const c_char,
+    gid_t,
+    *mut c_long,
+    *mut c_long,
+    *mut *mut gid_t,
+    c_long,
+    *mut c_int,
+) -> c_int;
+
+/// Decodes a C entry filled in by a module back into its Rust form.
+pub trait FromC<C>: Sized {
+    /// # Safety
+    ///
+    /// Every pointer in `c` must be null or point to valid, terminated data.
+    unsafe fn from_c(c: &C) -> Self;
+}
+
+impl FromC<CPasswd> for Passwd {
+    unsafe fn from_c(c: &CPasswd) -> Self {
+        Passwd {
+            name: string(c.name),
+            passwd: string(c.passwd),
+            uid: c.uid,
+            gid: c.gid,
+            gecos: string(c.gecos),
+            dir: string(c.dir),
+            shell: string(c.shell),
+        }
+    }
+}
+
+impl FromC<CGroup> for Group {
+    unsafe fn from_c(c: &CGroup) -> Self {
+        Group {
+            name: string(c.name),
+            passwd: string(c.passwd),
+            gid: c.gid,
+            members: strings(c.members),
+        }
+    }
+}
+
+impl FromC<CShadow> for Shadow {
+    unsafe fn from_c(c: &CShadow) -> Self {
+        Shadow {
+            name: string(c.name),
+            passwd: string(c.passwd),
+            last_change: from_c_days(c.last_change),
+            change_min_days: from_c_days(c.change_min_days),
+            change_max_days: from_c_days(c.change_max_days),
+            change_warn_days: from_c_days(c.change_warn_days),
+            change_inactive_days: from_c_days(c.change_inactive_days),
+            expire_date: from_c_days(c.expire_date),
+            reserved: c.reserved,
+        }
+    }
+}
+
+impl FromC<CHost> for Host {
+    /// The TTL is not part of a `hostent` and always decodes as `None`.
+    unsafe fn from_c(c: &CHost) -> Self {
+        let mut raw = Vec::new();
+        let mut pos = c.h_addr_list;
+        while !pos.is_null() && !(*pos).is_null() {
+            raw.push(*pos as *const u8);
+            pos = pos.offset(1);
+        }
+
+        let addresses = match c.h_addrtype {
+            libc::AF_INET6 => Addresses::V6(
+                raw.iter()
+                    .map(|addr| {
+                        let mut octets = [0u8; 16];
+                        octets.copy_from_slice(std::slice::from_raw_parts(*addr, 16));
+                        Ipv6Addr::from(octets)
+                    })
+                    .collect(),
+            ),
+            _ => Addresses::V4(
+                raw.iter()
+                    .map(|addr| {
+                        let mut octets = [0u8; 4];
+                        octets.copy_from_slice(std::slice::from_raw_parts(*addr, 4));
+                        Ipv4Addr::from(octets)
+                    })
+                    .collect(),
+            ),
+        };
+
+        Host {
+            name: string(c.name),
+            aliases: strings(c.h_aliases),
+            addresses,
+            ttl: None,
+        }
+    }
+}
+
+unsafe fn string(ptr: *const c_char) -> String {
+    match ptr.is_null() {
+        true => String::new(),
+        false => CStr::from_ptr(ptr).to_string_lossy().into_owned(),
+    }
+}
+
+unsafe fn strings(mut ptr: *const *mut c_char) -> Vec<String> {
+    let mut strings = Vec::new();
+    while !ptr.is_null() && !(*ptr).is_null() {
+        strings.push(string(*ptr));
+        ptr = ptr.offset(1);
+    }
+    strings
+}
+
+/// Caller owned scratch memory, the `buf`/`buflen` pair glibc passes along.
+pub struct Buffer {
+    data: Vec<u8>,
+}
+
+impl Buffer {
+    /// A buffer of `len` bytes, filled with garbage so that data a module
+    /// forgets to terminate shows up in tests.
+    pub fn new(len: usize) -> Self {
+        Buffer {
+            data: vec![0xa5; len],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut c_char {
+        self.data.as_mut_ptr() as *mut c_char
+    }
+
+    /// Whether `ptr` points into the buffer, as every pointer in a result must.
+    pub fn contains<T>(&self, ptr: *const T) -> bool {
+        let range = self.data.as_ptr_range();
+        range.contains(&(ptr as *const u8))
+    }
+}
+
+/// What a call into a module returned.
+pub struct Call<T> {
+    pub status: NssStatus,
+    pub errno: c_int,
+    /// Only reported by host lookups.
+    pub h_errno: Option<c_int>,
+    /// The decoded result, present when the call succeeded.
+    pub entry: Option<T>,
+}
+
+impl<T> Call<T> {
+    #[track_caller]
+    pub fn assert_status(&self, status: NssStatus) -> &Self {
+        assert_eq!(self.status, status, "unexpected status");
+        self
+    }
+
+    #[track_caller]
+    pub fn assert_errno(&self, errno: c_int) -> &Self {
+        assert_eq!(self.errno, errno, "unexpected errno");
+        self
+    }
+
+    #[track_caller]
+    pub fn assert_h_errno(&self, h_errno: c_int) -> &Self {
+        assert_eq!(self.h_errno, Some(h_errno), "unexpected h_errno");
+        self
+    }
+
+    /// The decoded entry, panicking unless the call succeeded.
+    #[track_caller]
+    pub fn unwrap(self) -> T {
+        match self.entry {
+            Some(entry) => entry,
+            None => panic!(
+                "call returned {:?} with errno {}, not an entry",
+                self.status, self.errno
+            ),
+        }
+    }
+
+    /// The call as the `Response` a hook would have returned.
+    pub fn into_response(self) -> Response<T> {
+        match (self.status, self.entry) {
+            (NssStatus::Success, Some(entry)) => Response::Success(entry),
+            (NssStatus::TryAgain, _) => Response::TryAgain,
+            (NssStatus::NotFound, _) => Response::NotFound,
+            (NssStatus::Return, _) => Response::Return,
+            _ => Response::Unavail,
+        }
+    }
+
+    fn without_h_errno(mut self) -> Self {
+        self.h_errno = None;
+        self
+    }
+}
+
+/// Calls a reentrant lookup with a fresh `buflen` byte buffer and decodes the
+/// result if it succeeded.
+///
+/// `f` receives the `result`, `buf`, `buflen` and `errnop` arguments.
+#[track_caller]
+pub fn call<C, T, F>(buflen: usize, f: F) -> Call<T>
+where
+    T: FromC<C>,
+    F: FnOnce(*mut C, *mut c_char, size_t, *mut c_int) -> c_int,
+{
+    call_with_h_errno(buflen, |result, buf, buflen, errnop, _| {
+        f(result, buf, buflen, errnop)
+    })
+    .without_h_errno()
+}
+
+/// Like [`call`], for host lookups that also report `h_errno`.
+#[track_caller]
+pub fn call_with_h_errno<C, T, F>(buflen: usize, f: F) -> Call<T>
+where
+    T: FromC<C>,
+    F: FnOnce(*mut C, *mut c_char, size_t, *mut c_int, *mut c_int) -> c_int,
+{
+    let mut result: C = unsafe { mem::zeroed() };
+    let mut buffer = Buffer::new(buflen);
+    let mut errno = 0;
+    let mut h_errno = 0;
+
+    let code = f(
+        &mut result,
+        buffer.as_mut_ptr(),
+        buffer.len(),
+        &mut errno,
+        &mut h_errno,
+    );
+
+    let status = status(code);
+    let entry = match status {
+        NssStatus::Success => Some(unsafe { T::from_c(&result) }),
+        _ => None,
+    };
+    Call {
+        status,
+        errno,
+        h_errno: Some(h_errno),
+        entry,
+    }
+}
+
+#[track_caller]
+fn status(code: c_int) -> NssStatus {
+    match NssStatus::from_c(code) {
+        Some(status) => status,
+        None => panic!("{} is not an NSS status", code),
+    }
+}
+
+/// Calls a `get*nam_r` style function.
+#[track_caller]
+pub fn get_by_name<C, T: FromC<C>>(f: GetByNameFn<C>, name: &str, buflen: usize) -> Call<T> {
+    let name = CString::new(name).expect("name contains a NUL byte");
+    call(buflen, |result, buf, buflen, errnop| unsafe {
+        f(name.as_ptr(), result, buf, buflen, errnop)
+    })
+}
+
+/// Calls a `getpwuid_r` or `getgrgid_r` style function.
+#[track_caller]
+pub fn get_by_id<C, T: FromC<C>>(f: GetByIdFn<C>, id: libc::uid_t, buflen: usize) -> Call<T> {
+    call(buflen, |result, buf, buflen, errnop| unsafe {
+        f(id, result, buf, buflen, errnop)
+    })
+}
+
+/// Calls `gethostbyname2_r` style functions for `family`.
+#[track_caller]
+pub fn get_host_by_name(
+    f: GetHostByName2Fn,
+    name: &str,
+    family: c_int,
+    buflen: usize,
+) -> Call<Host> {
+    let name = CString::new(name).expect("name contains a NUL byte");
+    call_with_h_errno(buflen, |result, buf, buflen, errnop, h_errnop| unsafe {
+        f(name.as_ptr(), family, result, buf, buflen, errnop, h_errnop)
+    })
+}
+
+/// Calls a `gethostbyaddr_r` style function.
+#[track_caller]
+pub fn get_host_by_addr(f: GetHostByAddrFn, addr: IpAddr, buflen: usize) -> Call<Host> {
+    let (octets, family) = match addr {
+        IpAddr::V4(addr) => (addr.octets().to_vec(), libc::AF_INET),
+        IpAddr::V6(addr) => (addr.octets().to_vec(), libc::AF_INET6),
+    };
+    call_with_h_errno(buflen, |result, buf, buflen, errnop, h_errnop| unsafe {
+        f(
+            octets.as_ptr() as *const c_char,
+            octets.len(),
+            family,
+            result,
+            buf,
+            buflen,
+            errnop,
+            h_errnop,
+        )
+    })
+}
+
+/// A whole `set*ent`, `get*ent_r`, `end*ent` sequence.
+pub struct Enumeration<T> {
+    pub setent: NssStatus,
+    /// Every `get*ent_r` call, up to and including the first one that did
+    /// not succeed.
+    pub calls: Vec<Call<T>>,
+    pub endent: NssStatus,
+}
+
+impl<T> Enumeration<T> {
+    /// The decoded entries, panicking unless the enumeration opened, ran
+    /// until `NotFound` and closed cleanly.
+    #[track_caller]
+    pub fn unwrap(self) -> Vec<T> {
+        assert_eq!(self.setent, NssStatus::Success, "unexpected setent status");
+        assert_eq!(self.endent, NssStatus::Success, "unexpected endent status");
+        let mut entries = Vec::with_capacity(self.calls.len());
+        for call in self.calls {
+            match call.status {
+                NssStatus::Success => entries.extend(call.entry),
+                NssStatus::NotFound => break,
+                status => panic!(
+                    "enumeration stopped with {:?} and errno {}",
+                    status, call.errno
+                ),
+            }
+        }
+        entries
+    }
+}
+
+/// Enumerates a database with a `buflen` byte buffer for every entry.
+#[track_caller]
+pub fn enumerate<C, T: FromC<C>>(
+    setent: SetEntFn,
+    getent: GetEntFn<C>,
+    endent: SetEntFn,
+    buflen: usize,
+) -> Enumeration<T> {
+    let setent = status(setent());
+    let mut calls = Vec::new();
+    if setent == NssStatus::Success {
+        loop {
+            let call = call(buflen, |result, buf, buflen, errnop| unsafe {
+                getent(result, buf, buflen, errnop)
+            });
+            let done = call.status != NssStatus::Success;
+            calls.push(call);
+            if done {
+                break;
+            }
+        }
+    }
+    let endent = status(endent());
+    Enumeration {
+        setent,
+        calls,
+        endent,
+    }
+}
+
+/// Calls an `initgroups_dyn` style function the way glibc's `initgroups`
+/// does: `groups` are already known, `size` slots are allocated and `limit`
+/// caps the total (zero or less means no limit).
+///
+/// The entry holds every group, including `groups`, when the call succeeded.
+#[track_caller]
+pub fn initgroups_dyn(
+    f: InitgroupsDynFn,
+    name: &str,
+    skipgroup: gid_t,
+    groups: &[gid_t],
+    size: usize,
+    limit: c_long,
+) -> Call<Vec<gid_t>> {
+    let name = CString::new(name).expect("name contains a NUL byte");
+    let size = size.max(groups.len()).max(1);
+    let mut start = groups.len() as c_long;
+    let mut allocated = size as c_long;
+    let mut errno = 0;
+
+    unsafe {
+        // The module may realloc the array, so it has to come from malloc
+        let mut array = libc::malloc(size * mem::size_of::<gid_t>()) as *mut gid_t;
+        assert!(!array.is_null(), "failed to allocate the group array");
+        std::ptr::copy_nonoverlapping(groups.as_ptr(), array, groups.len());
+
+        let code = f(
+            name.as_ptr(),
+            skipgroup,
+            &mut start,
+            &mut allocated,
+            &mut array,
+            limit,
+            &mut errno,
+        );
+        assert!(
+            start <= allocated,
+            "start {} is past the allocated {} groups",
+            start,
+            allocated
+        );
+        let all = std::slice::from_raw_parts(array, start as usize).to_vec();
+        libc::free(array as *mut libc::c_void);
+
+        let status = status(code);
+        Call {
+            status,
+            errno,
+            h_errno: None,
+            entry: match status {
+                NssStatus::Success => Some(all),
+                _ => None,
+            },
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::fs;
+use std::path::PathBuf;
+
+use libnss::build::Exports;
+use libnss::database::Database;
+
+/// A fresh source directory holding `lib.rs`.
+fn sources(name: &str, lib: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("libnss-exports-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("lib.rs"), lib).unwrap();
+    dir
+}
+
+#[test]
+fn scanning_skips_comments_and_strings() {
+    let dir = sources(
+        "comments",
+        r##"
+libnss_passwd_hooks!(test, TestPasswd);
+// libnss_group_hooks!(test, TestGroup);
+/* libnss_shadow_hooks!(test, TestShadow);
+   /* nested */ libnss_host_hooks!(test, TestHosts); */
+const USAGE: &str = "libnss_initgroups_hooks!(test, TestInitgroups)";
+const RAW: &str = r#"libnss_init_hooks!(other, OtherInit)"#;
+fn lifetime<'a>(name: &'a str) -> &'a str { name }
+macro_rules! wrap {
+    ($name:ident) => { libnss_host_hooks!($name, Hosts); };
+}
+libnss_group_hooks!(test, TestGroup);
+"##,
+    );
+    let exports = Exports::scan(&dir).unwrap();
+    assert_eq!(exports.name(), "test");
+    assert_eq!(exports.databases(), &[Database::Passwd, Database::Group]);
+    assert!(!exports.symbols().contains(&"_nss_test_init".to_string()));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn cfg_gated_hooks_are_refused() {
+    let dir = sources(
+        "cfg",
+        "libnss_passwd_hooks!(test, TestPasswd);\n\
+         #[cfg(feature = \"shadow\")]\n\
+         libnss_shadow_hooks!(test, TestShadow);\n",
+    );
+    let err = Exports::scan(&dir).unwrap_err();
+    assert!(err.to_string().contains("Exports::new"), "{}", err);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,156 @@
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate libnss;
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libnss::fork;
+use libnss::interop::{NssStatus, Response};
+use libnss::passwd::{Passwd, PasswdHooks};
+use libnss::testing::{self, DEFAULT_BUFLEN};
+
+struct ForkedPasswd;
+libnss_passwd_hooks!(forked, ForkedPasswd);
+
+impl PasswdHooks for ForkedPasswd {
+    fn get_all_entries() -> Response<Vec<Passwd>> {
+        // Keep the iterator locked long enough for forks to land inside
+        thread::sleep(Duration::from_millis(1));
+        Response::Success(vec![passwd("test", 1005), passwd("other", 1006)])
+    }
+
+    fn get_entry_by_uid(_uid: libc::uid_t) -> Response<Passwd> {
+        Response::NotFound
+    }
+
+    fn get_entry_by_name(_name: String) -> Response<Passwd> {
+        Response::NotFound
+    }
+}
+
+fn passwd(name: &str, uid: libc::uid_t) -> Passwd {
+    Passwd {
+        name: name.to_string(),
+        passwd: "x".to_string(),
+        uid,
+        gid: uid,
+        gecos: String::new(),
+        dir: format!("/home/{}", name),
+        shell: "/bin/sh".to_string(),
+    }
+}
+
+/// Runs `f` in a child process and returns its exit code, or `None` when it
+/// did not finish within a few seconds.
+fn in_child<F: FnOnce()>(f: F) -> Option<i32> {
+    let pid = unsafe { libc::fork() };
+    assert!(pid >= 0, "fork failed");
+    if pid == 0 {
+        let code = match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(()) => 0,
+            Err(_) => 1,
+        };
+        unsafe { libc::_exit(code) };
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut status = 0;
+    loop {
+        match unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) } {
+            0 if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            0 => {
+                unsafe {
+                    libc::kill(pid, libc::SIGKILL);
+                    libc::waitpid(pid, &mut status, 0);
+                }
+                return None;
+            }
+            _ => return Some(libc::WEXITSTATUS(status)),
+        }
+    }
+}
+
+#[test]
+fn locks_held_at_fork_are_reset_in_the_child() {
+    static HOOK_RAN: AtomicBool = AtomicBool::new(false);
+    fork::on_child(|| HOOK_RAN.store(true, Ordering::SeqCst));
+
+    let counter = Arc::new(fork::Mutex::new(0u32));
+    let (locked, is_locked) = mpsc::channel();
+    let (release, released) = mpsc::channel::<()>();
+    let holder = {
+        let counter = counter.clone();
+        thread::spawn(move || {
+            let mut count = counter.lock().unwrap();
+            *count = 5;
+            locked.send(()).unwrap();
+            released.recv().unwrap();
+        })
+    };
+    is_locked.recv().unwrap();
+
+    let generation = fork::generation();
+    let code = in_child(|| {
+        assert_eq!(*counter.lock().unwrap(), 0);
+        assert!(HOOK_RAN.load(Ordering::SeqCst));
+        assert_eq!(fork::generation(), generation + 1);
+    });
+
+    release.send(()).unwrap();
+    holder.join().unwrap();
+    assert_eq!(code, Some(0), "child failed or deadlocked");
+    assert_eq!(*counter.lock().unwrap(), 5);
+    assert_eq!(fork::generation(), generation);
+}
+
+#[test]
+fn enumerations_work_after_forking_busy_threads() {
+    let stop = Arc::new(AtomicBool::new(false));
+    let threads = (0..4)
+        .map(|_| {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    _nss_forked_setpwent();
+                    _nss_forked_endpwent();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let codes = (0..20)
+        .map(|_| {
+            thread::sleep(Duration::from_millis(2));
+            in_child(|| {
+                // Whatever the parent's threads had open is gone
+                let call = testing::call::<_, Passwd, _>(
+                    DEFAULT_BUFLEN,
+                    |result, buf, buflen, errnop| unsafe {
+                        _nss_forked_getpwent_r(result, buf, buflen, errnop)
+                    },
+                );
+                assert_eq!(call.status, NssStatus::Unavail);
+
+                let entries = testing::enumerate::<_, Passwd>(
+                    _nss_forked_setpwent,
+                    _nss_forked_getpwent_r,
+                    _nss_forked_endpwent,
+                    DEFAULT_BUFLEN,
+                )
+                .unwrap();
+                assert_eq!(entries.len(), 2);
+            })
+        })
+        .collect::<Vec<_>>();
+
+    stop.store(true, Ordering::Relaxed);
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert!(codes.iter().all(|code| *code == Some(0)), "{:?}", codes);
+}
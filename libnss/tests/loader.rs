@@ -0,0 +1,124 @@
+use std::path::PathBuf;
+
+use libnss::build::Exports;
+use libnss::database::Database;
+use libnss::host::AddressFamily;
+use libnss::interop::Response;
+use libnss::loader::Module;
+
+/// The example module, which cargo builds next to the test binaries.
+fn example_path() -> PathBuf {
+    let mut path = std::env::current_exe().unwrap();
+    path.pop();
+    if path.ends_with("deps") {
+        path.pop();
+    }
+    path.join("examples").join("libnss_example.so")
+}
+
+fn example() -> Module {
+    let path = example_path();
+    Module::open(&path, "example").unwrap_or_else(|err| panic!("{}: {}", path.display(), err))
+}
+
+#[test]
+fn lookups_are_typed() {
+    let module = example();
+    match module.getpwnam("test") {
+        Response::Success(passwd) => {
+            assert_eq!(passwd.uid, 1005);
+            assert_eq!(passwd.dir, "/home/test");
+        }
+        _ => panic!("test was not found"),
+    }
+    match module.getpwuid(1005) {
+        Response::Success(passwd) => assert_eq!(passwd.name, "test"),
+        _ => panic!("uid 1005 was not found"),
+    }
+    assert!(matches!(module.getpwnam("nobody"), Response::NotFound));
+    assert!(matches!(module.getpwnam("te\0st"), Response::NotFound));
+}
+
+#[test]
+fn buffers_grow_on_erange() {
+    let module = example();
+    match module.getgrnam("everyone") {
+        Response::Success(group) => assert_eq!(group.members.len(), 500),
+        _ => panic!("everyone was not found"),
+    }
+    // An entry that does not fit is retried rather than skipped
+    match module.getgrent() {
+        Response::Success(groups) => {
+            let names = groups.iter().map(|g| g.name.as_str()).collect::<Vec<_>>();
+            assert_eq!(names, vec!["test", "everyone"]);
+        }
+        _ => panic!("enumeration failed"),
+    }
+}
+
+#[test]
+fn missing_functions_are_unavailable() {
+    let module = example();
+    assert!(module.missing(Database::Passwd).is_empty());
+    assert!(module.missing(Database::Initgroups).is_empty());
+    assert_eq!(
+        module.missing(Database::Shadow),
+        Database::Shadow.functions()
+    );
+    assert!(!module.exports("getspnam_r"));
+    assert!(matches!(module.getspnam("test"), Response::Unavail));
+    assert!(matches!(
+        module.gethostbyname2("localhost", AddressFamily::IPv4),
+        Response::Unavail
+    ));
+}
+
+#[test]
+fn initgroups_starts_with_the_primary_group() {
+    let module = example();
+    match module.initgroups("test", 1005) {
+        Response::Success(groups) => assert_eq!(groups, vec![1005, 1100]),
+        _ => panic!("initgroups failed"),
+    }
+}
+
+#[test]
+fn missing_modules_are_reported() {
+    let err = match Module::open("/nonexistent/libnss_nothing.so.2", "nothing") {
+        Ok(_) => panic!("opened a module that does not exist"),
+        Err(err) => err,
+    };
+    assert!(err.to_string().contains("libnss_nothing"));
+}
+
+#[test]
+fn exports_are_checked() {
+    let exports = Exports::scan(concat!(env!("CARGO_MANIFEST_DIR"), "/examples")).unwrap();
+    assert_eq!(exports.name(), "example");
+    assert!(exports.check(example_path()).unwrap().is_ok());
+    assert!(exports.symbols().contains(&"_nss_example_init".to_string()));
+    assert!(exports
+        .render()
+        .contains("  global:\n    _nss_example_setpwent;\n"));
+
+    let check = Exports::new("example")
+        .database(Database::Passwd)
+        .database(Database::Shadow)
+        .check(example_path())
+        .unwrap();
+    assert_eq!(
+        check.missing,
+        vec![
+            "_nss_example_setspent",
+            "_nss_example_endspent",
+            "_nss_example_getspent_r",
+            "_nss_example_getspnam_r"
+        ]
+    );
+    assert!(check
+        .unexpected
+        .contains(&"_nss_example_getgrnam_r".to_string()));
+    assert!(check
+        .unexpected
+        .contains(&"_nss_example_initgroups_dyn".to_string()));
+}
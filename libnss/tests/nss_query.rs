@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use serde_json::Value;
+
+/// Runs nss-query against the example module, which cargo builds next to
+/// the test binaries.
+fn nss_query(args: &[&str]) -> Output {
+    let mut module = std::env::current_exe().unwrap();
+    module.pop();
+    if module.ends_with("deps") {
+        module.pop();
+    }
+    let module: PathBuf = module.join("examples").join("libnss_example.so");
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_nss-query"));
+    let (options, rest) = match args.iter().position(|arg| !arg.starts_with("--")) {
+        Some(at) => args.split_at(at),
+        None => (args, &[][..]),
+    };
+    command.args(options).arg(&module).args(rest);
+    command.output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn lookups_print_entries_and_statuses() {
+    let output = nss_query(&["passwd", "test", "1005"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "test:x:1005:1005:Test Account:/home/test:/bin/bash\n".repeat(2)
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("# test status=NSS_STATUS_SUCCESS (1) errno=0 buflen=1024"));
+
+    let output = nss_query(&["passwd", "nobody"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stdout(&output), "");
+}
+
+#[test]
+fn json_reports_buffer_growth() {
+    let output = nss_query(&["--json", "group", "everyone"]);
+    assert_eq!(output.status.code(), Some(0));
+    let lookup: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(lookup["status"], "NSS_STATUS_SUCCESS");
+    assert_eq!(lookup["errno"], 0);
+    assert!(lookup["retries"].as_u64().unwrap() > 0);
+    assert_eq!(lookup["entry"]["members"].as_array().unwrap().len(), 500);
+    assert!(lookup["needs"].as_u64().unwrap() > 1024);
+
+    let output = nss_query(&["--json", "initgroups", "test"]);
+    let lookup: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(lookup["entry"]["groups"], serde_json::json!([1005, 1100]));
+}
+
+#[test]
+fn enumerations_are_checked() {
+    let output = nss_query(&["--json", "group"]);
+    assert_eq!(output.status.code(), Some(0));
+    let enumeration: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(enumeration["problems"], serde_json::json!([]));
+    assert_eq!(enumeration["enumeration"]["entries"], 2);
+    assert_eq!(enumeration["erange"]["entries"], 2);
+    assert!(enumeration["erange"]["retries"].as_u64().unwrap() > 0);
+    assert_eq!(enumeration["oversized"][0]["entry"], "name everyone");
+
+    // The example has no shadow database
+    assert_eq!(nss_query(&["shadow"]).status.code(), Some(2));
+    assert_eq!(nss_query(&["hosts", "-h"]).status.code(), Some(1));
+}
@@ -0,0 +1,90 @@
+#![cfg(feature = "serde")]
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use libnss::group::Group;
+use libnss::host::{AddressFamily, Addresses, Host};
+use libnss::passwd::Passwd;
+use serde_json::json;
+
+#[test]
+fn hosts_flatten_their_addresses() {
+    let host = Host {
+        name: "example.test".to_string(),
+        aliases: vec!["example".to_string()],
+        addresses: Addresses::V4(vec![Ipv4Addr::new(10, 0, 0, 1)]),
+        ttl: Some(300),
+    };
+    let value = json!({
+        "name": "example.test",
+        "aliases": ["example"],
+        "family": "ipv4",
+        "addresses": ["10.0.0.1"],
+        "ttl": 300,
+    });
+    assert_eq!(serde_json::to_value(&host).unwrap(), value);
+    assert_eq!(serde_json::from_value::<Host>(value).unwrap(), host);
+
+    let host: Host = serde_json::from_value(json!({
+        "name": "v6.test",
+        "family": "ipv6",
+        "addresses": ["::1"],
+    }))
+    .unwrap();
+    assert_eq!(host.addresses, Addresses::V6(vec![Ipv6Addr::LOCALHOST]));
+    assert!(host.aliases.is_empty());
+    assert_eq!(host.ttl, None);
+
+    // An empty list still says which family it is
+    let host: Host = serde_json::from_value(json!({
+        "name": "none.test",
+        "family": "ipv6",
+        "addresses": [],
+    }))
+    .unwrap();
+    assert_eq!(host.addresses, Addresses::V6(vec![]));
+
+    for invalid in &[
+        json!({ "name": "a.test", "addresses": ["10.0.0.1"] }),
+        json!({ "name": "a.test", "family": "ipv6", "addresses": ["10.0.0.1"] }),
+        json!({ "name": "a.test", "family": "ipx", "addresses": [] }),
+    ] {
+        assert!(serde_json::from_value::<Host>(invalid.clone()).is_err());
+    }
+}
+
+#[test]
+fn address_families_are_lowercase() {
+    assert_eq!(
+        serde_json::to_value(AddressFamily::IPv6).unwrap(),
+        json!("ipv6")
+    );
+    assert_eq!(
+        serde_json::from_value::<AddressFamily>(json!("unspecified")).unwrap(),
+        AddressFamily::Unspecified
+    );
+}
+
+#[test]
+fn entries_roundtrip() {
+    let passwd = Passwd {
+        name: "test".to_string(),
+        passwd: "x".to_string(),
+        uid: 1005,
+        gid: 1005,
+        gecos: "Test Account".to_string(),
+        dir: "/home/test".to_string(),
+        shell: "/bin/bash".to_string(),
+    };
+    let value = serde_json::to_value(&passwd).unwrap();
+    assert_eq!(serde_json::from_value::<Passwd>(value).unwrap(), passwd);
+
+    let group = Group {
+        name: "staff".to_string(),
+        passwd: "x".to_string(),
+        gid: 50,
+        members: vec!["test".to_string()],
+    };
+    let value = serde_json::to_value(&group).unwrap();
+    assert_eq!(serde_json::from_value::<Group>(value).unwrap(), group);
+}
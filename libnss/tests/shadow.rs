@@ -0,0 +1,136 @@
+use std::mem::MaybeUninit;
+use std::time::{Duration, UNIX_EPOCH};
+
+use libnss::interop::{NssStatus, Response};
+use libnss::shadow::{self, CShadow, Shadow};
+#[cfg(feature = "serde")]
+use serde_json::json;
+
+#[test]
+fn dates_convert_to_days() {
+    assert_eq!(shadow::date_to_days("1970-01-01"), Some(0));
+    assert_eq!(shadow::date_to_days("2000-02-29"), Some(11016));
+    assert_eq!(shadow::date_to_days("2022-01-31"), Some(19023));
+    assert_eq!(shadow::days_to_date(19000), "2022-01-08");
+    assert_eq!(shadow::days_to_date(11016), "2000-02-29");
+
+    for invalid in &[
+        "1969-12-31",
+        "2023-02-29",
+        "2100-02-29",
+        "2024-13-01",
+        "2024-1-01",
+        "2024-01-01T00:00:00Z",
+        "20240101",
+        "",
+    ] {
+        assert_eq!(shadow::date_to_days(invalid), None, "{}", invalid);
+    }
+
+    for days in (0..200_000).step_by(7) {
+        assert_eq!(
+            shadow::date_to_days(&shadow::days_to_date(days)),
+            Some(days)
+        );
+    }
+}
+
+#[test]
+fn times_convert_to_days() {
+    let time = UNIX_EPOCH + Duration::from_secs(19000 * 86400 + 5 * 3600);
+    assert_eq!(shadow::time_to_days(time), 19000);
+    assert_eq!(shadow::time_to_days(UNIX_EPOCH - Duration::from_secs(1)), 0);
+
+    let entry = Shadow::new("test", "!").changed_at(time).expires_at(time);
+    assert_eq!(entry.last_change, Some(19000));
+    assert_eq!(entry.expire_date, Some(19000));
+    assert_eq!(entry.last_changed(), Some(shadow::days_to_time(19000)));
+    assert_eq!(entry.expires(), Some(shadow::days_to_time(19000)));
+    assert_eq!(Shadow::new("test", "!").expires(), None);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_accepts_numbers_and_dates() {
+    let entry: Shadow = serde_json::from_value(json!({
+        "name": "test",
+        "passwd": "!",
+        "last_change": "2022-01-08",
+        "change_min_days": 0,
+        "change_max_days": 99999,
+        "change_warn_days": null,
+        "change_inactive_days": -1,
+    }))
+    .unwrap();
+    assert_eq!(entry.last_change, Some(19000));
+    assert_eq!(entry.change_min_days, Some(0));
+    assert_eq!(entry.change_max_days, Some(99999));
+    assert_eq!(entry.change_warn_days, None);
+    assert_eq!(entry.change_inactive_days, None);
+    assert_eq!(entry.expire_date, None);
+    assert_eq!(entry.reserved, 0);
+
+    // Unset fields are written the way older versions expect them
+    assert_eq!(
+        serde_json::to_value(&entry).unwrap(),
+        json!({
+            "name": "test",
+            "passwd": "!",
+            "last_change": 19000,
+            "change_min_days": 0,
+            "change_max_days": 99999,
+            "change_warn_days": -1,
+            "change_inactive_days": -1,
+            "expire_date": -1,
+            "reserved": 0,
+        })
+    );
+
+    for invalid in &[
+        json!({ "name": "test", "passwd": "!", "change_max_days": "2022-01-08" }),
+        json!({ "name": "test", "passwd": "!", "expire_date": "2022-02-30" }),
+        json!({ "name": "test", "passwd": "!", "expire_date": true }),
+    ] {
+        assert!(serde_json::from_value::<Shadow>(invalid.clone()).is_err());
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn out_of_range_days_are_unset() {
+    let entry: Shadow = serde_json::from_value(json!({
+        "name": "test",
+        "passwd": "!",
+        "last_change": -2,
+        "change_min_days": i64::MIN,
+        "change_max_days": 1u64 << 32,
+        "change_warn_days": u64::MAX,
+        "change_inactive_days": 7,
+        "expire_date": -99999,
+    }))
+    .unwrap();
+    assert_eq!(entry, Shadow::new("test", "!").change_inactive_days(7));
+}
+
+#[test]
+fn unset_days_are_marshalled_as_minus_one() {
+    let entry = Shadow::new("test", "!")
+        .last_change(19000)
+        .change_max_days(99999);
+    let mut result = MaybeUninit::<CShadow>::uninit();
+    let mut buf = [0 as libc::c_char; 64];
+    let mut errno = 0;
+    let status = unsafe {
+        Response::Success(entry).to_c(result.as_mut_ptr(), buf.as_mut_ptr(), buf.len(), &mut errno)
+    };
+    assert_eq!(status, NssStatus::Success);
+
+    let result = unsafe { result.assume_init() };
+    assert_eq!(result.last_change, 19000);
+    assert_eq!(result.change_min_days, -1);
+    assert_eq!(result.change_max_days, 99999);
+    assert_eq!(result.change_warn_days, -1);
+    assert_eq!(result.change_inactive_days, -1);
+    assert_eq!(result.expire_date, -1);
+    assert_eq!(result.reserved, 0);
+}
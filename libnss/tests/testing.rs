@@ -0,0 +1,498 @@
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate libnss;
+
+use std::ffi::{CStr, CString};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Mutex;
+
+use libnss::database::Database;
+use libnss::group::{CGroup, Group, GroupHooks};
+use libnss::host::{AddressFamily, Addresses, Host, HostHooks};
+use libnss::init::{CTracedFile, InitHooks, Trigger};
+use libnss::initgroups::InitgroupsHooks;
+use libnss::interop::{NssStatus, Response, ToC};
+use libnss::passwd::{CPasswd, Passwd, PasswdHooks};
+use libnss::shadow::{Shadow, ShadowHooks};
+use libnss::testing::{self, Buffer, Call, DEFAULT_BUFLEN};
+
+fn passwd() -> Passwd {
+    Passwd {
+        name: "test".to_string(),
+        passwd: "x".to_string(),
+        uid: 1005,
+        gid: 1005,
+        gecos: "Test Account".to_string(),
+        dir: "/home/test".to_string(),
+        shell: "/bin/bash".to_string(),
+    }
+}
+
+fn group(name: &str, gid: libc::gid_t) -> Group {
+    Group {
+        name: name.to_string(),
+        passwd: "x".to_string(),
+        gid,
+        members: vec!["test".to_string(), "other".to_string()],
+    }
+}
+
+struct ExamplePasswd;
+libnss_passwd_hooks!(example, ExamplePasswd);
+
+impl PasswdHooks for ExamplePasswd {
+    fn get_all_entries() -> Response<Vec<Passwd>> {
+        let mut other = passwd();
+        other.name = "other".to_string();
+        other.uid = 1006;
+        Response::Success(vec![passwd(), other])
+    }
+
+    fn get_entry_by_uid(uid: libc::uid_t) -> Response<Passwd> {
+        match uid {
+            1005 => Response::Success(passwd()),
+            _ => Response::NotFound,
+        }
+    }
+
+    fn get_entry_by_name(name: String) -> Response<Passwd> {
+        match name.as_str() {
+            "test" => Response::Success(passwd()),
+            "flaky" => Response::TryAgain,
+            "nul" => Response::Success(Passwd {
+                gecos: "Test\0Account".to_string(),
+                ..passwd()
+            }),
+            _ => Response::NotFound,
+        }
+    }
+}
+
+struct ExampleGroup;
+libnss_group_hooks!(example, ExampleGroup);
+
+impl GroupHooks for ExampleGroup {
+    fn get_all_entries() -> Response<Vec<Group>> {
+        Response::Success(vec![group("staff", 50), group("dev", 51)])
+    }
+
+    fn get_entry_by_gid(gid: libc::gid_t) -> Response<Group> {
+        match gid {
+            50 => Response::Success(group("staff", 50)),
+            _ => Response::NotFound,
+        }
+    }
+
+    fn get_entry_by_name(name: String) -> Response<Group> {
+        match name.as_str() {
+            "staff" => Response::Success(group("staff", 50)),
+            _ => Response::NotFound,
+        }
+    }
+}
+
+struct ExampleShadow;
+libnss_shadow_hooks!(example, ExampleShadow);
+
+impl ShadowHooks for ExampleShadow {
+    fn get_all_entries() -> Response<Vec<Shadow>> {
+        Response::Unavail
+    }
+
+    fn get_entry_by_name(name: String) -> Response<Shadow> {
+        match name.as_str() {
+            "test" => Response::Success(
+                Shadow::new("test", "$6$salt$hash")
+                    .last_change(19000)
+                    .change_min_days(0)
+                    .change_max_days(99999)
+                    .change_warn_days(7),
+            ),
+            _ => Response::NotFound,
+        }
+    }
+}
+
+struct ExampleHost;
+libnss_host_hooks!(example, ExampleHost);
+
+impl HostHooks for ExampleHost {
+    fn get_all_entries() -> Response<Vec<Host>> {
+        Response::Success(Vec::new())
+    }
+
+    fn get_host_by_name(name: &str, family: AddressFamily) -> Response<Host> {
+        if name != "example.test" {
+            return Response::NotFound;
+        }
+        let addresses = match family {
+            AddressFamily::IPv6 => Addresses::V6(vec![Ipv6Addr::LOCALHOST]),
+            _ => Addresses::V4(vec![Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2)]),
+        };
+        Response::Success(Host {
+            name: name.to_string(),
+            aliases: vec!["www.example.test".to_string()],
+            addresses,
+            ttl: Some(60),
+        })
+    }
+
+    fn get_host_by_addr(addr: IpAddr) -> Response<Host> {
+        match addr {
+            IpAddr::V4(addr) if addr == Ipv4Addr::new(10, 0, 0, 1) => {
+                Self::get_host_by_name("example.test", AddressFamily::IPv4)
+            }
+            _ => Response::NotFound,
+        }
+    }
+}
+
+struct ExampleInitgroups;
+libnss_initgroups_hooks!(example, ExampleInitgroups);
+
+impl InitgroupsHooks for ExampleInitgroups {
+    fn get_entries_by_user(user: String) -> Response<Vec<Group>> {
+        match user.as_str() {
+            "test" => {
+                Response::Success(vec![group("staff", 50), group("dev", 51), group("ops", 52)])
+            }
+            _ => Response::NotFound,
+        }
+    }
+}
+
+struct ExampleInit;
+libnss_init_hooks!(example, ExampleInit);
+
+impl InitHooks for ExampleInit {
+    fn triggers() -> Vec<Trigger> {
+        vec![
+            Trigger::new(Database::Passwd, "/var/lib/example/passwd.changed"),
+            Trigger::new(Database::Shadow, "/var/lib/example/shadow.changed"),
+            Trigger::new(Database::Initgroups, "/var/lib/example/group.changed"),
+        ]
+    }
+}
+
+#[test]
+fn passwd_entries_are_decoded() {
+    let call: Call<Passwd> = testing::get_by_name(_nss_example_getpwnam_r, "test", DEFAULT_BUFLEN);
+    call.assert_status(NssStatus::Success).assert_errno(0);
+    let entry = call.unwrap();
+    assert_eq!(entry.name, "test");
+    assert_eq!(entry.gecos, "Test Account");
+    assert_eq!(entry.shell, "/bin/bash");
+
+    let call: Call<Passwd> = testing::get_by_id(_nss_example_getpwuid_r, 1005, DEFAULT_BUFLEN);
+    assert_eq!(call.unwrap().dir, "/home/test");
+}
+
+#[test]
+fn statuses_are_reported() {
+    let call: Call<Passwd> = testing::get_by_id(_nss_example_getpwuid_r, 1, DEFAULT_BUFLEN);
+    call.assert_status(NssStatus::NotFound);
+    assert!(call.entry.is_none());
+
+    let call: Call<Passwd> = testing::get_by_name(_nss_example_getpwnam_r, "flaky", DEFAULT_BUFLEN);
+    call.assert_status(NssStatus::TryAgain);
+}
+
+#[test]
+fn interior_nul_bytes_are_unavailable() {
+    let call: Call<Passwd> = testing::get_by_name(_nss_example_getpwnam_r, "nul", DEFAULT_BUFLEN);
+    call.assert_status(NssStatus::Unavail)
+        .assert_errno(libc::ENOENT);
+}
+
+#[test]
+fn small_buffers_ask_for_a_retry() {
+    let call: Call<Passwd> = testing::get_by_name(_nss_example_getpwnam_r, "test", 8);
+    call.assert_status(NssStatus::TryAgain)
+        .assert_errno(libc::ERANGE);
+
+    let call: Call<Group> = testing::get_by_id(_nss_example_getgrgid_r, 50, 16);
+    call.assert_status(NssStatus::TryAgain)
+        .assert_errno(libc::ERANGE);
+}
+
+#[test]
+fn required_sizes_are_exact() {
+    let mut buffer = Buffer::new(DEFAULT_BUFLEN);
+    let size = group("staff", 50)
+        .required_size(buffer.as_mut_ptr())
+        .unwrap();
+    // Two strings, three pointers and the members
+    assert!(size >= "staff\0x\0test\0other\0".len() + 3 * 8);
+
+    let mut result: CGroup = unsafe { std::mem::zeroed() };
+    let mut errno = 0;
+    for (buflen, status, expected) in [
+        (size, NssStatus::Success, 0),
+        (size - 1, NssStatus::TryAgain, libc::ERANGE),
+    ] {
+        let status_code = unsafe {
+            _nss_example_getgrgid_r(50, &mut result, buffer.as_mut_ptr(), buflen, &mut errno)
+        };
+        assert_eq!(NssStatus::from_c(status_code), Some(status));
+        assert_eq!(errno, expected);
+    }
+    // Nothing of the entry that did not fit is left behind
+    let written = unsafe { std::slice::from_raw_parts(buffer.as_mut_ptr(), size - 1) };
+    assert!(written.iter().all(|byte| *byte == 0));
+
+    let mut nul = passwd();
+    nul.gecos = "a\0b".to_string();
+    assert!(nul.required_size(std::ptr::null()).is_err());
+}
+
+#[test]
+fn results_point_into_the_buffer() {
+    let mut buffer = Buffer::new(DEFAULT_BUFLEN);
+    let mut result: CPasswd = unsafe { std::mem::zeroed() };
+    let mut errno = 0;
+    let name = CString::new("test").unwrap();
+    let status = unsafe {
+        _nss_example_getpwnam_r(
+            name.as_ptr(),
+            &mut result,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut errno,
+        )
+    };
+
+    assert_eq!(NssStatus::from_c(status), Some(NssStatus::Success));
+    for ptr in [
+        result.name,
+        result.passwd,
+        result.gecos,
+        result.dir,
+        result.shell,
+    ] {
+        assert!(buffer.contains(ptr));
+    }
+}
+
+#[test]
+fn groups_are_enumerated() {
+    let groups: Vec<Group> = testing::enumerate(
+        _nss_example_setgrent,
+        _nss_example_getgrent_r,
+        _nss_example_endgrent,
+        DEFAULT_BUFLEN,
+    )
+    .unwrap();
+    let names = groups.iter().map(|g| g.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["staff", "dev"]);
+    assert_eq!(groups[0].members, vec!["test", "other"]);
+
+    let enumeration = testing::enumerate::<_, Shadow>(
+        _nss_example_setspent,
+        _nss_example_getspent_r,
+        _nss_example_endspent,
+        DEFAULT_BUFLEN,
+    );
+    assert_eq!(enumeration.setent, NssStatus::Unavail);
+    assert!(enumeration.calls.is_empty());
+}
+
+#[test]
+fn shadow_entries_are_decoded() {
+    let call: Call<Shadow> = testing::get_by_name(_nss_example_getspnam_r, "test", DEFAULT_BUFLEN);
+    let entry = call.unwrap();
+    assert_eq!(entry.passwd, "$6$salt$hash");
+    assert_eq!(entry.change_max_days, Some(99999));
+    assert_eq!(entry.change_inactive_days, None);
+    assert_eq!(entry.expire_date, None);
+}
+
+#[test]
+fn hosts_report_h_errno() {
+    let call = testing::get_host_by_name(
+        _nss_example_gethostbyname2_r,
+        "example.test",
+        libc::AF_INET,
+        DEFAULT_BUFLEN,
+    );
+    call.assert_status(NssStatus::Success).assert_h_errno(0);
+    let host = call.unwrap();
+    assert_eq!(host.aliases, vec!["www.example.test"]);
+    match host.addresses {
+        Addresses::V4(addrs) => assert_eq!(
+            addrs,
+            vec![Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2)]
+        ),
+        Addresses::V6(_) => panic!("expected IPv4 addresses"),
+    }
+
+    let call = testing::get_host_by_name(
+        _nss_example_gethostbyname2_r,
+        "example.test",
+        libc::AF_INET6,
+        DEFAULT_BUFLEN,
+    );
+    match call.unwrap().addresses {
+        Addresses::V6(addrs) => assert_eq!(addrs, vec![Ipv6Addr::LOCALHOST]),
+        Addresses::V4(_) => panic!("expected IPv6 addresses"),
+    }
+
+    // NO_DATA
+    testing::get_host_by_name(
+        _nss_example_gethostbyname2_r,
+        "missing.test",
+        libc::AF_INET,
+        DEFAULT_BUFLEN,
+    )
+    .assert_status(NssStatus::NotFound)
+    .assert_h_errno(4);
+    // TRY_AGAIN
+    testing::get_host_by_name(
+        _nss_example_gethostbyname2_r,
+        "example.test",
+        libc::AF_INET,
+        16,
+    )
+    .assert_status(NssStatus::TryAgain)
+    .assert_errno(libc::ERANGE)
+    .assert_h_errno(2);
+}
+
+#[test]
+fn hosts_are_found_by_address() {
+    let call = testing::get_host_by_addr(
+        _nss_example_gethostbyaddr_r,
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+        DEFAULT_BUFLEN,
+    );
+    assert_eq!(call.unwrap().name, "example.test");
+}
+
+#[test]
+fn initgroups_appends_groups() {
+    let call = testing::initgroups_dyn(_nss_example_initgroups_dyn, "test", 51, &[1005], 1, 0);
+    call.assert_status(NssStatus::Success);
+    assert_eq!(call.unwrap(), vec![1005, 50, 52]);
+
+    let call = testing::initgroups_dyn(_nss_example_initgroups_dyn, "test", 1005, &[1005], 4, 2);
+    assert_eq!(call.unwrap(), vec![1005, 50]);
+
+    testing::initgroups_dyn(_nss_example_initgroups_dyn, "nobody", 0, &[], 4, 0)
+        .assert_status(NssStatus::NotFound)
+        .assert_errno(libc::ENOENT);
+}
+
+#[test]
+fn statuses_are_decoded() {
+    for status in [
+        NssStatus::TryAgain,
+        NssStatus::Unavail,
+        NssStatus::NotFound,
+        NssStatus::Success,
+        NssStatus::Return,
+    ] {
+        assert_eq!(NssStatus::from_c(status as libc::c_int), Some(status));
+    }
+    assert_eq!(NssStatus::from_c(7), None);
+}
+
+lazy_static! {
+    static ref TRACED: Mutex<Vec<(usize, String, String)>> = Mutex::new(Vec::new());
+}
+
+extern "C" fn trace(database: libc::size_t, file: *mut CTracedFile) {
+    let file = unsafe { &*file };
+    assert_eq!(file.inotify_descr, [-1, -1]);
+    let dname = unsafe { CStr::from_ptr(file.dname.as_ptr()) };
+    let fname = unsafe { CStr::from_ptr(std::ptr::addr_of!(file.fname) as *const libc::c_char) };
+    let sfname = unsafe { CStr::from_ptr(file.sfname) };
+    assert_eq!(
+        Some(sfname.to_bytes()),
+        fname.to_bytes().rsplit(|c| *c == b'/').next()
+    );
+    TRACED.lock().unwrap().push((
+        database,
+        fname.to_str().unwrap().to_string(),
+        dname.to_str().unwrap().to_string(),
+    ));
+}
+
+#[test]
+fn init_registers_triggers_with_nscd() {
+    unsafe { _nss_example_init(Some(trace)) };
+    assert_eq!(
+        *TRACED.lock().unwrap(),
+        vec![
+            (
+                0,
+                "/var/lib/example/passwd.changed".to_string(),
+                "/var/lib/example".to_string()
+            ),
+            (
+                1,
+                "/var/lib/example/group.changed".to_string(),
+                "/var/lib/example".to_string()
+            ),
+        ]
+    );
+}
+
+/// The offsets of `struct traced_file` from glibc's `nscd/nscd.h`, as laid
+/// out on 64-bit targets.
+#[cfg(all(target_env = "gnu", target_pointer_width = "64"))]
+#[test]
+fn traced_files_match_glibc() {
+    use std::mem::{align_of, offset_of, size_of};
+
+    assert_eq!(libc::PATH_MAX, 4096);
+    assert_eq!(offset_of!(CTracedFile, mtime), 0);
+    assert_eq!(offset_of!(CTracedFile, next), 8);
+    assert_eq!(offset_of!(CTracedFile, call_res_init), 16);
+    assert_eq!(offset_of!(CTracedFile, inotify_descr), 20);
+    assert_eq!(offset_of!(CTracedFile, dname), 28);
+    assert_eq!(offset_of!(CTracedFile, sfname), 4128);
+    assert_eq!(offset_of!(CTracedFile, fname), 4136);
+    assert_eq!(size_of::<CTracedFile>(), 4136);
+    assert_eq!(align_of::<CTracedFile>(), 8);
+}
+
+#[test]
+fn triggers_update_the_modification_time() {
+    let path = std::env::temp_dir().join(format!("libnss-trigger-{}", std::process::id()));
+    let trigger = Trigger::new(Database::Passwd, &path);
+    // Firing never creates the file
+    assert_eq!(
+        trigger.fire().unwrap_err().kind(),
+        std::io::ErrorKind::NotFound
+    );
+    assert!(!path.exists());
+
+    std::fs::File::create(&path).unwrap();
+    let past = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1);
+    std::fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(past)
+        .unwrap();
+    trigger.fire().unwrap();
+    let fired = std::fs::metadata(&path).unwrap();
+    assert!(fired.modified().unwrap() > past);
+    assert_eq!(fired.len(), 0);
+
+    // Nor follows a symlink to somewhere else
+    let link = path.with_extension("link");
+    let _ = std::fs::remove_file(&link);
+    std::os::unix::fs::symlink(&path, &link).unwrap();
+    std::fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(past)
+        .unwrap();
+    assert!(Trigger::new(Database::Passwd, &link).fire().is_err());
+    assert_eq!(std::fs::metadata(&path).unwrap().modified().unwrap(), past);
+
+    std::fs::remove_file(&link).unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,6 @@
+fn main() {
+    // Only the _nss_nya_* functions may be visible to the processes loading us
+    libnss::build::Exports::scan("src")
+        .and_then(|exports| exports.emit())
+        .expect("failed to set up the NSS exports");
+}
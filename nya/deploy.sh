@@ -1,10 +1,14 @@
 #!/bin/bash
-# export NSS_HTTP_API_REQUEST_TIMEOUT=30
-# export NSS_HTTP_API_DEBUG=false
-cat /etc/environment | grep NSS_HTTP_API_ENDPOINT
+# export NSS_HTTP_API_CONNECT_TIMEOUT_MS=1000
+# export NSS_HTTP_API_TIMEOUT_MS=5000
+# export NSS_HTTP_API_LOG_LEVEL=warn
+# The endpoint and everything else that is not tuning is only read from
+# /etc/nss_nya.conf, e.g. NSS_HTTP_API_DAEMON_SOCKET=/run/nya/nya.sock
+cat /etc/nss_nya.conf | grep NSS_HTTP_API_ENDPOINT
 if [[ $? -ne "0" ]]
 then
-  echo "NSS_HTTP_API_ENDPOINT=$NSS_HTTP_API_ENDPOINT" >> /etc/environment
+  echo "NSS_HTTP_API_ENDPOINT=$NSS_HTTP_API_ENDPOINT" >> /etc/nss_nya.conf
+  chmod 0644 /etc/nss_nya.conf
 fi
 TEST_USERNAME="test"
 GLIBC_VER=`ldd --version | grep ldd | awk '{print $NF}'`
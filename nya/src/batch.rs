@@ -0,0 +1,131 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+use libnss::fork;
+
+use crate::protocol::NetworkReqResponse;
+
+/// Cache misses for one operation and caller that are sent as a single
+/// request.
+struct Batch {
+    state: Mutex<BatchState>,
+    answered: Condvar,
+}
+
+struct BatchState {
+    values: Vec<String>,
+    answers: Option<HashMap<String, NetworkReqResponse>>,
+}
+
+/// Gathers concurrent cache misses into shared batch requests.
+///
+/// The first miss for an operation opens a batch and waits `window` for
+/// others to join before asking for all of them at once. A batch holding
+/// `max` keys takes no more, the next miss opens a new one.
+pub struct Batcher {
+    /// Batches left open by threads the child of a fork does not have are
+    /// forgotten there.
+    pending: fork::Mutex<HashMap<String, Arc<Batch>>>,
+    window: Duration,
+    max: usize,
+}
+
+impl Batcher {
+    pub fn new(window: Duration, max: usize) -> Self {
+        Batcher {
+            pending: fork::Mutex::new(HashMap::new()),
+            window,
+            max: max.max(1),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.window.is_zero() && self.max > 1
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Resolves `value` together with every other miss with the same `key`,
+    /// e.g. operation and caller, that arrives within the window.
+    ///
+    /// `fetch` runs once per batch, on the thread that opened it, and should
+    /// answer every value it is given; values it leaves out are `Unavail`.
+    pub fn join<F>(&self, key: &str, value: &str, fetch: F) -> NetworkReqResponse
+    where
+        F: FnOnce(&[String]) -> HashMap<String, NetworkReqResponse>,
+    {
+        let (batch, opened) = {
+            let mut pending = self.pending.lock().unwrap();
+            match pending.get(key).cloned() {
+                Some(batch) => {
+                    let mut state = batch.state.lock().unwrap();
+                    state.values.push(value.to_string());
+                    if state.values.len() >= self.max {
+                        pending.remove(key);
+                    }
+                    drop(state);
+                    (batch, false)
+                }
+                None => {
+                    let batch = Arc::new(Batch {
+                        state: Mutex::new(BatchState {
+                            values: vec![value.to_string()],
+                            answers: None,
+                        }),
+                        answered: Condvar::new(),
+                    });
+                    pending.insert(key.to_string(), batch.clone());
+                    (batch, true)
+                }
+            }
+        };
+
+        if opened {
+            thread::sleep(self.window);
+            {
+                let mut pending = self.pending.lock().unwrap();
+                if pending
+                    .get(key)
+                    .is_some_and(|open| Arc::ptr_eq(open, &batch))
+                {
+                    pending.remove(key);
+                }
+            }
+            // Waiters are released even if `fetch` panics
+            let _answered = Answered(&batch);
+            let mut values = batch.state.lock().unwrap().values.clone();
+            values.sort();
+            values.dedup();
+            let answers = fetch(&values);
+            batch.state.lock().unwrap().answers = Some(answers);
+        }
+
+        let mut state = batch.state.lock().unwrap();
+        while state.answers.is_none() {
+            state = batch.answered.wait(state).unwrap();
+        }
+        state
+            .answers
+            .as_ref()
+            .and_then(|answers| answers.get(value).cloned())
+            .unwrap_or(NetworkReqResponse::Unavail)
+    }
+}
+
+/// Marks a batch as answered and wakes its waiters when dropped.
+struct Answered<'a>(&'a Batch);
+
+impl Drop for Answered<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.0.state.lock() {
+            state.answers.get_or_insert_with(HashMap::new);
+        }
+        self.0.answered.notify_all();
+    }
+}
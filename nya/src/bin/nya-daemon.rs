@@ -0,0 +1,97 @@
+use std::{
+    env,
+    ffi::CString,
+    fs,
+    os::unix::{
+        fs::{DirBuilderExt, PermissionsExt},
+        net::UnixListener,
+    },
+    path::PathBuf,
+    process,
+    sync::Arc,
+};
+
+use nss_nya::config::Config;
+use nss_nya::daemon::Daemon;
+use nss_nya::protocol::DEFAULT_SOCKET_PATH;
+
+fn main() {
+    // Lookups made by the daemon itself (e.g. resolving the API host) must
+    // never be routed back to the daemon through libnss_nya
+    env::set_var("NSS_NYA_DAEMON", "1");
+
+    let config = Config::load();
+    let mut socket = config
+        .daemon_socket
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_SOCKET_PATH));
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--socket", Some(path)) => socket = PathBuf::from(path),
+            _ => {
+                eprintln!("usage: nya-daemon [--socket PATH]");
+                process::exit(2);
+            }
+        }
+    }
+
+    if let Some(dir) = socket.parent() {
+        let _ = fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o755)
+            .create(dir);
+    }
+    let _ = fs::remove_file(&socket);
+    // Nobody may connect before the socket has its final permissions
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(&socket);
+    unsafe { libc::umask(umask) };
+    let listener = match listener {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("nya-daemon: failed to bind {}: {}", socket.display(), err);
+            process::exit(1);
+        }
+    };
+    if let Some(group) = &config.daemon_socket_group {
+        let gid = match group_id(group) {
+            Some(gid) => gid,
+            None => {
+                eprintln!("nya-daemon: unknown group {}", group);
+                process::exit(1);
+            }
+        };
+        if let Err(err) = std::os::unix::fs::chown(&socket, None, Some(gid)) {
+            eprintln!("nya-daemon: failed to chown {}: {}", socket.display(), err);
+            process::exit(1);
+        }
+    }
+    let mode = fs::Permissions::from_mode(config.daemon_socket_mode);
+    if let Err(err) = fs::set_permissions(&socket, mode) {
+        eprintln!("nya-daemon: failed to chmod {}: {}", socket.display(), err);
+        process::exit(1);
+    }
+
+    let sync_interval = config.snapshot_sync_interval;
+    let daemon = Arc::new(Daemon::new(config));
+    if let Some(interval) = sync_interval {
+        daemon.clone().sync_every(interval);
+    }
+    daemon.serve(listener);
+}
+
+/// A gid given by number or by name.
+fn group_id(group: &str) -> Option<u32> {
+    if let Ok(gid) = group.parse() {
+        return Some(gid);
+    }
+    let name = CString::new(group).ok()?;
+    // Still single threaded, so the static result of getgrnam is ours
+    let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+    match entry.is_null() {
+        true => None,
+        false => Some(unsafe { (*entry).gr_gid }),
+    }
+}
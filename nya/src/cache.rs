@@ -0,0 +1,76 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use libnss::fork::Mutex;
+
+use crate::protocol::NetworkReqResponse;
+
+/// In-memory lookup cache keyed by caller, operation and parameters.
+///
+/// Only definitive answers (`Success` and `NotFound`) are stored, so an
+/// unreachable directory is always retried.
+pub struct Cache {
+    entries: Mutex<HashMap<String, (Instant, NetworkReqResponse)>>,
+    ttl: Duration,
+    negative_ttl: Duration,
+}
+
+impl Cache {
+    pub fn new(ttl: Duration, negative_ttl: Duration) -> Self {
+        Cache {
+            entries: Mutex::new(HashMap::new()),
+            ttl,
+            negative_ttl,
+        }
+    }
+
+    /// The key of a lookup made for callers with `scope`, see
+    /// `Caller::scope`.
+    pub fn key(scope: &str, fn_name: &str, params: &[(&str, String)]) -> String {
+        let mut key = format!("{}\0{}", scope, fn_name);
+        for (name, value) in params {
+            key.push('\0');
+            key.push_str(name);
+            key.push('=');
+            key.push_str(value);
+        }
+        key
+    }
+
+    pub fn get(&self, key: &str) -> Option<NetworkReqResponse> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((expires, response)) if Instant::now() < *expires => Some(response.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Stores `response` using the configured positive or negative TTL.
+    pub fn insert(&self, key: String, response: &NetworkReqResponse) {
+        let ttl = match response {
+            NetworkReqResponse::Success(_) => self.ttl,
+            NetworkReqResponse::NotFound => self.negative_ttl,
+            _ => return,
+        };
+        self.insert_with_ttl(key, response, ttl);
+    }
+
+    /// Stores `response` for the TTL the entry asked for, but never longer
+    /// than the configured one, so a server cannot pin an answer.
+    pub fn insert_with_ttl(&self, key: String, response: &NetworkReqResponse, ttl: Duration) {
+        let ttl = ttl.min(self.ttl);
+        if ttl.is_zero() {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, (expires, _)| now < *expires);
+        entries.insert(key, (now + ttl, response.clone()));
+    }
+}
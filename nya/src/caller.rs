@@ -0,0 +1,74 @@
+use std::{fs, path::PathBuf};
+
+use libc::{gid_t, pid_t, uid_t};
+
+/// The process a lookup is made on behalf of.
+///
+/// In-process lookups describe the current process; the daemon builds one
+/// from the peer credentials of each connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Caller {
+    pub uid: uid_t,
+    pub euid: uid_t,
+    pub gid: gid_t,
+    pub pid: pid_t,
+    pub ppid: pid_t,
+    /// Effective capability set (`CapEff` in `/proc/<pid>/status`).
+    pub cap_eff: u64,
+    /// The executable, when `/proc/<pid>/exe` can be read.
+    pub exe: Option<PathBuf>,
+}
+
+impl Caller {
+    pub fn current() -> Caller {
+        let mut caller = unsafe {
+            Caller {
+                uid: libc::getuid(),
+                euid: libc::geteuid(),
+                gid: libc::getgid(),
+                pid: libc::getpid(),
+                ppid: libc::getppid(),
+                cap_eff: 0,
+                exe: fs::read_link("/proc/self/exe").ok(),
+            }
+        };
+        if let Ok(status) = fs::read_to_string("/proc/self/status") {
+            caller.cap_eff = status_field(&status, "CapEff:")
+                .and_then(|caps| u64::from_str_radix(caps, 16).ok())
+                .unwrap_or(0);
+        }
+        caller
+    }
+
+    /// Describes another process from its `SO_PEERCRED` credentials alone.
+    ///
+    /// Nothing is read from `/proc/<pid>`: by the time it is read the peer
+    /// may have exec'd a privileged binary while a child keeps using the
+    /// connection, or exited and had its pid reused. The capabilities,
+    /// parent and executable of a peer are therefore unknown.
+    pub fn from_peer(pid: pid_t, euid: uid_t, egid: gid_t) -> Caller {
+        Caller {
+            uid: euid,
+            euid,
+            gid: egid,
+            pid,
+            ppid: 0,
+            cap_eff: 0,
+            exe: None,
+        }
+    }
+
+    /// The identity the API may answer differently for. Answers are only
+    /// shared between lookups made for the same one.
+    pub fn scope(&self) -> String {
+        format!("{}:{}:{}", self.uid, self.euid, self.gid)
+    }
+}
+
+/// The first value of a `/proc/<pid>/status` line starting with `name`.
+fn status_field<'a>(status: &'a str, name: &str) -> Option<&'a str> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(name))
+        .and_then(|rest| rest.split_whitespace().next())
+}
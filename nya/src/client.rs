@@ -0,0 +1,437 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use libnss::fork::Mutex;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::Method;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::caller::Caller;
+use crate::conditional::Validators;
+use crate::config::{Auth, Config, EndpointOrder};
+use crate::health::HealthTracker;
+use crate::identity::{self, Host};
+use crate::logging::{self, redact_entry, redact_header, Lookup};
+use crate::template::{batch_op, default_url_template, is_enumeration, render_url};
+use crate::tls;
+
+pub use crate::protocol::NetworkReqResponse;
+
+pub struct ApiClient {
+    config: Config,
+    health: HealthTracker,
+    validators: Validators,
+    host: Host,
+    /// Built on first use and shared by every lookup, since building one
+    /// reads the CA bundle and client certificate and starts a runtime
+    /// thread. The child of a fork, which lacks that thread, builds its own.
+    http: Mutex<Option<reqwest::blocking::Client>>,
+}
+
+impl ApiClient {
+    pub fn new(config: Config) -> Self {
+        let health = HealthTracker::new(
+            config.endpoints.len(),
+            config.failure_threshold,
+            Duration::from_secs(config.circuit_cooldown),
+        );
+        ApiClient {
+            config,
+            health,
+            validators: Validators::default(),
+            host: Host::current(),
+            http: Mutex::new(None),
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    fn url_template(&self, fn_name: &str) -> Option<String> {
+        match self.config.url_templates.get(fn_name) {
+            Some(template) => Some(template.clone()),
+            None => default_url_template(fn_name).map(String::from),
+        }
+    }
+
+    fn http_client(&self) -> Result<reqwest::blocking::Client, String> {
+        let mut http = self.http.lock().unwrap();
+        if let Some(client) = http.as_ref() {
+            return Ok(client.clone());
+        }
+        let tls = tls::client_config(&self.config)?;
+        let client = reqwest::blocking::Client::builder()
+            .connect_timeout(self.config.connect_timeout)
+            .use_preconfigured_tls(tls)
+            .build()
+            .map_err(|err| err.to_string())?;
+        *http = Some(client.clone());
+        Ok(client)
+    }
+
+    /// The identity headers describing `caller` to the server.
+    fn identity_headers(&self, caller: &Caller, fn_name: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in
+            identity::headers(&self.config.identity_headers, &self.host, caller, fn_name)
+        {
+            match HeaderValue::from_str(&value) {
+                Ok(value) => {
+                    headers.insert(name, value);
+                }
+                Err(_) => debug!("{} is not a valid header value, not sent", name),
+            }
+        }
+        headers
+    }
+
+    pub fn request(&self, fn_name: &str, params: &[(&str, String)]) -> NetworkReqResponse {
+        self.request_as(&Caller::current(), fn_name, params)
+    }
+
+    /// Performs a lookup on behalf of `caller`, who is described to the
+    /// server by the configured identity headers (`X-UID`, `X-Hostname`, ...).
+    pub fn request_as(
+        &self,
+        caller: &Caller,
+        fn_name: &str,
+        params: &[(&str, String)],
+    ) -> NetworkReqResponse {
+        let value = params
+            .iter()
+            .map(|(_, value)| value.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.dispatch(caller, fn_name, params, &value, None)
+    }
+
+    /// Looks up many values of `param` at once through the batch operation of
+    /// `fn_name`, e.g. `POST {base}/passwd/batch` for `getpwuid`.
+    ///
+    /// The request body is `{"<param>": [<values>]}` and the answer is the
+    /// array of entries that exist; missing ones are simply left out.
+    pub fn request_batch_as(
+        &self,
+        caller: &Caller,
+        fn_name: &str,
+        param: &str,
+        values: &[String],
+    ) -> NetworkReqResponse {
+        let op = match batch_op(fn_name) {
+            Some(op) => op,
+            None => return NetworkReqResponse::Error(format!("{} cannot be batched", fn_name)),
+        };
+        let keys = values
+            .iter()
+            .map(|value| match param {
+                "uid" | "gid" => value.parse::<u64>().map_or(json!(value), |id| json!(id)),
+                _ => json!(value),
+            })
+            .collect::<Vec<_>>();
+        let body = json!({ param: keys });
+        self.dispatch(caller, op, &[], &values.join(", "), Some(&body))
+    }
+
+    fn dispatch(
+        &self,
+        caller: &Caller,
+        fn_name: &str,
+        params: &[(&str, String)],
+        value: &str,
+        body: Option<&Value>,
+    ) -> NetworkReqResponse {
+        if self.config.endpoints.is_empty() {
+            warn!(
+                "{}({}) got error => {}",
+                fn_name, value, "NSS_HTTP_API_ENDPOINT is not configured"
+            );
+            return NetworkReqResponse::NotFound;
+        }
+        let template = match self.url_template(fn_name) {
+            Some(template) => template,
+            None => {
+                warn!("{}({}) has no url template", fn_name, value);
+                return NetworkReqResponse::NotFound;
+            }
+        };
+        if let Err(err) = self.http_client() {
+            error!("{}({}) got client error => {}", fn_name, value, err);
+            return NetworkReqResponse::Error(err);
+        }
+
+        let candidates = self
+            .endpoint_order()
+            .into_iter()
+            .filter(|index| self.health.available(*index))
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            warn!("{}({}) all endpoints are marked down", fn_name, value);
+            return NetworkReqResponse::Unavail;
+        }
+
+        let deadline = Instant::now() + self.config.deadline;
+        let mut result = NetworkReqResponse::Unavail;
+        for index in candidates {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                warn!("{}({}) reached the lookup deadline", fn_name, value);
+                break;
+            }
+            let timeout = self.config.request_timeout_for(fn_name).min(remaining);
+            let api_url = &self.config.endpoints[index];
+            let url = match render_url(&template, api_url, params) {
+                Ok(url) => url,
+                Err(err) => {
+                    error!("{}({}) got url template error => {}", fn_name, value, err);
+                    return NetworkReqResponse::Error(err);
+                }
+            };
+            let started = Instant::now();
+            let attempt = self.send(caller, fn_name, value, &url, timeout, body);
+            logging::lookup(&Lookup {
+                op: fn_name,
+                key: value,
+                endpoint: api_url,
+                latency: started.elapsed(),
+                outcome: match &attempt {
+                    Attempt::Answered(response) | Attempt::Failed(response) => outcome(response),
+                },
+            });
+            match attempt {
+                Attempt::Answered(response) => {
+                    self.health.record_success(index);
+                    return response;
+                }
+                Attempt::Failed(response) => {
+                    info!("{}({}) failing over from {}", fn_name, value, api_url);
+                    self.health.record_failure(index);
+                    result = response;
+                }
+            }
+        }
+        result
+    }
+
+    /// Indices into `config.endpoints` in the order they should be tried.
+    fn endpoint_order(&self) -> Vec<usize> {
+        let mut order = (0..self.config.endpoints.len()).collect::<Vec<_>>();
+        if self.config.endpoint_order == EndpointOrder::Random {
+            let mut seed = [0u8; 8];
+            if getrandom::getrandom(&mut seed).is_ok() {
+                let mut seed = u64::from_ne_bytes(seed);
+                for i in (1..order.len()).rev() {
+                    order.swap(i, (seed % (i as u64 + 1)) as usize);
+                    seed = seed.rotate_right(7) ^ (i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+                }
+            }
+        }
+        order
+    }
+
+    fn send(
+        &self,
+        caller: &Caller,
+        fn_name: &str,
+        value: &str,
+        url: &str,
+        timeout: Duration,
+        body: Option<&Value>,
+    ) -> Attempt {
+        debug!("requesting url => {} (timeout {:?})", url, timeout);
+        let conditional = is_enumeration(fn_name);
+        let validators = Validators::key(&caller.scope(), url);
+        let (method, payload) = match body {
+            Some(body) => (Method::POST, body.to_string()),
+            None => (Method::GET, String::new()),
+        };
+        let client = match self.http_client() {
+            Ok(client) => client,
+            Err(err) => return Attempt::Answered(NetworkReqResponse::Error(err)),
+        };
+        let mut request = client
+            .request(method.clone(), url)
+            .timeout(timeout)
+            .headers(self.identity_headers(caller, fn_name));
+        if conditional {
+            request = request.headers(self.validators.headers(&validators));
+        }
+        if body.is_some() {
+            request = request
+                .header(CONTENT_TYPE, "application/json")
+                .body(payload.clone());
+        }
+        match &self.config.auth {
+            Auth::None => {}
+            Auth::Bearer(token) => {
+                request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+            }
+            Auth::Hmac { key_id, secret } => {
+                let (path, timestamp, nonce) = match (
+                    reqwest::Url::parse(url),
+                    SystemTime::now().duration_since(UNIX_EPOCH),
+                    random_nonce(),
+                ) {
+                    (Ok(url), Ok(now), Ok(nonce)) => {
+                        let path = match url.query() {
+                            Some(query) => format!("{}?{}", url.path(), query),
+                            None => url.path().to_string(),
+                        };
+                        (path, now.as_secs().to_string(), nonce)
+                    }
+                    _ => {
+                        error!("{}({}) failed to sign request", fn_name, value);
+                        return Attempt::Answered(NetworkReqResponse::Error(
+                            "failed to sign request".to_string(),
+                        ));
+                    }
+                };
+                let signature = sign_request_with_body(
+                    secret,
+                    method.as_str(),
+                    &path,
+                    &timestamp,
+                    &nonce,
+                    payload.as_bytes(),
+                );
+                request = request
+                    .header("X-Nya-Key-Id", key_id.as_str())
+                    .header("X-Nya-Timestamp", timestamp)
+                    .header("X-Nya-Nonce", nonce)
+                    .header("X-Nya-Signature", signature);
+            }
+        }
+
+        let request = match request.build() {
+            Ok(request) => request,
+            Err(err) => {
+                error!("{}({}) got request error => {:?}", fn_name, value, err);
+                return Attempt::Answered(NetworkReqResponse::Error(err.to_string()));
+            }
+        };
+        if logging::enabled(logging::Level::Debug) {
+            let headers = request
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    let value = value.to_str().unwrap_or("<binary>");
+                    format!("{}: {}", name, redact_header(name.as_str(), value))
+                })
+                .collect::<Vec<_>>();
+            debug!("request headers => {:?}", headers);
+        }
+
+        let response = match client.execute(request) {
+            Ok(client) => client,
+            Err(err) => {
+                if err.is_timeout() {
+                    debug!("{}({}) got timeout error => {:?}", fn_name, value, err);
+                    return Attempt::Failed(NetworkReqResponse::TimeOut);
+                }
+                debug!("{}({}) got request error => {:?}", fn_name, value, err);
+                return Attempt::Failed(NetworkReqResponse::Unavail);
+            }
+        };
+        if conditional && response.status() == 304 {
+            if let Some(body) = self.validators.body(&validators) {
+                debug!("{}({}) not modified", fn_name, value);
+                return Attempt::Answered(NetworkReqResponse::Success(body));
+            }
+        }
+        if response.status() == 404 {
+            debug!("{}({}) got 404", fn_name, value);
+            return Attempt::Answered(NetworkReqResponse::NotFound);
+        }
+        if response.status().is_server_error() {
+            debug!("{}({}) got status {}", fn_name, value, response.status());
+            return Attempt::Failed(NetworkReqResponse::Unavail);
+        }
+        if !response.status().is_success() {
+            debug!("{}({}) got status {}", fn_name, value, response.status());
+            return Attempt::Answered(NetworkReqResponse::Error(format!(
+                "unexpected status {}",
+                response.status()
+            )));
+        }
+        let headers = response.headers().clone();
+        match response.json::<Value>() {
+            Ok(passwd) => {
+                debug!(
+                    "{}({}) got json => {}",
+                    fn_name,
+                    value,
+                    redact_entry(&passwd)
+                );
+                if conditional {
+                    self.validators.store(&validators, &headers, &passwd);
+                }
+                Attempt::Answered(NetworkReqResponse::Success(passwd))
+            }
+            Err(err) => {
+                warn!("{}({}) got json parse error => {:?}", fn_name, value, err);
+                Attempt::Answered(NetworkReqResponse::Error(err.to_string()))
+            }
+        }
+    }
+}
+
+/// The `outcome` field of a lookup record.
+fn outcome(response: &NetworkReqResponse) -> &'static str {
+    match response {
+        NetworkReqResponse::Success(_) => "success",
+        NetworkReqResponse::NotFound => "notfound",
+        NetworkReqResponse::Error(_) => "error",
+        NetworkReqResponse::TimeOut => "timeout",
+        NetworkReqResponse::Unavail => "unavail",
+    }
+}
+
+/// Outcome of sending a request to a single endpoint.
+enum Attempt {
+    /// The endpoint answered; no other endpoint is tried.
+    Answered(NetworkReqResponse),
+    /// The endpoint is unreachable or broken, try the next one.
+    Failed(NetworkReqResponse),
+}
+
+/// Computes the hex encoded `X-Nya-Signature` header.
+///
+/// The signed message is `METHOD\nPATH?QUERY\nTIMESTAMP\nNONCE`, so the server
+/// can reject both tampered and replayed requests.
+pub fn sign_request(
+    secret: &str,
+    method: &str,
+    path: &str,
+    timestamp: &str,
+    nonce: &str,
+) -> String {
+    sign_request_with_body(secret, method, path, timestamp, nonce, &[])
+}
+
+/// Like `sign_request`, for requests carrying a body. A non-empty body adds
+/// a fifth line holding its hex encoded SHA-256.
+pub fn sign_request_with_body(
+    secret: &str,
+    method: &str,
+    path: &str,
+    timestamp: &str,
+    nonce: &str,
+    body: &[u8],
+) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    let mut message = format!("{}\n{}\n{}\n{}", method, path, timestamp, nonce);
+    if !body.is_empty() {
+        message.push('\n');
+        message.push_str(&hex::encode(Sha256::digest(body)));
+    }
+    mac.update(message.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn random_nonce() -> Result<String, getrandom::Error> {
+    let mut nonce = [0u8; 16];
+    getrandom::getrandom(&mut nonce)?;
+    Ok(hex::encode(nonce))
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use libnss::fork::Mutex;
+
+use reqwest::header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use serde_json::Value;
+
+/// A previously downloaded enumeration and the validators it came with.
+#[derive(Clone, Debug)]
+struct Validated {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    body: Value,
+}
+
+/// Remembers `ETag` and `Last-Modified` of enumeration responses per caller
+/// and URL, so the next download can be made conditional and a `304 Not
+/// Modified` can be answered with the copy kept here.
+///
+/// Keyed by [`Validators::key`]: every endpoint has its own validators, and
+/// the server may answer each caller differently.
+#[derive(Default)]
+pub struct Validators {
+    entries: Mutex<HashMap<String, Validated>>,
+}
+
+impl Validators {
+    /// The key of a download of `url` made for callers with `scope`, see
+    /// `Caller::scope`.
+    pub fn key(scope: &str, url: &str) -> String {
+        format!("{}\0{}", scope, url)
+    }
+
+    /// `If-None-Match` and `If-Modified-Since` headers for the download
+    /// stored under `key`.
+    pub fn headers(&self, key: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let entries = self.entries.lock().unwrap();
+        if let Some(validated) = entries.get(key) {
+            if let Some(etag) = validated.etag.as_ref().and_then(|v| v.parse().ok()) {
+                headers.insert(IF_NONE_MATCH, etag);
+            }
+            if let Some(date) = validated
+                .last_modified
+                .as_ref()
+                .and_then(|v| v.parse().ok())
+            {
+                headers.insert(IF_MODIFIED_SINCE, date);
+            }
+        }
+        headers
+    }
+
+    /// The body stored under `key`, used to answer a `304`.
+    pub fn body(&self, key: &str) -> Option<Value> {
+        let entries = self.entries.lock().unwrap();
+        entries.get(key).map(|validated| validated.body.clone())
+    }
+
+    /// Stores `body` along with the validators in `headers` under `key`, or
+    /// forgets it when the response carried none.
+    pub fn store(&self, key: &str, headers: &HeaderMap, body: &Value) {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+
+        let mut entries = self.entries.lock().unwrap();
+        if etag.is_none() && last_modified.is_none() {
+            entries.remove(key);
+            return;
+        }
+        entries.insert(
+            key.to_string(),
+            Validated {
+                etag,
+                last_modified,
+                body: body.clone(),
+            },
+        );
+    }
+}
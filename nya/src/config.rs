@@ -0,0 +1,541 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    os::unix::prelude::MetadataExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::identity;
+use crate::logging::Level;
+use crate::template::{database, DATABASES, OPS};
+
+lazy_static! {
+    pub static ref CONFIG: Config = Config::load();
+}
+
+/// Root-owned configuration file, holding `KEY=value` lines such as
+/// `NSS_HTTP_API_ENDPOINT=https://...`.
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/nss_nya.conf";
+
+/// Keys the environment may override, none of which choose where lookups
+/// go, what they may return or which files are touched. Everything else is
+/// read from the config file only.
+const ENV_KEYS: &[&str] = &[
+    "NSS_HTTP_API_ENDPOINT_ORDER",
+    "NSS_HTTP_API_FAILURE_THRESHOLD",
+    "NSS_HTTP_API_CIRCUIT_COOLDOWN",
+    "NSS_HTTP_API_DEBUG",
+    "NSS_HTTP_API_LOG_LEVEL",
+    "NSS_HTTP_API_CONNECT_TIMEOUT_MS",
+    "NSS_HTTP_API_TIMEOUT_MS",
+    "NSS_HTTP_API_REQUEST_TIMEOUT",
+    "NSS_HTTP_API_DEADLINE_MS",
+    "NSS_HTTP_API_CACHE_TTL",
+    "NSS_HTTP_API_CACHE_NEGATIVE_TTL",
+    "NSS_HTTP_API_BATCH_WINDOW_MS",
+    "NSS_HTTP_API_BATCH_MAX",
+];
+
+/// Keys that may only come from the config file, and only when the file is
+/// not readable by other users.
+const SECRET_KEYS: &[&str] = &["NSS_HTTP_API_TOKEN", "NSS_HTTP_API_HMAC_SECRET"];
+
+/// Keys deciding which entries the API may return and who may read shadow
+/// entries. They are only read from a root-owned config file, never from
+/// one owned by the effective uid.
+const POLICY_KEYS: &[&str] = &[
+    "NSS_HTTP_API_UID_RANGES",
+    "NSS_HTTP_API_GID_RANGES",
+    "NSS_HTTP_API_RESERVED_NAMES",
+    "NSS_HTTP_API_ALLOWED_SHELLS",
+    "NSS_HTTP_API_HOME_PREFIXES",
+    "NSS_HTTP_API_SHADOW_UIDS",
+    "NSS_HTTP_API_SHADOW_CAPABILITIES",
+    "NSS_HTTP_API_SHADOW_EXECUTABLES",
+    "NSS_HTTP_API_SNAPSHOT_SHADOW",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Auth {
+    None,
+    /// Sent as `Authorization: Bearer <token>`.
+    Bearer(String),
+    /// HMAC-SHA256 over method, path, timestamp, nonce and any body, see
+    /// `client::sign_request_with_body`.
+    Hmac {
+        key_id: String,
+        secret: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EndpointOrder {
+    /// Always prefer endpoints in the order they are configured.
+    Ordered,
+    /// Shuffle endpoints for every lookup to spread load.
+    Random,
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// API base URLs, tried in `endpoint_order` until one answers.
+    pub endpoints: Vec<String>,
+    pub endpoint_order: EndpointOrder,
+    /// Consecutive failures after which an endpoint is skipped.
+    pub failure_threshold: u32,
+    /// Seconds an endpoint is skipped for once its circuit opens.
+    pub circuit_cooldown: u64,
+    /// Most verbose level logged, `None` disables logging.
+    pub log_level: Option<Level>,
+    /// Log to this file instead of syslog.
+    pub log_file: Option<PathBuf>,
+    /// Time allowed to establish a connection to a single endpoint.
+    pub connect_timeout: Duration,
+    /// Total time allowed for a single request, connect included.
+    pub request_timeout: Duration,
+    /// `request_timeout` overrides keyed by database (`passwd`) or operation (`getpwent`).
+    pub request_timeouts: HashMap<String, Duration>,
+    /// Upper bound for a whole lookup, across every endpoint tried.
+    pub deadline: Duration,
+    pub url_templates: HashMap<String, String>,
+    /// Which of `identity::FIELDS` are sent as request headers.
+    pub identity_headers: Vec<String>,
+    pub auth: Auth,
+    /// PEM file holding the client certificate chain used for mutual TLS.
+    pub client_cert: Option<PathBuf>,
+    /// PEM file holding the private key for `client_cert`.
+    pub client_key: Option<PathBuf>,
+    /// PEM bundle of additional trust anchors, e.g. an internal CA.
+    pub ca_bundle: Option<PathBuf>,
+    /// Whether the built-in webpki roots are trusted besides `ca_bundle`.
+    pub public_roots: bool,
+    /// Directory holding the offline snapshot, disabled when unset.
+    pub snapshot_dir: Option<PathBuf>,
+    /// Whether shadow entries are kept in the snapshot as well.
+    pub snapshot_shadow: bool,
+    /// Snapshot entries older than this are never served.
+    pub snapshot_max_age: Duration,
+    /// Unchanged entries are rewritten at most this often.
+    pub snapshot_refresh: Duration,
+    /// How often `nya-daemon` refreshes the snapshot from full enumerations.
+    pub snapshot_sync_interval: Option<Duration>,
+    /// Unix socket of `nya-daemon`. When set the module forwards every
+    /// lookup to the daemon instead of talking HTTP itself.
+    pub daemon_socket: Option<PathBuf>,
+    /// Permissions of the daemon socket. Processes that cannot connect fall
+    /// back to the snapshot, so `0o666` is needed for everyone to see
+    /// remote users.
+    pub daemon_socket_mode: u32,
+    /// Group owning the daemon socket, by name or gid.
+    pub daemon_socket_group: Option<String>,
+    /// Connections the daemon serves at once; further ones wait to be
+    /// accepted.
+    pub daemon_max_connections: usize,
+    /// Connections the daemon serves at once for any one uid, so no local
+    /// user can take all of them. Further ones are closed right away.
+    pub daemon_max_connections_per_uid: usize,
+    /// How long successful lookups are cached, and the most a `ttl` the
+    /// server sends with an entry can ask for.
+    pub cache_ttl: Duration,
+    /// How long "not found" answers are cached.
+    pub cache_negative_ttl: Duration,
+    /// How long a cache miss waits for others to share one batch request.
+    /// Batching is disabled when zero.
+    pub batch_window: Duration,
+    /// Most keys sent in a single batch request.
+    pub batch_max: usize,
+    /// `sha256//<base64>` SPKI pins, one of which must match the server chain.
+    pub pinned_pubkeys: Vec<String>,
+    /// Inclusive uid ranges remote users may have.
+    pub uid_ranges: Vec<(u32, u32)>,
+    /// Inclusive gid ranges remote users and groups may have.
+    pub gid_ranges: Vec<(u32, u32)>,
+    /// Names the API may never return.
+    pub reserved_names: Vec<String>,
+    /// Login shells remote users may have, any shell when empty.
+    pub allowed_shells: Vec<String>,
+    /// Directories remote home directories must live under, anywhere when empty.
+    pub home_prefixes: Vec<String>,
+    /// Effective uids allowed to read shadow entries.
+    pub shadow_uids: Vec<u32>,
+    /// Effective capabilities that allow reading shadow entries, by name.
+    pub shadow_capabilities: Vec<String>,
+    /// Executables (full paths or file names) that shadow readers must be.
+    /// Any executable when empty.
+    pub shadow_executables: Vec<String>,
+    /// Local databases remote entries may not shadow, by name or id.
+    pub local_passwd: PathBuf,
+    pub local_group: PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            endpoints: Vec::new(),
+            endpoint_order: EndpointOrder::Ordered,
+            failure_threshold: 3,
+            circuit_cooldown: 30,
+            log_level: Some(Level::Warn),
+            log_file: None,
+            connect_timeout: Duration::from_millis(1000),
+            request_timeout: Duration::from_millis(5000),
+            request_timeouts: HashMap::new(),
+            deadline: Duration::from_millis(10000),
+            url_templates: HashMap::new(),
+            identity_headers: identity::FIELDS.iter().map(|f| f.to_string()).collect(),
+            auth: Auth::None,
+            client_cert: None,
+            client_key: None,
+            ca_bundle: None,
+            public_roots: true,
+            pinned_pubkeys: Vec::new(),
+            snapshot_dir: None,
+            snapshot_shadow: false,
+            snapshot_max_age: Duration::from_secs(7 * 24 * 60 * 60),
+            snapshot_refresh: Duration::from_secs(60 * 60),
+            snapshot_sync_interval: None,
+            // Without an HTTP stack the daemon is the only way to reach the API
+            daemon_socket: match cfg!(feature = "http") {
+                true => None,
+                false => Some(PathBuf::from(crate::protocol::DEFAULT_SOCKET_PATH)),
+            },
+            daemon_socket_mode: 0o660,
+            daemon_socket_group: None,
+            daemon_max_connections: 64,
+            daemon_max_connections_per_uid: 8,
+            cache_ttl: Duration::from_secs(60),
+            cache_negative_ttl: Duration::from_secs(10),
+            batch_window: Duration::ZERO,
+            batch_max: 100,
+            // Everything but system accounts and the 32-bit "no id" value
+            uid_ranges: vec![(1000, u32::MAX - 1)],
+            gid_ranges: vec![(1000, u32::MAX - 1)],
+            reserved_names: vec!["root".to_string(), "nobody".to_string()],
+            allowed_shells: Vec::new(),
+            home_prefixes: Vec::new(),
+            // The same processes that could read /etc/shadow themselves
+            shadow_uids: vec![0],
+            shadow_capabilities: vec![
+                "CAP_DAC_READ_SEARCH".to_string(),
+                "CAP_DAC_OVERRIDE".to_string(),
+            ],
+            shadow_executables: Vec::new(),
+            local_passwd: PathBuf::from("/etc/passwd"),
+            local_group: PathBuf::from("/etc/group"),
+        }
+    }
+}
+
+impl Config {
+    /// Per-request timeout for `fn_name`, preferring an operation override
+    /// over a database override over the global default.
+    pub fn request_timeout_for(&self, fn_name: &str) -> Duration {
+        self.request_timeouts
+            .get(fn_name)
+            .or_else(|| self.request_timeouts.get(database(fn_name)))
+            .copied()
+            .unwrap_or(self.request_timeout)
+    }
+
+    /// Loads the system config file. Processes running with `AT_SECURE`,
+    /// e.g. setuid binaries, ignore the environment completely.
+    pub fn load() -> Config {
+        let secure = unsafe { libc::getauxval(libc::AT_SECURE) } != 0;
+        Config::load_from(|key| env::var(key).ok(), secure)
+    }
+
+    /// `load` with the environment and `AT_SECURE` passed in.
+    ///
+    /// Without `secure`, `NSS_HTTP_API_CONFIG` may point at another file,
+    /// which may also be owned by the effective uid as long as it sets no
+    /// `POLICY_KEYS`, and the environment may override the tuning keys in
+    /// `ENV_KEYS`.
+    pub fn load_from<E: Fn(&str) -> Option<String>>(env: E, secure: bool) -> Config {
+        let env = |key: &str| match secure {
+            true => None,
+            false => env(key),
+        };
+        let file = match env("NSS_HTTP_API_CONFIG") {
+            Some(path) => read_config_file(Path::new(&path), unsafe { libc::geteuid() }),
+            None => read_config_file(Path::new(DEFAULT_CONFIG_PATH), 0),
+        };
+        let file = file.unwrap_or_default();
+
+        Config::from_lookup(|key| {
+            if SECRET_KEYS.contains(&key) {
+                return match file.private {
+                    true => file.values.get(key).cloned(),
+                    false => None,
+                };
+            }
+            if POLICY_KEYS.contains(&key) {
+                return match file.root_owned {
+                    true => file.values.get(key).cloned(),
+                    false => None,
+                };
+            }
+            let overridable =
+                ENV_KEYS.contains(&key) || key.starts_with("NSS_HTTP_API_TIMEOUT_MS_");
+            match overridable {
+                true => file.values.get(key).cloned().or_else(|| env(key)),
+                false => file.values.get(key).cloned(),
+            }
+        })
+    }
+
+    pub fn from_lookup<F: Fn(&str) -> Option<String>>(lookup: F) -> Config {
+        let mut config = Config {
+            endpoints: lookup("NSS_HTTP_API_ENDPOINT")
+                .map(|endpoints| {
+                    endpoints
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|endpoint| !endpoint.is_empty())
+                        .map(String::from)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+        if lookup("NSS_HTTP_API_ENDPOINT_ORDER").as_deref() == Some("random") {
+            config.endpoint_order = EndpointOrder::Random;
+        }
+        if let Some(threshold) =
+            lookup("NSS_HTTP_API_FAILURE_THRESHOLD").and_then(|v| v.parse().ok())
+        {
+            config.failure_threshold = threshold;
+        }
+        if let Some(cooldown) = lookup("NSS_HTTP_API_CIRCUIT_COOLDOWN").and_then(|v| v.parse().ok())
+        {
+            config.circuit_cooldown = cooldown;
+        }
+
+        // NSS_HTTP_API_DEBUG predates log levels and means "log everything"
+        if let Some(true) = lookup("NSS_HTTP_API_DEBUG").and_then(|v| v.parse().ok()) {
+            config.log_level = Some(Level::Debug);
+        }
+        if let Some(level) = lookup("NSS_HTTP_API_LOG_LEVEL").and_then(|v| Level::parse(&v)) {
+            config.log_level = level;
+        }
+        if let Some(file) = lookup("NSS_HTTP_API_LOG_FILE") {
+            config.log_file = Some(PathBuf::from(file));
+        }
+        if let Some(timeout) =
+            lookup("NSS_HTTP_API_CONNECT_TIMEOUT_MS").and_then(|v| v.parse().ok())
+        {
+            config.connect_timeout = Duration::from_millis(timeout);
+        }
+        // NSS_HTTP_API_REQUEST_TIMEOUT is the original whole-second setting
+        let timeout = match lookup("NSS_HTTP_API_TIMEOUT_MS").and_then(|v| v.parse().ok()) {
+            Some(timeout) => Some(Duration::from_millis(timeout)),
+            None => lookup("NSS_HTTP_API_REQUEST_TIMEOUT")
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs),
+        };
+        if let Some(timeout) = timeout {
+            config.request_timeout = timeout;
+        }
+        for name in DATABASES.iter().chain(OPS) {
+            let key = format!("NSS_HTTP_API_TIMEOUT_MS_{}", name.to_uppercase());
+            if let Some(timeout) = lookup(&key).and_then(|v| v.parse().ok()) {
+                config
+                    .request_timeouts
+                    .insert(name.to_string(), Duration::from_millis(timeout));
+            }
+        }
+        if let Some(deadline) = lookup("NSS_HTTP_API_DEADLINE_MS").and_then(|v| v.parse().ok()) {
+            config.deadline = Duration::from_millis(deadline);
+        }
+
+        for op in OPS {
+            if let Some(template) = lookup(&format!("NSS_HTTP_API_URL_{}", op.to_uppercase())) {
+                config.url_templates.insert(op.to_string(), template);
+            }
+        }
+
+        if let Some(fields) = lookup("NSS_HTTP_API_IDENTITY_HEADERS") {
+            config.identity_headers = parse_list(&fields)
+                .into_iter()
+                .map(|field| field.to_lowercase())
+                .filter(|field| identity::FIELDS.contains(&field.as_str()))
+                .collect();
+        }
+
+        config.auth = match lookup("NSS_HTTP_API_AUTH").as_deref() {
+            Some("bearer") => match lookup("NSS_HTTP_API_TOKEN") {
+                Some(token) => Auth::Bearer(token),
+                None => Auth::None,
+            },
+            Some("hmac") => match (
+                lookup("NSS_HTTP_API_HMAC_KEY_ID"),
+                lookup("NSS_HTTP_API_HMAC_SECRET"),
+            ) {
+                (Some(key_id), Some(secret)) => Auth::Hmac { key_id, secret },
+                _ => Auth::None,
+            },
+            _ => Auth::None,
+        };
+        config.client_cert = lookup("NSS_HTTP_API_CLIENT_CERT").map(PathBuf::from);
+        config.client_key = lookup("NSS_HTTP_API_CLIENT_KEY").map(PathBuf::from);
+        config.ca_bundle = lookup("NSS_HTTP_API_CA_BUNDLE").map(PathBuf::from);
+        if let Some(public_roots) = lookup("NSS_HTTP_API_PUBLIC_ROOTS").and_then(|v| v.parse().ok())
+        {
+            config.public_roots = public_roots;
+        }
+        if let Some(pins) = lookup("NSS_HTTP_API_PINNED_PUBKEYS") {
+            config.pinned_pubkeys = pins
+                .split(';')
+                .map(str::trim)
+                .filter(|pin| !pin.is_empty())
+                .map(String::from)
+                .collect();
+        }
+        if let Some(ranges) = lookup("NSS_HTTP_API_UID_RANGES") {
+            config.uid_ranges = parse_ranges(&ranges);
+        }
+        if let Some(ranges) = lookup("NSS_HTTP_API_GID_RANGES") {
+            config.gid_ranges = parse_ranges(&ranges);
+        }
+        if let Some(names) = lookup("NSS_HTTP_API_RESERVED_NAMES") {
+            config.reserved_names = parse_list(&names);
+        }
+        if let Some(shells) = lookup("NSS_HTTP_API_ALLOWED_SHELLS") {
+            config.allowed_shells = parse_list(&shells);
+        }
+        if let Some(prefixes) = lookup("NSS_HTTP_API_HOME_PREFIXES") {
+            config.home_prefixes = parse_list(&prefixes);
+        }
+        if let Some(uids) = lookup("NSS_HTTP_API_SHADOW_UIDS") {
+            config.shadow_uids = parse_list(&uids)
+                .iter()
+                .map(|uid| uid.parse().ok())
+                .collect::<Option<_>>()
+                .unwrap_or_default();
+        }
+        if let Some(capabilities) = lookup("NSS_HTTP_API_SHADOW_CAPABILITIES") {
+            config.shadow_capabilities = parse_list(&capabilities);
+        }
+        if let Some(executables) = lookup("NSS_HTTP_API_SHADOW_EXECUTABLES") {
+            config.shadow_executables = parse_list(&executables);
+        }
+        config.snapshot_dir = lookup("NSS_HTTP_API_SNAPSHOT_DIR").map(PathBuf::from);
+        if let Some(shadow) = lookup("NSS_HTTP_API_SNAPSHOT_SHADOW").and_then(|v| v.parse().ok()) {
+            config.snapshot_shadow = shadow;
+        }
+        if let Some(max_age) = lookup("NSS_HTTP_API_SNAPSHOT_MAX_AGE").and_then(|v| v.parse().ok())
+        {
+            config.snapshot_max_age = Duration::from_secs(max_age);
+        }
+        if let Some(refresh) = lookup("NSS_HTTP_API_SNAPSHOT_REFRESH").and_then(|v| v.parse().ok())
+        {
+            config.snapshot_refresh = Duration::from_secs(refresh);
+        }
+        if let Some(interval) =
+            lookup("NSS_HTTP_API_SNAPSHOT_SYNC_INTERVAL").and_then(|v| v.parse().ok())
+        {
+            config.snapshot_sync_interval = Some(Duration::from_secs(interval));
+        }
+        if let Some(socket) = lookup("NSS_HTTP_API_DAEMON_SOCKET") {
+            config.daemon_socket = Some(PathBuf::from(socket));
+        }
+        if let Some(mode) = lookup("NSS_HTTP_API_DAEMON_SOCKET_MODE")
+            .and_then(|v| u32::from_str_radix(&v, 8).ok())
+            .filter(|mode| *mode <= 0o777)
+        {
+            config.daemon_socket_mode = mode;
+        }
+        config.daemon_socket_group = lookup("NSS_HTTP_API_DAEMON_SOCKET_GROUP");
+        if let Some(max) =
+            lookup("NSS_HTTP_API_DAEMON_MAX_CONNECTIONS").and_then(|v| v.parse().ok())
+        {
+            config.daemon_max_connections = max;
+        }
+        if let Some(max) =
+            lookup("NSS_HTTP_API_DAEMON_MAX_CONNECTIONS_PER_UID").and_then(|v| v.parse().ok())
+        {
+            config.daemon_max_connections_per_uid = max;
+        }
+        if let Some(ttl) = lookup("NSS_HTTP_API_CACHE_TTL").and_then(|v| v.parse().ok()) {
+            config.cache_ttl = Duration::from_secs(ttl);
+        }
+        if let Some(ttl) = lookup("NSS_HTTP_API_CACHE_NEGATIVE_TTL").and_then(|v| v.parse().ok()) {
+            config.cache_negative_ttl = Duration::from_secs(ttl);
+        }
+        if let Some(window) = lookup("NSS_HTTP_API_BATCH_WINDOW_MS").and_then(|v| v.parse().ok()) {
+            config.batch_window = Duration::from_millis(window);
+        }
+        if let Some(max) = lookup("NSS_HTTP_API_BATCH_MAX").and_then(|v| v.parse().ok()) {
+            config.batch_max = max;
+        }
+
+        config
+    }
+}
+
+/// Parses a comma separated list, dropping empty items.
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Parses ranges like `1000-59999,70000`. A malformed list allows nothing
+/// rather than falling back to the defaults.
+fn parse_ranges(value: &str) -> Vec<(u32, u32)> {
+    let ranges = parse_list(value)
+        .iter()
+        .map(|range| match range.split_once('-') {
+            Some((start, end)) => Some((start.trim().parse().ok()?, end.trim().parse().ok()?)),
+            None => range.parse().ok().map(|id| (id, id)),
+        })
+        .collect::<Option<Vec<_>>>();
+    ranges.unwrap_or_default()
+}
+
+/// Parses `KEY=value` lines, ignoring blank lines and `#` comments.
+pub fn parse_config(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            (key.trim().to_string(), value.to_string())
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct ConfigFile {
+    values: HashMap<String, String>,
+    /// Not readable by other users, so it may hold secrets.
+    private: bool,
+    /// Owned by root rather than the effective uid, so it may set policy.
+    root_owned: bool,
+}
+
+/// Reads and parses the config file at `path`.
+///
+/// The file is ignored unless it is owned by root or `owner` and not
+/// writable by anyone else.
+fn read_config_file(path: &Path, owner: u32) -> Option<ConfigFile> {
+    let metadata = fs::metadata(path).ok()?;
+    if (metadata.uid() != 0 && metadata.uid() != owner) || metadata.mode() & 0o022 != 0 {
+        return None;
+    }
+    let contents = fs::read_to_string(path).ok()?;
+    Some(ConfigFile {
+        values: parse_config(&contents),
+        private: metadata.mode() & 0o007 == 0,
+        root_owned: metadata.uid() == 0,
+    })
+}
@@ -0,0 +1,204 @@
+use std::{
+    collections::HashMap,
+    io, mem,
+    os::unix::{
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use libc::uid_t;
+
+use crate::caller::Caller;
+use crate::config::Config;
+use crate::privilege::ShadowAccess;
+use crate::protocol::{read_frame, write_frame, Deadline, NetworkReqResponse, Request};
+use crate::resolver::Resolver;
+use crate::template::database;
+
+/// Serves lookups from NSS modules over a Unix domain socket.
+///
+/// The daemon owns the HTTP client, cache and credentials, so the module
+/// loaded into every process only needs to speak the socket protocol.
+pub struct Daemon {
+    resolver: Resolver,
+    shadow_access: ShadowAccess,
+    /// How long a connection may take from accept to answer, however the
+    /// peer paces its request.
+    connection_timeout: Duration,
+    max_connections: usize,
+    max_connections_per_uid: usize,
+    /// Connections being served, and a signal for when one is done.
+    connections: (Mutex<usize>, Condvar),
+    /// Connections being served per peer uid.
+    connections_per_uid: Mutex<HashMap<uid_t, usize>>,
+}
+
+impl Daemon {
+    pub fn new(config: Config) -> Self {
+        Daemon {
+            connection_timeout: config.deadline + Duration::from_secs(1),
+            max_connections: config.daemon_max_connections.max(1),
+            max_connections_per_uid: config.daemon_max_connections_per_uid.max(1),
+            connections: (Mutex::new(0), Condvar::new()),
+            connections_per_uid: Mutex::new(HashMap::new()),
+            shadow_access: ShadowAccess::new(&config),
+            resolver: Resolver::new(config),
+        }
+    }
+
+    /// Answers a single request on behalf of `caller`.
+    ///
+    /// Shadow entries are only handed to callers the shadow access policy
+    /// allows, whatever the module on the other end decided. Peers are known
+    /// by their `SO_PEERCRED` credentials only, so over the socket only
+    /// `shadow_uids` grant access and an executable allowlist refuses
+    /// everyone.
+    pub fn handle(&self, caller: &Caller, request: &Request) -> NetworkReqResponse {
+        if database(&request.op) == "shadow" {
+            if let Err(reason) = self.shadow_access.check(caller) {
+                info!(
+                    "{} refused for pid {} => {}",
+                    request.op, caller.pid, reason
+                );
+                return NetworkReqResponse::NotFound;
+            }
+        }
+
+        let params = request
+            .params
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.clone()))
+            .collect::<Vec<_>>();
+        self.resolver.resolve(caller, &request.op, &params)
+    }
+
+    /// Accepts connections forever, one thread per connection. Once
+    /// `max_connections` are being served, the next is only accepted when
+    /// one of them is done, which takes at most the connection timeout.
+    /// Connections beyond `max_connections_per_uid` for the same peer uid
+    /// are closed unanswered.
+    pub fn serve(self: Arc<Self>, listener: UnixListener) {
+        loop {
+            self.wait_for_slot();
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let daemon = self.clone();
+                    thread::spawn(move || {
+                        let _slot = Slot(&daemon.connections);
+                        if let Err(err) = daemon.serve_connection(stream) {
+                            warn!("connection failed => {}", err);
+                        }
+                    });
+                }
+                Err(err) => {
+                    drop(Slot(&self.connections));
+                    error!("accept failed => {}", err);
+                }
+            }
+        }
+    }
+
+    /// Blocks until fewer than `max_connections` are served, and takes a
+    /// slot for the next one.
+    fn wait_for_slot(&self) {
+        let (count, done) = &self.connections;
+        let mut count = count.lock().unwrap();
+        while *count >= self.max_connections {
+            count = done.wait(count).unwrap();
+        }
+        *count += 1;
+    }
+
+    /// Periodically refreshes the offline snapshot from full enumerations.
+    pub fn sync_every(self: Arc<Self>, interval: Duration) {
+        thread::spawn(move || loop {
+            self.resolver.sync(&Caller::current());
+            thread::sleep(interval);
+        });
+    }
+
+    /// Takes one of the connection slots of `uid`, if it has any left.
+    fn uid_slot(&self, uid: uid_t) -> Option<UidSlot<'_>> {
+        let mut counts = self.connections_per_uid.lock().unwrap();
+        let count = counts.entry(uid).or_insert(0);
+        if *count >= self.max_connections_per_uid {
+            return None;
+        }
+        *count += 1;
+        Some(UidSlot {
+            counts: &self.connections_per_uid,
+            uid,
+        })
+    }
+
+    fn serve_connection(&self, stream: UnixStream) -> io::Result<()> {
+        let until = Instant::now() + self.connection_timeout;
+        let caller = peer_caller(&stream)?;
+        let _slot = match self.uid_slot(caller.uid) {
+            Some(slot) => slot,
+            None => {
+                info!("refused connection from uid {} => too many", caller.uid);
+                return Ok(());
+            }
+        };
+
+        let mut stream = Deadline::new(&stream, until);
+        let request: Request = read_frame(&mut stream)?;
+        let response = self.handle(&caller, &request);
+        write_frame(&mut stream, &response)
+    }
+}
+
+/// Gives a connection slot back when dropped.
+struct Slot<'a>(&'a (Mutex<usize>, Condvar));
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        let (count, done) = self.0;
+        if let Ok(mut count) = count.lock() {
+            *count -= 1;
+        }
+        done.notify_one();
+    }
+}
+
+/// Gives a connection slot of a uid back when dropped.
+struct UidSlot<'a> {
+    counts: &'a Mutex<HashMap<uid_t, usize>>,
+    uid: uid_t,
+}
+
+impl Drop for UidSlot<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut counts) = self.counts.lock() {
+            if let Some(count) = counts.get_mut(&self.uid) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.remove(&self.uid);
+                }
+            }
+        }
+    }
+}
+
+fn peer_caller(stream: &UnixStream) -> io::Result<Caller> {
+    let mut cred: libc::ucred = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Caller::from_peer(cred.pid, cred.uid, cred.gid))
+}
@@ -1,11 +0,0 @@
-#[macro_export]
-macro_rules! debug {
-  ($($arg:tt)*) => {
-      if *NSS_HTTP_API_DEBUG {
-          println!("NSS_HTTP DEBUG: {}", format_args!($($arg)*))
-      }
-  };
-  () => {
-
-  };
-}
\ No newline at end of file
@@ -0,0 +1,61 @@
+use std::time::{Duration, Instant};
+
+use libnss::fork::Mutex;
+
+#[derive(Clone, Debug, Default)]
+struct EndpointHealth {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// Per-endpoint circuit breaker shared by every lookup in the process.
+///
+/// After `threshold` consecutive failures an endpoint is skipped for
+/// `cooldown`. Once the cooldown has passed a single lookup may probe it
+/// again; a success closes the circuit, a failure re-opens it.
+pub struct HealthTracker {
+    endpoints: Mutex<Vec<EndpointHealth>>,
+    threshold: u32,
+    cooldown: Duration,
+}
+
+impl HealthTracker {
+    pub fn new(count: usize, threshold: u32, cooldown: Duration) -> Self {
+        HealthTracker {
+            endpoints: Mutex::with_reset(vec![EndpointHealth::default(); count], move || {
+                vec![EndpointHealth::default(); count]
+            }),
+            threshold: threshold.max(1),
+            cooldown,
+        }
+    }
+
+    /// Whether a request may be sent to the endpoint at `index`.
+    pub fn available(&self, index: usize) -> bool {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let endpoint = &mut endpoints[index];
+        match endpoint.open_until {
+            Some(until) if Instant::now() < until => false,
+            Some(_) => {
+                // Half-open: let this caller probe, everyone else waits for its result
+                endpoint.open_until = Some(Instant::now() + self.cooldown);
+                true
+            }
+            None => true,
+        }
+    }
+
+    pub fn record_success(&self, index: usize) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        endpoints[index] = EndpointHealth::default();
+    }
+
+    pub fn record_failure(&self, index: usize) {
+        let mut endpoints = self.endpoints.lock().unwrap();
+        let endpoint = &mut endpoints[index];
+        endpoint.failures = endpoint.failures.saturating_add(1);
+        if endpoint.failures >= self.threshold {
+            endpoint.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::net::IpAddr;
+
+use libnss::host::{AddressFamily, Addresses, Host};
+use serde::Deserialize;
+
+use crate::config::CONFIG;
+use crate::protocol::NetworkReqResponse;
+use crate::pwd::request_entry;
+use crate::template::endpoint_host;
+
+pub enum HostResponse {
+    Success(Host),
+    Retry,
+    NotFound,
+    Unavail,
+}
+pub enum HostVectorResponse {
+    Success(Vec<Host>),
+    Retry,
+    NotFound,
+    Unavail,
+}
+
+/// A host as returned by the API. Addresses of both families may be mixed.
+#[derive(Deserialize)]
+struct HostEntry {
+    name: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    addresses: Vec<IpAddr>,
+    ttl: Option<u32>,
+}
+
+impl HostEntry {
+    /// The entry restricted to one address family, if it has any such address.
+    fn to_host(&self, family: &AddressFamily) -> Option<Host> {
+        let addresses = match family {
+            AddressFamily::IPv6 => Addresses::V6(
+                self.addresses
+                    .iter()
+                    .filter_map(|addr| match addr {
+                        IpAddr::V6(addr) => Some(*addr),
+                        IpAddr::V4(_) => None,
+                    })
+                    .collect(),
+            ),
+            _ => Addresses::V4(
+                self.addresses
+                    .iter()
+                    .filter_map(|addr| match addr {
+                        IpAddr::V4(addr) => Some(*addr),
+                        IpAddr::V6(_) => None,
+                    })
+                    .collect(),
+            ),
+        };
+        let empty = match &addresses {
+            Addresses::V4(addrs) => addrs.is_empty(),
+            Addresses::V6(addrs) => addrs.is_empty(),
+        };
+        if empty {
+            return None;
+        }
+
+        Some(Host {
+            name: self.name.clone(),
+            aliases: self.aliases.clone(),
+            addresses,
+            ttl: self.ttl,
+        })
+    }
+}
+
+pub fn gethostent() -> HostVectorResponse {
+    let entries: Vec<HostEntry> = match request_entry("gethostent", &[]) {
+        NetworkReqResponse::Success(hosts) => match serde_json::from_value(hosts) {
+            Ok(hosts) => hosts,
+            Err(err) => {
+                warn!("gethostent() got invalid hosts => {}", err);
+                return HostVectorResponse::NotFound;
+            }
+        },
+        NetworkReqResponse::NotFound => return HostVectorResponse::NotFound,
+        NetworkReqResponse::TimeOut => return HostVectorResponse::Retry,
+        NetworkReqResponse::Unavail => return HostVectorResponse::Unavail,
+        NetworkReqResponse::Error(err) => {
+            warn!("gethostent() got error => {}", err);
+            return HostVectorResponse::NotFound;
+        }
+    };
+
+    // A hostent only holds one family, so dual-stack hosts appear twice
+    let hosts = entries
+        .iter()
+        .flat_map(|entry| {
+            [AddressFamily::IPv4, AddressFamily::IPv6]
+                .iter()
+                .filter_map(|family| entry.to_host(family))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    HostVectorResponse::Success(hosts)
+}
+
+pub fn gethostbyname(name: &str, family: AddressFamily) -> HostResponse {
+    // Resolving the API's own host through the API would never terminate
+    if is_endpoint_host(name) {
+        return HostResponse::Unavail;
+    }
+
+    let family_param = match family {
+        AddressFamily::IPv6 => "ipv6",
+        _ => "ipv4",
+    };
+    let params = [
+        ("name", name.to_string()),
+        ("family", family_param.to_string()),
+    ];
+    match request_entry("gethostbyname", &params) {
+        NetworkReqResponse::Success(host) => host_response(host, &family, "gethostbyname", name),
+        NetworkReqResponse::NotFound => HostResponse::NotFound,
+        NetworkReqResponse::TimeOut => HostResponse::Retry,
+        NetworkReqResponse::Unavail => HostResponse::Unavail,
+        NetworkReqResponse::Error(err) => {
+            warn!("gethostbyname({}) got error => {}", name, err);
+            HostResponse::NotFound
+        }
+    }
+}
+
+pub fn gethostbyaddr(addr: IpAddr) -> HostResponse {
+    let family = match addr {
+        IpAddr::V4(_) => AddressFamily::IPv4,
+        IpAddr::V6(_) => AddressFamily::IPv6,
+    };
+    match request_entry("gethostbyaddr", &[("addr", addr.to_string())]) {
+        NetworkReqResponse::Success(host) => {
+            host_response(host, &family, "gethostbyaddr", &addr.to_string())
+        }
+        NetworkReqResponse::NotFound => HostResponse::NotFound,
+        NetworkReqResponse::TimeOut => HostResponse::Retry,
+        NetworkReqResponse::Unavail => HostResponse::Unavail,
+        NetworkReqResponse::Error(err) => {
+            warn!("gethostbyaddr({}) got error => {}", addr, err);
+            HostResponse::NotFound
+        }
+    }
+}
+
+fn host_response(
+    host: serde_json::Value,
+    family: &AddressFamily,
+    fn_name: &str,
+    key: &str,
+) -> HostResponse {
+    let entry: HostEntry = match serde_json::from_value(host) {
+        Ok(entry) => entry,
+        Err(err) => {
+            warn!("{}({}) got invalid host => {}", fn_name, key, err);
+            return HostResponse::NotFound;
+        }
+    };
+    match entry.to_host(family) {
+        Some(host) => HostResponse::Success(host),
+        None => HostResponse::NotFound,
+    }
+}
+
+fn is_endpoint_host(name: &str) -> bool {
+    CONFIG
+        .endpoints
+        .iter()
+        .filter_map(|endpoint| endpoint_host(endpoint))
+        .any(|host| host.eq_ignore_ascii_case(name.trim_end_matches('.')))
+}
@@ -0,0 +1,118 @@
+use std::ffi::CStr;
+
+#[cfg(feature = "http")]
+use hmac::{Hmac, Mac};
+#[cfg(feature = "http")]
+use sha2::Sha256;
+
+use crate::caller::Caller;
+
+/// Identity fields that can be sent along with every request.
+pub const FIELDS: &[&str] = &[
+    "uid",
+    "gid",
+    "pid",
+    "ppid",
+    "hostname",
+    "machine-id",
+    "exe",
+    "op",
+    "version",
+];
+
+const MACHINE_ID_PATH: &str = "/etc/machine-id";
+
+/// Application id the machine id is hashed with, so the API sees an id of
+/// its own rather than one shared with everything else on the machine.
+#[cfg(feature = "http")]
+const APP_ID: [u8; 16] = [
+    0x8c, 0x2b, 0x6a, 0x0f, 0x4e, 0x5d, 0x4f, 0x1c, 0x9b, 0x7a, 0x3e, 0x2d, 0x1f, 0x0c, 0x6b, 0x5a,
+];
+
+/// Describes the machine a lookup comes from, read once per client.
+#[derive(Clone, Debug, Default)]
+pub struct Host {
+    pub hostname: Option<String>,
+    /// Derived from `/etc/machine-id` by `app_specific_id`, never the raw id.
+    pub machine_id: Option<String>,
+}
+
+impl Host {
+    #[cfg(feature = "http")]
+    pub fn current() -> Host {
+        Host {
+            hostname: hostname(),
+            machine_id: std::fs::read_to_string(MACHINE_ID_PATH)
+                .ok()
+                .and_then(|id| app_specific_id(id.trim())),
+        }
+    }
+}
+
+/// The id `sd_id128_get_machine_app_specific` derives for nya from a
+/// machine id: a UUID made from the HMAC-SHA256 of the app id keyed with
+/// the machine id. Malformed machine ids have none.
+#[cfg(feature = "http")]
+pub fn app_specific_id(machine_id: &str) -> Option<String> {
+    if machine_id.len() != 32 {
+        return None;
+    }
+    let key = hex::decode(machine_id).ok()?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key).expect("HMAC accepts keys of any size");
+    mac.update(&APP_ID);
+    let mut id = [0u8; 16];
+    id.copy_from_slice(&mac.finalize().into_bytes()[..16]);
+    // Version 4, variant 1, like systemd
+    id[6] = (id[6] & 0x0f) | 0x40;
+    id[8] = (id[8] & 0x3f) | 0x80;
+    Some(hex::encode(id))
+}
+
+/// Headers describing who asks for `op`, limited to the configured `fields`.
+///
+/// Fields whose value is unknown, like the executable of a process that has
+/// already exited, are left out.
+pub fn headers(
+    fields: &[String],
+    host: &Host,
+    caller: &Caller,
+    op: &str,
+) -> Vec<(&'static str, String)> {
+    let mut headers = Vec::new();
+    for field in fields {
+        match field.as_str() {
+            "uid" => headers.push(("X-UID", caller.uid.to_string())),
+            "gid" => headers.push(("X-GID", caller.gid.to_string())),
+            "pid" => headers.push(("X-PID", caller.pid.to_string())),
+            "ppid" => headers.push(("X-PPID", caller.ppid.to_string())),
+            "hostname" => headers.extend(host.hostname.clone().map(|name| ("X-Hostname", name))),
+            "machine-id" => headers.extend(host.machine_id.clone().map(|id| ("X-Machine-Id", id))),
+            "exe" => {
+                if let Some(exe) = &caller.exe {
+                    if let Some(name) = exe.file_name() {
+                        headers.push(("X-Exe-Name", name.to_string_lossy().into_owned()));
+                    }
+                    headers.push(("X-Exe-Path", exe.to_string_lossy().into_owned()));
+                }
+            }
+            "op" => headers.push(("X-NSS-Op", op.to_string())),
+            "version" => {
+                headers.push(("X-Nya-Version", env!("CARGO_PKG_VERSION").to_string()));
+                headers.push(("X-Libnss-Version", libnss::VERSION.to_string()));
+            }
+            _ => {}
+        }
+    }
+    headers
+}
+
+fn hostname() -> Option<String> {
+    let mut buf = [0 as libc::c_char; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) } != 0 {
+        return None;
+    }
+    // Truncated names are not guaranteed to be terminated
+    buf[buf.len() - 1] = 0;
+    let name = unsafe { CStr::from_ptr(buf.as_ptr()) };
+    Some(name.to_string_lossy().into_owned()).filter(|name| !name.is_empty())
+}
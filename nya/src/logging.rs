@@ -0,0 +1,259 @@
+use std::{
+    fmt::{self, Display, Write as _},
+    fs::OpenOptions,
+    io::Write,
+    os::unix::{fs::OpenOptionsExt, net::UnixDatagram},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::Value;
+
+use crate::config::CONFIG;
+
+/// Replaces secrets and password hashes in log records.
+pub const REDACTED: &str = "<redacted>";
+
+/// Request headers whose values are never logged.
+const SENSITIVE_HEADERS: &[&str] = &["authorization", "x-nya-signature"];
+
+/// `LOG_AUTHPRIV`: records name users and must not end up in world readable logs.
+const FACILITY: u8 = 10;
+
+lazy_static! {
+    // A setuid process must not leave files behind for its caller
+    static ref LOGGER: Logger = Logger::new(CONFIG.log_level, CONFIG.log_file.clone())
+        .create(unsafe { libc::getauxval(libc::AT_SECURE) } == 0);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    /// Parses a `NSS_HTTP_API_LOG_LEVEL` value. `off` disables logging.
+    pub fn parse(value: &str) -> Option<Option<Level>> {
+        match value.to_ascii_lowercase().as_str() {
+            "off" | "none" => Some(None),
+            "error" => Some(Some(Level::Error)),
+            "warn" | "warning" => Some(Some(Level::Warn)),
+            "info" => Some(Some(Level::Info)),
+            "debug" => Some(Some(Level::Debug)),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        }
+    }
+
+    fn severity(&self) -> u8 {
+        match self {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug => 7,
+        }
+    }
+}
+
+/// The outcome of one request to one endpoint.
+pub struct Lookup<'a> {
+    pub op: &'a str,
+    pub key: &'a str,
+    pub endpoint: &'a str,
+    pub latency: Duration,
+    pub outcome: &'a str,
+}
+
+/// Writes `key=value` records to syslog, or to `file` when one is configured.
+///
+/// Nothing is ever written to stdout or stderr: the module runs inside
+/// arbitrary processes whose output may be a protocol stream.
+pub struct Logger {
+    level: Option<Level>,
+    file: Option<PathBuf>,
+    create: bool,
+}
+
+impl Logger {
+    pub fn new(level: Option<Level>, file: Option<PathBuf>) -> Self {
+        Logger {
+            level,
+            file,
+            create: true,
+        }
+    }
+
+    /// Whether a missing log file is created. A symlink is never followed.
+    pub fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
+    pub fn enabled(&self, level: Level) -> bool {
+        self.level.is_some_and(|max| level <= max)
+    }
+
+    pub fn log(&self, level: Level, fields: &[(&str, &dyn Display)]) {
+        if !self.enabled(level) {
+            return;
+        }
+        let record = format_record(level, fields);
+
+        match &self.file {
+            Some(path) => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                let line = format!(
+                    "{}.{:03} nss_nya[{}]: {}\n",
+                    now.as_secs(),
+                    now.subsec_millis(),
+                    std::process::id(),
+                    record
+                );
+                let file = OpenOptions::new()
+                    .append(true)
+                    .create(self.create)
+                    .mode(0o640)
+                    .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+                    .open(path);
+                if let Ok(mut file) = file {
+                    let _ = file.write_all(line.as_bytes());
+                }
+            }
+            None => {
+                let message = format!(
+                    "<{}>nss_nya[{}]: {}",
+                    FACILITY * 8 + level.severity(),
+                    std::process::id(),
+                    record
+                );
+                if let Ok(socket) = UnixDatagram::unbound() {
+                    let _ = socket.send_to(message.as_bytes(), "/dev/log");
+                }
+            }
+        }
+    }
+
+    /// Records a request: failures as warnings, answers at debug level.
+    pub fn lookup(&self, lookup: &Lookup) {
+        let level = match lookup.outcome {
+            "success" | "notfound" => Level::Debug,
+            _ => Level::Warn,
+        };
+        self.log(
+            level,
+            &[
+                ("op", &lookup.op),
+                ("key", &lookup.key),
+                ("endpoint", &lookup.endpoint),
+                ("latency_ms", &lookup.latency.as_millis()),
+                ("outcome", &lookup.outcome),
+            ],
+        );
+    }
+}
+
+pub fn enabled(level: Level) -> bool {
+    LOGGER.enabled(level)
+}
+
+pub fn log(level: Level, fields: &[(&str, &dyn Display)]) {
+    LOGGER.log(level, fields)
+}
+
+pub fn message(level: Level, args: fmt::Arguments) {
+    LOGGER.log(level, &[("msg", &args)])
+}
+
+pub fn lookup(lookup: &Lookup) {
+    LOGGER.lookup(lookup)
+}
+
+/// Formats `fields` as `level=<level> key=value ...`, quoting values that
+/// contain spaces, quotes or control characters.
+pub fn format_record(level: Level, fields: &[(&str, &dyn Display)]) -> String {
+    let mut record = format!("level={}", level.as_str());
+    for (key, value) in fields {
+        let value = value.to_string();
+        let plain = !value.is_empty()
+            && !value
+                .chars()
+                .any(|c| c.is_whitespace() || c.is_control() || c == '"' || c == '=');
+        let _ = match plain {
+            true => write!(record, " {}={}", key, value),
+            false => write!(record, " {}={:?}", key, value),
+        };
+    }
+    record
+}
+
+/// The value of a request header as it may appear in a log.
+pub fn redact_header<'a>(name: &str, value: &'a str) -> &'a str {
+    match SENSITIVE_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+        true => REDACTED,
+        false => value,
+    }
+}
+
+/// A copy of an API answer with every password field blanked out.
+pub fn redact_entry(entry: &Value) -> Value {
+    match entry {
+        Value::Array(entries) => Value::Array(entries.iter().map(redact_entry).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| match key.as_str() {
+                    "passwd" => (key.clone(), Value::String(REDACTED.to_string())),
+                    _ => (key.clone(), redact_entry(value)),
+                })
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+// Only used by the HTTP client and the daemon
+#[allow(unused_macros)]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::Level::Error) {
+            $crate::logging::message($crate::logging::Level::Error, format_args!($($arg)*))
+        }
+    };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::Level::Warn) {
+            $crate::logging::message($crate::logging::Level::Warn, format_args!($($arg)*))
+        }
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::Level::Info) {
+            $crate::logging::message($crate::logging::Level::Info, format_args!($($arg)*))
+        }
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::logging::enabled($crate::logging::Level::Debug) {
+            $crate::logging::message($crate::logging::Level::Debug, format_args!($($arg)*))
+        }
+    };
+}
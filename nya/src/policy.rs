@@ -0,0 +1,167 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+use libnss::{fork::Mutex, group::Group, passwd::Passwd, shadow::Shadow};
+
+use crate::config::Config;
+
+/// Rules every remote entry has to satisfy before it is handed to glibc.
+///
+/// A compromised or misconfigured API must not be able to hand out uid 0,
+/// take over a local account or point a user at an arbitrary shell.
+pub struct Policy {
+    uid_ranges: Vec<(u32, u32)>,
+    gid_ranges: Vec<(u32, u32)>,
+    reserved_names: Vec<String>,
+    allowed_shells: Vec<String>,
+    home_prefixes: Vec<PathBuf>,
+    local_passwd: Local,
+    local_group: Local,
+}
+
+impl Policy {
+    pub fn new(config: &Config) -> Self {
+        Policy {
+            uid_ranges: config.uid_ranges.clone(),
+            gid_ranges: config.gid_ranges.clone(),
+            reserved_names: config.reserved_names.clone(),
+            allowed_shells: config.allowed_shells.clone(),
+            home_prefixes: config.home_prefixes.iter().map(PathBuf::from).collect(),
+            local_passwd: Local::new(config.local_passwd.clone()),
+            local_group: Local::new(config.local_group.clone()),
+        }
+    }
+
+    pub fn check_passwd(&self, passwd: &Passwd) -> Result<(), String> {
+        self.check_name(&passwd.name)?;
+        if !in_ranges(&self.uid_ranges, passwd.uid) {
+            return Err(format!("uid {} is outside the allowed ranges", passwd.uid));
+        }
+        if !in_ranges(&self.gid_ranges, passwd.gid) {
+            return Err(format!("gid {} is outside the allowed ranges", passwd.gid));
+        }
+        if self.local_passwd.contains(&passwd.name, passwd.uid) {
+            return Err(format!(
+                "{} (uid {}) would shadow a local account",
+                passwd.name, passwd.uid
+            ));
+        }
+        if !self.allowed_shells.is_empty() && !self.allowed_shells.contains(&passwd.shell) {
+            return Err(format!("shell {} is not allowed", passwd.shell));
+        }
+        if !self.home_prefixes.is_empty() && !self.home_allowed(&passwd.dir) {
+            return Err(format!("home directory {} is not allowed", passwd.dir));
+        }
+        Ok(())
+    }
+
+    pub fn check_group(&self, group: &Group) -> Result<(), String> {
+        self.check_name(&group.name)?;
+        if !in_ranges(&self.gid_ranges, group.gid) {
+            return Err(format!("gid {} is outside the allowed ranges", group.gid));
+        }
+        if self.local_group.contains(&group.name, group.gid) {
+            return Err(format!(
+                "{} (gid {}) would shadow a local group",
+                group.name, group.gid
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn check_shadow(&self, shadow: &Shadow) -> Result<(), String> {
+        self.check_name(&shadow.name)?;
+        if self.local_passwd.contains_name(&shadow.name) {
+            return Err(format!("{} would shadow a local account", shadow.name));
+        }
+        Ok(())
+    }
+
+    fn check_name(&self, name: &str) -> Result<(), String> {
+        if self.reserved_names.iter().any(|reserved| reserved == name) {
+            return Err(format!("{} is a reserved name", name));
+        }
+        Ok(())
+    }
+
+    fn home_allowed(&self, dir: &str) -> bool {
+        let dir = Path::new(dir);
+        // Prefix checks mean nothing once `..` is involved
+        if !dir.is_absolute() || dir.components().any(|c| c == Component::ParentDir) {
+            return false;
+        }
+        self.home_prefixes
+            .iter()
+            .any(|prefix| dir.starts_with(prefix))
+    }
+}
+
+fn in_ranges(ranges: &[(u32, u32)], id: u32) -> bool {
+    ranges
+        .iter()
+        .any(|(start, end)| (*start..=*end).contains(&id))
+}
+
+/// Names and ids of a local database, re-read whenever the file changes.
+struct Local {
+    path: PathBuf,
+    cached: Mutex<Option<LocalEntries>>,
+}
+
+struct LocalEntries {
+    modified: SystemTime,
+    names: HashSet<String>,
+    ids: HashSet<u32>,
+}
+
+impl Local {
+    fn new(path: PathBuf) -> Self {
+        Local {
+            path,
+            cached: Mutex::new(None),
+        }
+    }
+
+    fn contains(&self, name: &str, id: u32) -> bool {
+        self.with(|names, ids| names.contains(name) || ids.contains(&id))
+    }
+
+    fn contains_name(&self, name: &str) -> bool {
+        self.with(|names, _| names.contains(name))
+    }
+
+    fn with<F: FnOnce(&HashSet<String>, &HashSet<u32>) -> bool>(&self, f: F) -> bool {
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        let mut cached = self.cached.lock().unwrap();
+        let stale = match (&*cached, modified) {
+            (Some(entries), Some(modified)) => entries.modified != modified,
+            _ => true,
+        };
+        if stale {
+            let contents = fs::read_to_string(&self.path).unwrap_or_default();
+            let mut names = HashSet::new();
+            let mut ids = HashSet::new();
+            for line in contents.lines().filter(|line| !line.starts_with('#')) {
+                let mut fields = line.split(':');
+                if let Some(name) = fields.next().filter(|name| !name.is_empty()) {
+                    names.insert(name.to_string());
+                }
+                if let Some(id) = fields.nth(1).and_then(|id| id.parse().ok()) {
+                    ids.insert(id);
+                }
+            }
+            *cached = Some(LocalEntries {
+                modified: modified.unwrap_or(SystemTime::UNIX_EPOCH),
+                names,
+                ids,
+            });
+        }
+
+        let entries = cached.as_ref().unwrap();
+        f(&entries.names, &entries.ids)
+    }
+}
@@ -0,0 +1,87 @@
+use std::path::Path;
+
+use libc::uid_t;
+
+use crate::caller::Caller;
+use crate::config::Config;
+
+/// Decides which processes may see shadow entries.
+///
+/// A caller needs an allowed effective uid or one of the allowed effective
+/// capabilities. When an executable allowlist is configured the caller must
+/// also be one of those programs; the allowlist never grants access on its
+/// own, since anyone can name a binary `sshd`.
+pub struct ShadowAccess {
+    uids: Vec<uid_t>,
+    capabilities: Vec<u32>,
+    executables: Vec<String>,
+}
+
+impl ShadowAccess {
+    pub fn new(config: &Config) -> Self {
+        ShadowAccess {
+            uids: config.shadow_uids.clone(),
+            capabilities: config
+                .shadow_capabilities
+                .iter()
+                .filter_map(|name| capability(name))
+                .collect(),
+            executables: config.shadow_executables.clone(),
+        }
+    }
+
+    pub fn check(&self, caller: &Caller) -> Result<(), String> {
+        let privileged = self.uids.contains(&caller.euid)
+            || self
+                .capabilities
+                .iter()
+                .any(|cap| caller.cap_eff & (1 << cap) != 0);
+        if !privileged {
+            return Err(format!(
+                "euid {} has neither an allowed uid nor an allowed capability",
+                caller.euid
+            ));
+        }
+
+        if self.executables.is_empty() {
+            return Ok(());
+        }
+        let exe = match &caller.exe {
+            Some(exe) => exe,
+            None => return Err("executable is unknown".to_string()),
+        };
+        if self
+            .executables
+            .iter()
+            .any(|allowed| matches_exe(allowed, exe))
+        {
+            return Ok(());
+        }
+        Err(format!("{} is not an allowed executable", exe.display()))
+    }
+}
+
+/// Absolute entries match the full path, anything else the file name.
+fn matches_exe(allowed: &str, exe: &Path) -> bool {
+    match allowed.starts_with('/') {
+        true => exe == Path::new(allowed),
+        false => exe.file_name().is_some_and(|name| name == allowed),
+    }
+}
+
+/// Bit number of a capability given by name (`CAP_DAC_READ_SEARCH`) or number.
+pub fn capability(name: &str) -> Option<u32> {
+    let name = name.trim().to_ascii_uppercase();
+    let bit = match name.trim_start_matches("CAP_") {
+        "CHOWN" => 0,
+        "DAC_OVERRIDE" => 1,
+        "DAC_READ_SEARCH" => 2,
+        "FOWNER" => 3,
+        "SETGID" => 6,
+        "SETUID" => 7,
+        "SYS_ADMIN" => 21,
+        "AUDIT_WRITE" => 29,
+        other => other.parse().ok().filter(|bit| *bit < 64)?,
+    };
+    Some(bit)
+}
@@ -0,0 +1,221 @@
+use std::{
+    io::{self, Read, Write},
+    mem,
+    os::unix::{
+        ffi::OsStrExt,
+        io::{AsRawFd, FromRawFd},
+        net::UnixStream,
+    },
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+/// Where `nya-daemon` listens unless configured otherwise.
+pub const DEFAULT_SOCKET_PATH: &str = "/run/nya/nya.sock";
+
+/// Frames larger than this are rejected instead of being buffered.
+const MAX_FRAME: usize = 16 * 1024 * 1024;
+
+/// How often a connect is retried while the daemon's backlog is full.
+const CONNECT_RETRY: Duration = Duration::from_millis(10);
+
+/// Outcome of a lookup, whether it was answered over HTTP or by the daemon.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", content = "data", rename_all = "lowercase")]
+pub enum NetworkReqResponse {
+    Success(Value),
+    NotFound,
+    Error(String),
+    TimeOut,
+    /// No endpoint could be reached.
+    Unavail,
+}
+
+/// A lookup forwarded from the NSS module to the daemon.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    pub op: String,
+    pub params: Vec<(String, String)>,
+}
+
+/// Writes a 4 byte big-endian length followed by the JSON encoded `message`.
+pub fn write_frame<W: Write, T: Serialize>(writer: &mut W, message: &T) -> io::Result<()> {
+    let payload = serde_json::to_vec(message)?;
+    if payload.len() > MAX_FRAME {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame too large",
+        ));
+    }
+    writer.write_all(&(payload.len() as u32).to_be_bytes())?;
+    writer.write_all(&payload)?;
+    writer.flush()
+}
+
+pub fn read_frame<R: Read, T: DeserializeOwned>(reader: &mut R) -> io::Result<T> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame too large",
+        ));
+    }
+
+    // Grow with what arrives rather than trusting the announced length
+    let mut payload = Vec::new();
+    reader.take(len as u64).read_to_end(&mut payload)?;
+    if payload.len() < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated frame",
+        ));
+    }
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+/// A stream whose reads and writes all have to be done by one deadline,
+/// however the peer paces its bytes.
+pub struct Deadline<'a> {
+    stream: &'a UnixStream,
+    until: Instant,
+}
+
+impl<'a> Deadline<'a> {
+    pub fn new(stream: &'a UnixStream, until: Instant) -> Self {
+        Deadline { stream, until }
+    }
+
+    /// Sets the socket timeouts to what is left of the deadline.
+    fn arm(&self) -> io::Result<()> {
+        let left = remaining(self.until)?;
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.set_write_timeout(Some(left))
+    }
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.arm()?;
+        self.stream.read(buf)
+    }
+}
+
+impl Write for Deadline<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.arm()?;
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// The time left until `until`, or `TimedOut` once it passed.
+fn remaining(until: Instant) -> io::Result<Duration> {
+    match until.checked_duration_since(Instant::now()) {
+        Some(left) if !left.is_zero() => Ok(left),
+        _ => Err(io::Error::new(io::ErrorKind::TimedOut, "deadline exceeded")),
+    }
+}
+
+/// Connects to the daemon at `socket` without waiting past `until`.
+///
+/// A blocking connect waits for as long as the daemon's backlog is full, so
+/// the socket is non-blocking while connecting and the connect is retried
+/// until there is room or time is up.
+fn connect(socket: &Path, until: Instant) -> io::Result<UnixStream> {
+    let path = socket.as_os_str().as_bytes();
+    let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+    if path.len() >= addr.sun_path.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "socket path too long",
+        ));
+    }
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    for (dst, src) in addr.sun_path.iter_mut().zip(path) {
+        *dst = *src as libc::c_char;
+    }
+
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_UNIX,
+            libc::SOCK_STREAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            0,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let stream = unsafe { UnixStream::from_raw_fd(fd) };
+
+    loop {
+        let ret = unsafe {
+            libc::connect(
+                stream.as_raw_fd(),
+                &addr as *const libc::sockaddr_un as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_un>() as libc::socklen_t,
+            )
+        };
+        if ret == 0 {
+            break;
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            // Unix sockets connect at once or report a full backlog
+            Some(libc::EAGAIN) => thread::sleep(remaining(until)?.min(CONNECT_RETRY)),
+            Some(libc::EINTR) => {}
+            _ => return Err(err),
+        }
+    }
+    stream.set_nonblocking(false)?;
+    Ok(stream)
+}
+
+/// Sends one lookup to the daemon listening on `socket`, connecting and
+/// exchanging it within `timeout`.
+///
+/// A daemon that cannot be reached or answers garbage is reported as
+/// `Unavail`, a daemon that does not answer in time as `TimeOut`.
+pub fn request(
+    socket: &Path,
+    timeout: Duration,
+    fn_name: &str,
+    params: &[(&str, String)],
+) -> NetworkReqResponse {
+    let request = Request {
+        op: fn_name.to_string(),
+        params: params
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect(),
+    };
+
+    let exchange = || -> io::Result<NetworkReqResponse> {
+        let until = Instant::now() + timeout;
+        let stream = connect(socket, until)?;
+        let mut stream = Deadline::new(&stream, until);
+        write_frame(&mut stream, &request)?;
+        read_frame(&mut stream)
+    };
+
+    match exchange() {
+        Ok(response) => response,
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            NetworkReqResponse::TimeOut
+        }
+        Err(_) => NetworkReqResponse::Unavail,
+    }
+}
@@ -1,340 +1,357 @@
-use std::{collections::HashMap, env, fs, os::unix::prelude::PermissionsExt, time::Duration};
+use std::{env, ptr, time::Duration};
 
-use debug::debug;
-use libc::{getgid, getpid, getppid, getuid, gid_t, uid_t};
-use libnss::{group::Group, passwd::Passwd, shadow::Shadow};
-use reqwest::header::{HeaderMap, HeaderValue};
-use serde_json::Value;
+use libc::{gid_t, uid_t};
+use libnss::group::{CGroup, Group};
+use libnss::init::Trigger;
+use libnss::interop::ToC;
+use libnss::passwd::{CPasswd, Passwd};
+use libnss::shadow::{CShadow, Shadow};
+
+use crate::caller::Caller;
+use crate::config::CONFIG;
+use crate::logging::{self, Level};
+use crate::policy::Policy;
+use crate::privilege::ShadowAccess;
+use crate::protocol::{self, NetworkReqResponse};
+#[cfg(feature = "http")]
+use crate::resolver::Resolver;
+use crate::snapshot::Snapshot;
 pub enum PasswdResponse {
     Success(Passwd),
     Retry,
     NotFound,
+    Unavail,
 }
 pub enum PasswdVectorResponse {
     Success(Vec<Passwd>),
     Retry,
     NotFound,
+    Unavail,
 }
 pub enum GroupResponse {
     Success(Group),
     Retry,
     NotFound,
+    Unavail,
 }
 pub enum GroupVectorResponse {
     Success(Vec<Group>),
     Retry,
     NotFound,
+    Unavail,
 }
 
 pub enum ShadowResponse {
     Success(Shadow),
     Retry,
     NotFound,
+    Unavail,
 }
 pub enum ShadowVectorResponse {
     Success(Vec<Shadow>),
     Retry,
     NotFound,
+    Unavail,
 }
-pub enum NetworkReqResponse {
-    Success(Value),
-    NotFound,
-    Error(String),
-    TimeOut,
+
+lazy_static! {
+    /// Fallback for lookups the daemon could not answer.
+    static ref SNAPSHOT: Option<Snapshot> = Snapshot::new(&CONFIG);
+    static ref POLICY: Policy = Policy::new(&CONFIG);
+    static ref SHADOW_ACCESS: ShadowAccess = ShadowAccess::new(&CONFIG);
 }
 
+#[cfg(feature = "http")]
 lazy_static! {
-    static ref HTTP_API_ENDPOINT: Option<String> = {
-        match env::var("NSS_HTTP_API_ENDPOINT") {
-            Ok(val) => Some(val),
-            Err(_) => None,
-        }
-    };
-    static ref NSS_HTTP_API_DEBUG: bool = {
-        env::var("NSS_HTTP_API_DEBUG")
-            .unwrap_or(false.to_string())
-            .parse::<bool>()
-            .unwrap()
-    };
-    static ref NSS_HTTP_API_REQUEST_TIMEOUT: u64 = {
-        env::var("NSS_HTTP_API_REQUEST_TIMEOUT")
-            .unwrap_or(30.to_string())
-            .parse::<u64>()
-            .unwrap()
-    };
+    static ref RESOLVER: Resolver = Resolver::new(CONFIG.clone());
+}
+
+/// The snapshot files nscd should trace, if there is a snapshot.
+pub fn triggers() -> Vec<Trigger> {
+    SNAPSHOT
+        .as_ref()
+        .map_or_else(Vec::new, |snapshot| snapshot.triggers())
 }
 
 pub fn getpwent() -> PasswdVectorResponse {
-    let passwd = match request_entry(
-        "passwd".to_string(),
-        Option::None,
-        Option::None,
-        "getpwent".to_string(),
-    ) {
-        NetworkReqResponse::Success(passwd) => serde_json::from_value(passwd).unwrap(),
+    let passwd: Vec<Passwd> = match request_entry("getpwent", &[]) {
+        NetworkReqResponse::Success(passwd) => match serde_json::from_value(passwd) {
+            Ok(passwd) => passwd,
+            Err(err) => {
+                warn!("getpwent() got invalid entries => {}", err);
+                return PasswdVectorResponse::NotFound;
+            }
+        },
         NetworkReqResponse::NotFound => return PasswdVectorResponse::NotFound,
         NetworkReqResponse::TimeOut => return PasswdVectorResponse::Retry,
+        NetworkReqResponse::Unavail => return PasswdVectorResponse::Unavail,
         NetworkReqResponse::Error(err) => {
-            debug!("getpwent() got error => {}", err);
+            warn!("getpwent() got error => {}", err);
             return PasswdVectorResponse::NotFound;
         }
     };
+    let passwd = passwd
+        .into_iter()
+        .filter(|entry| permitted("getpwent", &entry.name, POLICY.check_passwd(entry)))
+        .collect();
     PasswdVectorResponse::Success(passwd)
 }
 
 pub fn getpwuid(uid: uid_t) -> PasswdResponse {
-    let passwd = match request_entry(
-        "passwd".to_string(),
-        Some(String::from("uid")),
-        Some(uid.to_string()),
-        "getpwuid".to_string(),
-    ) {
-        NetworkReqResponse::Success(passwd) => serde_json::from_value(passwd).unwrap(),
+    let passwd: Passwd = match request_entry("getpwuid", &[("uid", uid.to_string())]) {
+        NetworkReqResponse::Success(passwd) => match serde_json::from_value(passwd) {
+            Ok(passwd) => passwd,
+            Err(err) => {
+                warn!("getpwuid({}) got invalid entry => {}", uid, err);
+                return PasswdResponse::NotFound;
+            }
+        },
         NetworkReqResponse::NotFound => return PasswdResponse::NotFound,
         NetworkReqResponse::TimeOut => return PasswdResponse::Retry,
+        NetworkReqResponse::Unavail => return PasswdResponse::Unavail,
         NetworkReqResponse::Error(err) => {
-            debug!("getpwuid({}) got error => {}", uid, err);
+            warn!("getpwuid({}) got error => {}", uid, err);
             return PasswdResponse::NotFound;
         }
     };
-    return PasswdResponse::Success(passwd);
+    if !permitted("getpwuid", &passwd.name, POLICY.check_passwd(&passwd)) {
+        return PasswdResponse::NotFound;
+    }
+    log_size::<CPasswd, _>("getpwuid", &passwd.name, &passwd);
+    PasswdResponse::Success(passwd)
 }
 
 pub fn getpwnam(name: String) -> PasswdResponse {
-    let passwd = match request_entry(
-        "passwd".to_string(),
-        Some(String::from("name")),
-        Some(name.clone()),
-        "getpwnam".to_string(),
-    ) {
-        NetworkReqResponse::Success(passwd) => serde_json::from_value(passwd).unwrap(),
+    let passwd: Passwd = match request_entry("getpwnam", &[("name", name.clone())]) {
+        NetworkReqResponse::Success(passwd) => match serde_json::from_value(passwd) {
+            Ok(passwd) => passwd,
+            Err(err) => {
+                warn!("getpwnam({}) got invalid entry => {}", name, err);
+                return PasswdResponse::NotFound;
+            }
+        },
         NetworkReqResponse::NotFound => return PasswdResponse::NotFound,
         NetworkReqResponse::TimeOut => return PasswdResponse::Retry,
+        NetworkReqResponse::Unavail => return PasswdResponse::Unavail,
         NetworkReqResponse::Error(err) => {
-            debug!("getpwnam({}) got error => {}", name, err);
+            warn!("getpwnam({}) got error => {}", name, err);
             return PasswdResponse::NotFound;
         }
     };
-    return PasswdResponse::Success(passwd);
+    if !permitted("getpwnam", &passwd.name, POLICY.check_passwd(&passwd)) {
+        return PasswdResponse::NotFound;
+    }
+    log_size::<CPasswd, _>("getpwnam", &passwd.name, &passwd);
+    PasswdResponse::Success(passwd)
 }
 
 pub fn getgrent() -> GroupVectorResponse {
-    let group = match request_entry(
-        "group".to_string(),
-        Option::None,
-        Option::None,
-        "getgrent".to_string(),
-    ) {
-        NetworkReqResponse::Success(group) => serde_json::from_value(group).unwrap(),
+    let group: Vec<Group> = match request_entry("getgrent", &[]) {
+        NetworkReqResponse::Success(group) => match serde_json::from_value(group) {
+            Ok(group) => group,
+            Err(err) => {
+                warn!("getgrent() got invalid entries => {}", err);
+                return GroupVectorResponse::NotFound;
+            }
+        },
         NetworkReqResponse::NotFound => return GroupVectorResponse::NotFound,
         NetworkReqResponse::TimeOut => return GroupVectorResponse::Retry,
+        NetworkReqResponse::Unavail => return GroupVectorResponse::Unavail,
         NetworkReqResponse::Error(err) => {
-            debug!("getgrent() got error => {}", err);
+            warn!("getgrent() got error => {}", err);
             return GroupVectorResponse::NotFound;
         }
     };
+    let group = group
+        .into_iter()
+        .filter(|entry| permitted("getgrent", &entry.name, POLICY.check_group(entry)))
+        .collect();
     GroupVectorResponse::Success(group)
 }
 
 pub fn getgrgid(gid: gid_t) -> GroupResponse {
-    let group = match request_entry(
-        "group".to_string(),
-        Some(String::from("gid")),
-        Some(gid.to_string()),
-        "getgrgid".to_string(),
-    ) {
-        NetworkReqResponse::Success(group) => serde_json::from_value(group).unwrap(),
+    let group: Group = match request_entry("getgrgid", &[("gid", gid.to_string())]) {
+        NetworkReqResponse::Success(group) => match serde_json::from_value(group) {
+            Ok(group) => group,
+            Err(err) => {
+                warn!("getgrgid({}) got invalid entry => {}", gid, err);
+                return GroupResponse::NotFound;
+            }
+        },
         NetworkReqResponse::NotFound => return GroupResponse::NotFound,
         NetworkReqResponse::TimeOut => return GroupResponse::Retry,
+        NetworkReqResponse::Unavail => return GroupResponse::Unavail,
         NetworkReqResponse::Error(err) => {
-            debug!("getgrgid({}) got error => {}", gid, err);
+            warn!("getgrgid({}) got error => {}", gid, err);
             return GroupResponse::NotFound;
         }
     };
+    if !permitted("getgrgid", &group.name, POLICY.check_group(&group)) {
+        return GroupResponse::NotFound;
+    }
+    log_size::<CGroup, _>("getgrgid", &group.name, &group);
     GroupResponse::Success(group)
 }
 
 pub fn getgrnam(name: String) -> GroupResponse {
-    let group = match request_entry(
-        "group".to_string(),
-        Some(String::from("name")),
-        Some(name.clone()),
-        "getgrnam".to_string(),
-    ) {
-        NetworkReqResponse::Success(group) => serde_json::from_value(group).unwrap(),
+    let group: Group = match request_entry("getgrnam", &[("name", name.clone())]) {
+        NetworkReqResponse::Success(group) => match serde_json::from_value(group) {
+            Ok(group) => group,
+            Err(err) => {
+                warn!("getgrnam({}) got invalid entry => {}", name, err);
+                return GroupResponse::NotFound;
+            }
+        },
         NetworkReqResponse::NotFound => return GroupResponse::NotFound,
         NetworkReqResponse::TimeOut => return GroupResponse::Retry,
+        NetworkReqResponse::Unavail => return GroupResponse::Unavail,
         NetworkReqResponse::Error(err) => {
-            debug!("getgrnam({}) got error => {}", name, err);
+            warn!("getgrnam({}) got error => {}", name, err);
             return GroupResponse::NotFound;
         }
     };
+    if !permitted("getgrnam", &group.name, POLICY.check_group(&group)) {
+        return GroupResponse::NotFound;
+    }
+    log_size::<CGroup, _>("getgrnam", &group.name, &group);
     GroupResponse::Success(group)
 }
-pub fn check_suid_bit() -> bool {
-    match fs::read_link("/proc/self/exe") {
-        Ok(path) => {
-            debug!(
-                "check_suid_bit() real executable file path => {}",
-                path.display()
-            );
-            let metadata = match fs::metadata(path) {
-                Ok(metadata) => metadata,
-                Err(err) => {
-                    debug!("check_suid_bit() got metadata error => {}", err);
-                    return false;
-                }
-            };
-            let permissions = metadata.permissions().mode();
-            let suid_bit = permissions & 0o4000 != 0;
-            debug!(
-                "check_suid_bit() permissions => {:o}, {}",
-                permissions,
-                if suid_bit {
-                    "suid bit is set"
-                } else {
-                    "suid bit is not set"
-                }
-            );
-            suid_bit
+/// Every group `name` is a supplementary member of.
+pub fn initgroups(name: String) -> GroupVectorResponse {
+    let groups: Vec<Group> = match request_entry("initgroups", &[("name", name.clone())]) {
+        NetworkReqResponse::Success(groups) => match serde_json::from_value(groups) {
+            Ok(groups) => groups,
+            Err(err) => {
+                warn!("initgroups({}) got invalid entries => {}", name, err);
+                return GroupVectorResponse::NotFound;
+            }
+        },
+        NetworkReqResponse::NotFound => return GroupVectorResponse::NotFound,
+        NetworkReqResponse::TimeOut => return GroupVectorResponse::Retry,
+        NetworkReqResponse::Unavail => return GroupVectorResponse::Unavail,
+        NetworkReqResponse::Error(err) => {
+            warn!("initgroups({}) got error => {}", name, err);
+            return GroupVectorResponse::NotFound;
         }
-        Err(err) => {
-            debug!("check_suid_bit() got readlink error => {}", err);
+    };
+    let groups = groups
+        .into_iter()
+        .filter(|entry| permitted("initgroups", &entry.name, POLICY.check_group(entry)))
+        .collect();
+    GroupVectorResponse::Success(groups)
+}
+/// Whether the current process may see shadow entries.
+fn shadow_allowed(fn_name: &str) -> bool {
+    match SHADOW_ACCESS.check(&Caller::current()) {
+        Ok(()) => true,
+        Err(reason) => {
+            info!("{} refused => {}", fn_name, reason);
             false
         }
     }
 }
+
 pub fn getspent() -> ShadowVectorResponse {
-    if !check_suid_bit() {
+    if !shadow_allowed("getspent") {
         return ShadowVectorResponse::NotFound;
     }
-    let shadow = match request_entry(
-        "shadow".to_string(),
-        Option::None,
-        Option::None,
-        "getspent".to_string(),
-    ) {
-        NetworkReqResponse::Success(shadow) => serde_json::from_value(shadow).unwrap(),
+    let shadow: Vec<Shadow> = match request_entry("getspent", &[]) {
+        NetworkReqResponse::Success(shadow) => match serde_json::from_value(shadow) {
+            Ok(shadow) => shadow,
+            Err(err) => {
+                warn!("getspent() got invalid entries => {}", err);
+                return ShadowVectorResponse::NotFound;
+            }
+        },
         NetworkReqResponse::NotFound => return ShadowVectorResponse::NotFound,
         NetworkReqResponse::TimeOut => return ShadowVectorResponse::Retry,
+        NetworkReqResponse::Unavail => return ShadowVectorResponse::Unavail,
         NetworkReqResponse::Error(err) => {
-            debug!("getspent() got error => {}", err);
+            warn!("getspent() got error => {}", err);
             return ShadowVectorResponse::NotFound;
         }
     };
+    let shadow = shadow
+        .into_iter()
+        .filter(|entry| permitted("getspent", &entry.name, POLICY.check_shadow(entry)))
+        .collect();
     ShadowVectorResponse::Success(shadow)
 }
 
 pub fn getspnam(name: String) -> ShadowResponse {
-    if !check_suid_bit() {
+    if !shadow_allowed("getspnam") {
         return ShadowResponse::NotFound;
     }
-    let shadow = match request_entry(
-        "shadow".to_string(),
-        Some(String::from("name")),
-        Some(name.clone()),
-        "getspnam".to_string(),
-    ) {
-        NetworkReqResponse::Success(shadow) => serde_json::from_value(shadow).unwrap(),
+    let shadow: Shadow = match request_entry("getspnam", &[("name", name.clone())]) {
+        NetworkReqResponse::Success(shadow) => match serde_json::from_value(shadow) {
+            Ok(shadow) => shadow,
+            Err(err) => {
+                warn!("getspnam({}) got invalid entry => {}", name, err);
+                return ShadowResponse::NotFound;
+            }
+        },
         NetworkReqResponse::NotFound => return ShadowResponse::NotFound,
         NetworkReqResponse::TimeOut => return ShadowResponse::Retry,
+        NetworkReqResponse::Unavail => return ShadowResponse::Unavail,
         NetworkReqResponse::Error(err) => {
-            debug!("getspnam({}) got error => {}", name, err);
+            warn!("getspnam({}) got error => {}", name, err);
             return ShadowResponse::NotFound;
         }
     };
+    if !permitted("getspnam", &shadow.name, POLICY.check_shadow(&shadow)) {
+        return ShadowResponse::NotFound;
+    }
+    log_size::<CShadow, _>("getspnam", &shadow.name, &shadow);
     ShadowResponse::Success(shadow)
 }
 
-fn request_entry(
-    file: String,
-    key: Option<String>,
-    value: Option<String>,
-    fn_name: String,
-) -> NetworkReqResponse {
-    let api_url = match &*HTTP_API_ENDPOINT {
-        Some(api_url) => api_url,
-        None => {
-            debug!(
-                "{}({}) got error => {}",
-                fn_name,
-                value.unwrap_or(String::from("")),
-                "environment variable NSS_HTTP_API_ENDPOINT is not set"
-            );
-            return NetworkReqResponse::NotFound;
+/// Whether `name` passed the policy, logging it when it did not.
+fn permitted(fn_name: &str, name: &str, verdict: Result<(), String>) -> bool {
+    match verdict {
+        Ok(()) => true,
+        Err(reason) => {
+            warn!("{} rejected {} => {}", fn_name, name, reason);
+            false
         }
-    };
-    let info = unsafe {
-        let mut map = HashMap::new();
-        map.insert("uid", getuid().to_string());
-        map.insert("gid", getgid().to_string());
-        map.insert("pid", getpid().to_string());
-        map.insert("ppid", getppid().to_string());
-        map
-    };
+    }
+}
 
-    let client = reqwest::blocking::Client::builder()
-        .timeout(Duration::from_secs(*NSS_HTTP_API_REQUEST_TIMEOUT))
-        .default_headers({
-            let mut headers = HeaderMap::new();
-            headers.insert(
-                "X-UID",
-                HeaderValue::from_str(&info["uid"]).expect("failed to set X-UID header"),
-            );
-            headers.insert(
-                "X-GID",
-                HeaderValue::from_str(&info["gid"]).expect("failed to set X-GID header"),
-            );
-            headers.insert(
-                "X-PID",
-                HeaderValue::from_str(&info["pid"]).expect("failed to set X-PID header"),
-            );
-            headers.insert(
-                "X-PPID",
-                HeaderValue::from_str(&info["ppid"]).expect("failed to set X-PPID header"),
-            );
-            debug!("request headers => {:?}", headers);
-            headers
-        })
-        .build()
-        .unwrap();
-    let value = match value {
-        Some(value) => value,
-        None => "".to_string(),
-    };
-    let url = match key {
-        Some(key) => format!("{}/{}?{}={}", api_url, file, key, value),
-        None => format!("{}/{}", api_url, file),
-    };
-    debug!("requesting url => {}", url);
-    let response = match client.get(&url).send() {
-        Ok(client) => client,
-        Err(err) => {
-            if err.is_timeout() {
-                debug!("{}({}) got timeout error => {:?}", fn_name, value, err);
-                return NetworkReqResponse::TimeOut;
+/// Logs the buffer `entry` needs, as glibc retries every lookup that does
+/// not fit the 1024 bytes it starts with.
+fn log_size<C, T: ToC<C>>(fn_name: &str, name: &str, entry: &T) {
+    if !logging::enabled(Level::Debug) {
+        return;
+    }
+    if let Ok(size) = entry.required_size(ptr::null()) {
+        debug!("{} {} needs a {} byte buffer", fn_name, name, size);
+    }
+}
+
+pub(crate) fn request_entry(fn_name: &str, params: &[(&str, String)]) -> NetworkReqResponse {
+    // The daemon must never answer its own lookups through this module
+    if env::var_os("NSS_NYA_DAEMON").is_some() {
+        return NetworkReqResponse::Unavail;
+    }
+
+    if let Some(socket) = &CONFIG.daemon_socket {
+        let timeout = CONFIG.deadline + Duration::from_secs(1);
+        let response = protocol::request(socket, timeout, fn_name, params);
+        return match response {
+            NetworkReqResponse::Unavail | NetworkReqResponse::TimeOut => {
+                match SNAPSHOT.as_ref().and_then(|s| s.lookup(fn_name, params)) {
+                    Some(entry) => {
+                        info!("{} answered from snapshot", fn_name);
+                        NetworkReqResponse::Success(entry)
+                    }
+                    None => response,
+                }
             }
-            debug!("{}({}) got request error => {:?}", fn_name, value, err);
-            return NetworkReqResponse::Error(err.to_string());
-        }
-    };
-    if response.status() == 404 {
-        debug!("{}({}) got 404", fn_name, value);
-        return NetworkReqResponse::NotFound;
+            response => response,
+        };
     }
-    match response.json::<Value>() {
-        Ok(passwd) => {
-            debug!(
-                "{}({}) got json => {:?}",
-                fn_name,
-                value,
-                passwd.to_string()
-            );
-            return NetworkReqResponse::Success(passwd);
-        }
-        Err(err) => {
-            debug!("{}({}) got json parse error => {:?}", fn_name, value, err);
-            return NetworkReqResponse::Error(err.to_string());
-        }
-    };
+
+    #[cfg(feature = "http")]
+    return RESOLVER.resolve(&Caller::current(), fn_name, params);
+    #[cfg(not(feature = "http"))]
+    return NetworkReqResponse::Unavail;
 }
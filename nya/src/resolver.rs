@@ -0,0 +1,198 @@
+use std::{collections::HashMap, time::Duration};
+
+use serde_json::Value;
+
+use crate::batch::Batcher;
+use crate::cache::Cache;
+use crate::caller::Caller;
+use crate::client::ApiClient;
+use crate::config::Config;
+use crate::protocol::NetworkReqResponse;
+use crate::snapshot::{matches, Snapshot};
+use crate::template::batch_op;
+
+/// Answers lookups from the cache, the API and finally the offline snapshot.
+///
+/// Used directly by the NSS module when no daemon is configured, and by
+/// `nya-daemon` for every request it receives.
+pub struct Resolver {
+    client: ApiClient,
+    cache: Cache,
+    batcher: Batcher,
+    snapshot: Option<Snapshot>,
+}
+
+impl Resolver {
+    pub fn new(config: Config) -> Self {
+        Resolver {
+            cache: Cache::new(config.cache_ttl, config.cache_negative_ttl),
+            batcher: Batcher::new(config.batch_window, config.batch_max),
+            snapshot: Snapshot::new(&config),
+            client: ApiClient::new(config),
+        }
+    }
+
+    pub fn client(&self) -> &ApiClient {
+        &self.client
+    }
+
+    pub fn resolve(
+        &self,
+        caller: &Caller,
+        fn_name: &str,
+        params: &[(&str, String)],
+    ) -> NetworkReqResponse {
+        let scope = caller.scope();
+        let key = Cache::key(&scope, fn_name, params);
+        if let Some(response) = self.cache.get(&key) {
+            debug!("{} answered from cache", fn_name);
+            return response;
+        }
+
+        let response = match params {
+            [(param, value)] if self.batcher.enabled() && batch_op(fn_name).is_some() => {
+                let batch = format!("{}\0{}", scope, fn_name);
+                self.batcher.join(&batch, value, |values| {
+                    self.fetch_batch(caller, fn_name, param, values)
+                })
+            }
+            _ => self.client.request_as(caller, fn_name, params),
+        };
+        self.remember(caller, fn_name, params, &response);
+
+        let snapshot = match &self.snapshot {
+            Some(snapshot) => snapshot,
+            None => return response,
+        };
+        match response {
+            NetworkReqResponse::Unavail | NetworkReqResponse::TimeOut => {
+                match snapshot.lookup(fn_name, params) {
+                    Some(entry) => {
+                        info!("{} answered from snapshot", fn_name);
+                        NetworkReqResponse::Success(entry)
+                    }
+                    None => response,
+                }
+            }
+            response => response,
+        }
+    }
+
+    /// Looks up `values` of `param` ahead of time, e.g. every uid in a
+    /// directory listing, so the lookups that follow are cache hits.
+    ///
+    /// Values already cached are skipped, the rest is sent in batches of at
+    /// most `batch_max` keys. Only operations with a batch endpoint are
+    /// prefetched.
+    pub fn prefetch(&self, caller: &Caller, fn_name: &str, param: &str, values: &[String]) {
+        if batch_op(fn_name).is_none() {
+            return;
+        }
+        let scope = caller.scope();
+        let mut missing = values
+            .iter()
+            .filter(|value| {
+                let key = Cache::key(&scope, fn_name, &[(param, value.to_string())]);
+                self.cache.get(&key).is_none()
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        missing.sort();
+        missing.dedup();
+
+        for chunk in missing.chunks(self.batcher.max()) {
+            for (value, response) in self.fetch_batch(caller, fn_name, param, chunk) {
+                self.remember(caller, fn_name, &[(param, value)], &response);
+            }
+        }
+    }
+
+    /// Answers every value, with a single batch request when there is more
+    /// than one.
+    fn fetch_batch(
+        &self,
+        caller: &Caller,
+        fn_name: &str,
+        param: &str,
+        values: &[String],
+    ) -> HashMap<String, NetworkReqResponse> {
+        let single = |value: &String| {
+            let response = self
+                .client
+                .request_as(caller, fn_name, &[(param, value.clone())]);
+            (value.clone(), response)
+        };
+        if values.len() == 1 {
+            return values.iter().map(single).collect();
+        }
+
+        debug!("{} batching {} lookups", fn_name, values.len());
+        match self.client.request_batch_as(caller, fn_name, param, values) {
+            NetworkReqResponse::Success(Value::Array(entries)) => values
+                .iter()
+                .map(|value| {
+                    let response = match entries.iter().find(|entry| matches(entry, param, value)) {
+                        Some(entry) => NetworkReqResponse::Success(entry.clone()),
+                        None => NetworkReqResponse::NotFound,
+                    };
+                    (value.clone(), response)
+                })
+                .collect(),
+            response @ (NetworkReqResponse::Unavail | NetworkReqResponse::TimeOut) => values
+                .iter()
+                .map(|value| (value.clone(), response.clone()))
+                .collect(),
+            // No batch endpoint, or one that misbehaves: ask one by one
+            response => {
+                debug!("{} batch failed => {:?}", fn_name, response);
+                values.iter().map(single).collect()
+            }
+        }
+    }
+
+    /// Caches `response` for `caller` and keeps the snapshot up to date
+    /// with it.
+    fn remember(
+        &self,
+        caller: &Caller,
+        fn_name: &str,
+        params: &[(&str, String)],
+        response: &NetworkReqResponse,
+    ) {
+        let key = Cache::key(&caller.scope(), fn_name, params);
+        match ttl(response) {
+            Some(ttl) => self.cache.insert_with_ttl(key, response, ttl),
+            None => self.cache.insert(key, response),
+        }
+        if let Some(snapshot) = &self.snapshot {
+            if !matches!(
+                response,
+                NetworkReqResponse::Unavail | NetworkReqResponse::TimeOut
+            ) {
+                snapshot.record(fn_name, params, response);
+            }
+        }
+    }
+
+    /// Refreshes the snapshot from full enumerations, bypassing the cache.
+    pub fn sync(&self, caller: &Caller) {
+        let snapshot = match &self.snapshot {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+        for fn_name in ["getpwent", "getgrent", "getspent"] {
+            let response = self.client.request_as(caller, fn_name, &[]);
+            if let NetworkReqResponse::Success(_) = response {
+                snapshot.record(fn_name, &[], &response);
+            }
+        }
+    }
+}
+
+/// The TTL an entry asks to be cached for, e.g. the `ttl` of a host.
+fn ttl(response: &NetworkReqResponse) -> Option<Duration> {
+    match response {
+        NetworkReqResponse::Success(entry) => entry["ttl"].as_u64().map(Duration::from_secs),
+        _ => None,
+    }
+}
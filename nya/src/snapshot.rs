@@ -0,0 +1,358 @@
+use std::{
+    fs,
+    io::{ErrorKind, Read, Write},
+    os::unix::prelude::{AsRawFd, MetadataExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use libnss::database::Database;
+use libnss::init::Trigger;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+use crate::protocol::NetworkReqResponse;
+use crate::template::database;
+
+/// On-disk copy of directory entries used when the API cannot be reached.
+///
+/// Every database lives in its own file inside `dir` (`passwd.json`,
+/// `group.json`, `shadow.json`). A file starts with a `sha256:<hex>` line
+/// covering the rest of the file, and is always replaced atomically.
+/// `shadow.json` is only written when enabled and is never world readable.
+///
+/// The checksum only catches torn or truncated files; it authenticates
+/// nothing, since whoever can write a file can fix up its checksum. Trust
+/// comes from ownership alone: `dir` and every file in it must belong to
+/// root and must not be writable by group or others, and symlinks are never
+/// followed.
+///
+/// `passwd.changed` and `group.changed` are touched whenever the entries of
+/// their database change, for nscd to drop what it cached.
+pub struct Snapshot {
+    dir: PathBuf,
+    owner: libc::uid_t,
+    shadow: bool,
+    max_age: Duration,
+    refresh: Duration,
+}
+
+impl Snapshot {
+    pub fn new(config: &Config) -> Option<Snapshot> {
+        config.snapshot_dir.as_ref().map(|dir| Snapshot {
+            dir: dir.clone(),
+            owner: 0,
+            shadow: config.snapshot_shadow,
+            max_age: config.snapshot_max_age,
+            refresh: config.snapshot_refresh,
+        })
+    }
+
+    /// Trusts a snapshot owned by `owner` rather than root, e.g. one kept by
+    /// an unprivileged user for their own processes.
+    pub fn owned_by(mut self, owner: libc::uid_t) -> Self {
+        self.owner = owner;
+        self
+    }
+
+    /// Updates the snapshot with the outcome of a successful API call.
+    ///
+    /// Enumerations replace the whole database, single lookups replace (or,
+    /// for a 404, remove) the matching entry. Group memberships are not
+    /// stored on their own, they are derived from `group.json`. Only
+    /// processes running as the owner write; everyone else just reads.
+    pub fn record(&self, fn_name: &str, params: &[(&str, String)], response: &NetworkReqResponse) {
+        let db = database(fn_name);
+        if !self.enabled(db) || fn_name == "initgroups" || unsafe { libc::geteuid() } != self.owner
+        {
+            return;
+        }
+
+        let now = now();
+        let result = match (response, params.first()) {
+            (NetworkReqResponse::Success(Value::Array(entries)), None) => {
+                self.update(db, |stored| {
+                    *stored = entries
+                        .iter()
+                        .map(|entry| json!({ "updated": now, "entry": entry }))
+                        .collect();
+                    true
+                })
+            }
+            (NetworkReqResponse::Success(entry), Some((key, value))) => {
+                self.update(db, |entries| {
+                    let existing = entries
+                        .iter()
+                        .position(|stored| matches(&stored["entry"], key, value));
+                    if let Some(index) = existing {
+                        let fresh = now
+                            .saturating_sub(entries[index]["updated"].as_u64().unwrap_or(0))
+                            < self.refresh.as_secs();
+                        if fresh && entries[index]["entry"] == *entry {
+                            return false;
+                        }
+                        entries.remove(index);
+                    }
+                    entries.push(json!({ "updated": now, "entry": entry }));
+                    true
+                })
+            }
+            (NetworkReqResponse::NotFound, Some((key, value))) => self.update(db, |entries| {
+                let before = entries.len();
+                entries.retain(|stored| !matches(&stored["entry"], key, value));
+                entries.len() != before
+            }),
+            _ => Ok(()),
+        };
+        if let Err(err) = result {
+            warn!("snapshot {} update failed => {}", db, err);
+        }
+    }
+
+    /// Answers a lookup from the snapshot, ignoring entries older than the
+    /// configured maximum staleness.
+    pub fn lookup(&self, fn_name: &str, params: &[(&str, String)]) -> Option<Value> {
+        let db = database(fn_name);
+        if !self.enabled(db) {
+            return None;
+        }
+
+        let oldest = now().saturating_sub(self.max_age.as_secs());
+        let entries = self
+            .read(db)
+            .into_iter()
+            .filter(|stored| stored["updated"].as_u64().unwrap_or(0) >= oldest)
+            .map(|mut stored| stored["entry"].take());
+
+        match params.first() {
+            Some((_, name)) if fn_name == "initgroups" => {
+                let groups = entries
+                    .filter(|group| {
+                        group["members"]
+                            .as_array()
+                            .is_some_and(|members| members.iter().any(|m| m == name.as_str()))
+                    })
+                    .collect::<Vec<_>>();
+                match groups.is_empty() {
+                    true => None,
+                    false => Some(Value::Array(groups)),
+                }
+            }
+            None => {
+                let entries = entries.collect::<Vec<_>>();
+                match entries.is_empty() {
+                    true => None,
+                    false => Some(Value::Array(entries)),
+                }
+            }
+            Some((key, value)) => entries.into_iter().find(|entry| matches(entry, key, value)),
+        }
+    }
+
+    /// The files nscd should trace, see [`libnss::init`].
+    pub fn triggers(&self) -> Vec<Trigger> {
+        ["passwd", "group"]
+            .iter()
+            .filter_map(|db| self.trigger(db))
+            .collect()
+    }
+
+    fn trigger(&self, db: &str) -> Option<Trigger> {
+        let database = match db {
+            "passwd" => Database::Passwd,
+            "group" => Database::Group,
+            _ => return None,
+        };
+        Some(Trigger::new(
+            database,
+            self.dir.join(format!("{}.changed", db)),
+        ))
+    }
+
+    fn enabled(&self, db: &str) -> bool {
+        match db {
+            "passwd" | "group" => true,
+            "shadow" => self.shadow,
+            _ => false,
+        }
+    }
+
+    fn path(&self, db: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", db))
+    }
+
+    /// Whether `metadata` belongs to the owner and is not writable by
+    /// anyone else.
+    fn trusted(&self, metadata: &fs::Metadata) -> bool {
+        metadata.uid() == self.owner && metadata.mode() & 0o022 == 0
+    }
+
+    /// Whether the snapshot directory may be used at all.
+    fn dir_trusted(&self) -> bool {
+        let trusted = fs::metadata(&self.dir)
+            .is_ok_and(|metadata| metadata.is_dir() && self.trusted(&metadata));
+        if !trusted {
+            debug!(
+                "snapshot {} is not a directory owned by uid {} or is writable by others",
+                self.dir.display(),
+                self.owner
+            );
+        }
+        trusted
+    }
+
+    /// Reads and verifies a database file, returning no entries if it is
+    /// missing, not trustworthy or corrupt.
+    fn read(&self, db: &str) -> Vec<Value> {
+        if !self.dir_trusted() {
+            return vec![];
+        }
+        let path = self.path(db);
+        let mut file = match open_nofollow(fs::OpenOptions::new().read(true), &path) {
+            Ok(file) => file,
+            Err(_) => return vec![],
+        };
+        // Checked on the open file, which cannot be swapped underneath
+        if !file
+            .metadata()
+            .is_ok_and(|metadata| self.trusted(&metadata))
+        {
+            debug!(
+                "snapshot {} is not owned by uid {} or is writable by others",
+                path.display(),
+                self.owner
+            );
+            return vec![];
+        }
+
+        let mut contents = String::new();
+        if file.read_to_string(&mut contents).is_err() {
+            return vec![];
+        }
+        let (checksum, payload) = match contents.split_once('\n') {
+            Some((checksum, payload)) => (checksum, payload),
+            None => return vec![],
+        };
+        if checksum.strip_prefix("sha256:") != Some(&hex::encode(Sha256::digest(payload))) {
+            warn!("snapshot {} failed checksum verification", path.display());
+            return vec![];
+        }
+        serde_json::from_str(payload).unwrap_or_default()
+    }
+
+    /// Read-modify-write of a database under an exclusive lock. `change`
+    /// returns whether anything needs to be written.
+    fn update<F: FnOnce(&mut Vec<Value>) -> bool>(
+        &self,
+        db: &str,
+        change: F,
+    ) -> std::io::Result<()> {
+        if !self.dir_trusted() {
+            return Err(ErrorKind::PermissionDenied.into());
+        }
+        let lock = open_nofollow(
+            fs::OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .mode(0o600),
+            &self.dir.join(".lock"),
+        )?;
+        unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX) };
+
+        let mut entries = self.read(db);
+        let before = contents(&entries);
+        if !change(&mut entries) {
+            return Ok(());
+        }
+        self.write(db, &entries)?;
+
+        // Refreshed timestamps alone are no reason to flush nscd
+        if contents(&entries) != before {
+            if let Some(trigger) = self.trigger(db) {
+                // Creating the trigger wakes nscd just like touching it
+                let result = match trigger.fire() {
+                    Err(err) if err.kind() == ErrorKind::NotFound => open_nofollow(
+                        fs::OpenOptions::new()
+                            .create_new(true)
+                            .write(true)
+                            .mode(0o644),
+                        trigger.path(),
+                    )
+                    .map(drop),
+                    result => result,
+                };
+                if let Err(err) = result {
+                    warn!("{} not touched => {}", trigger.path().display(), err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Atomically replaces a database file.
+    fn write(&self, db: &str, entries: &[Value]) -> std::io::Result<()> {
+        let payload = serde_json::to_string(entries)?;
+        let path = self.path(db);
+        let tmp = self.dir.join(format!(".{}.{}.tmp", db, std::process::id()));
+        let mode = match db {
+            "shadow" => 0o600,
+            _ => 0o644,
+        };
+
+        let _ = fs::remove_file(&tmp);
+        let result = write_file(&tmp, mode, &payload).and_then(|_| fs::rename(&tmp, &path));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        result
+    }
+}
+
+fn write_file(path: &Path, mode: u32, payload: &str) -> std::io::Result<()> {
+    let mut file = open_nofollow(
+        fs::OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .mode(mode),
+        path,
+    )?;
+    writeln!(file, "sha256:{}", hex::encode(Sha256::digest(payload)))?;
+    file.write_all(payload.as_bytes())?;
+    file.sync_all()
+}
+
+/// Opens `path` with `options`, failing if it is a symlink.
+fn open_nofollow(options: &mut fs::OpenOptions, path: &Path) -> std::io::Result<fs::File> {
+    options
+        .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        .open(path)
+}
+
+/// The stored entries without their timestamps, in a stable order.
+fn contents(entries: &[Value]) -> Vec<String> {
+    let mut contents = entries
+        .iter()
+        .map(|stored| stored["entry"].to_string())
+        .collect::<Vec<_>>();
+    contents.sort();
+    contents
+}
+
+/// Whether `entry[key]` equals a lookup parameter, comparing numbers by
+/// their decimal representation.
+pub(crate) fn matches(entry: &Value, key: &str, value: &str) -> bool {
+    match &entry[key] {
+        Value::String(s) => s == value,
+        Value::Number(n) => n.to_string() == value,
+        _ => false,
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or(0)
+}
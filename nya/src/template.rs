@@ -0,0 +1,138 @@
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+/// Everything outside the RFC 3986 unreserved set is escaped, so a value can
+/// be placed in a path segment or a query parameter without changing its meaning.
+const COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Every operation nya knows about.
+pub const OPS: &[&str] = &[
+    "getpwent",
+    "getpwuid",
+    "getpwnam",
+    "getgrent",
+    "getgrgid",
+    "getgrnam",
+    "initgroups",
+    "getspent",
+    "getspnam",
+    "gethostent",
+    "gethostbyname",
+    "gethostbyaddr",
+    "passwd_batch",
+    "group_batch",
+];
+
+/// The databases operations are grouped into.
+pub const DATABASES: &[&str] = &["passwd", "group", "shadow", "hosts"];
+
+/// Default URL template for every operation nya knows about.
+pub fn default_url_template(op: &str) -> Option<&'static str> {
+    match op {
+        "getpwent" => Some("{base}/passwd"),
+        "getpwuid" => Some("{base}/passwd?uid={uid}"),
+        "getpwnam" => Some("{base}/passwd?name={name}"),
+        "getgrent" => Some("{base}/group"),
+        "getgrgid" => Some("{base}/group?gid={gid}"),
+        "getgrnam" => Some("{base}/group?name={name}"),
+        "initgroups" => Some("{base}/initgroups?name={name}"),
+        "getspent" => Some("{base}/shadow"),
+        "getspnam" => Some("{base}/shadow?name={name}"),
+        "gethostent" => Some("{base}/hosts"),
+        "gethostbyname" => Some("{base}/hosts?name={name}&family={family}"),
+        "gethostbyaddr" => Some("{base}/hosts?addr={addr}"),
+        "passwd_batch" => Some("{base}/passwd/batch"),
+        "group_batch" => Some("{base}/group/batch"),
+        _ => None,
+    }
+}
+
+/// Whether `op` downloads a whole database rather than a single entry.
+pub fn is_enumeration(op: &str) -> bool {
+    matches!(op, "getpwent" | "getgrent" | "getspent" | "gethostent")
+}
+
+/// The operation answering many lookups of `op` in one request, if any.
+pub fn batch_op(op: &str) -> Option<&'static str> {
+    match op {
+        "getpwuid" | "getpwnam" => Some("passwd_batch"),
+        "getgrgid" | "getgrnam" => Some("group_batch"),
+        _ => None,
+    }
+}
+
+/// The NSS database an operation belongs to.
+pub fn database(op: &str) -> &str {
+    match op {
+        "getpwent" | "getpwuid" | "getpwnam" | "passwd_batch" => "passwd",
+        "getgrent" | "getgrgid" | "getgrnam" | "initgroups" | "group_batch" => "group",
+        "getspent" | "getspnam" => "shadow",
+        "gethostent" | "gethostbyname" | "gethostbyaddr" => "hosts",
+        _ => op,
+    }
+}
+
+/// Expands `{base}` and `{<param>}` placeholders in `template`.
+///
+/// `base` is inserted verbatim (minus any trailing slash), parameters are
+/// percent-encoded. Unknown placeholders are reported as an error rather than
+/// silently sent to the server.
+pub fn render_url(template: &str, base: &str, params: &[(&str, String)]) -> Result<String, String> {
+    let mut url = String::with_capacity(template.len() + base.len());
+    let mut rest = template;
+
+    while let Some(open) = rest.find('{') {
+        url.push_str(&rest[..open]);
+        let close = match rest[open..].find('}') {
+            Some(close) => open + close,
+            None => {
+                return Err(format!(
+                    "unterminated placeholder in url template {}",
+                    template
+                ))
+            }
+        };
+
+        let name = &rest[open + 1..close];
+        if name == "base" {
+            url.push_str(base.trim_end_matches('/'));
+        } else {
+            match params.iter().find(|(key, _)| *key == name) {
+                Some((_, value)) => url.extend(utf8_percent_encode(value, COMPONENT)),
+                None => {
+                    return Err(format!(
+                        "unknown placeholder {{{}}} in url template {}",
+                        name, template
+                    ))
+                }
+            }
+        }
+        rest = &rest[close + 1..];
+    }
+    url.push_str(rest);
+
+    Ok(url)
+}
+
+/// The host name of an endpoint URL, without brackets around IPv6 literals.
+pub fn endpoint_host(endpoint: &str) -> Option<&str> {
+    let authority = endpoint
+        .split_once("://")
+        .map_or(endpoint, |(_, rest)| rest);
+    let authority = authority.split(['/', '?', '#']).next()?;
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+
+    let host = match host.strip_prefix('[') {
+        Some(literal) => literal.split(']').next()?,
+        None => host.split(':').next()?,
+    };
+    match host.is_empty() {
+        true => None,
+        false => Some(host),
+    }
+}
@@ -0,0 +1,185 @@
+use std::{fs, io::BufReader, path::Path, sync::Arc, time::SystemTime};
+
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerName,
+};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+
+/// Builds the rustls configuration used for every API request.
+///
+/// Trust anchors are the public webpki roots (unless disabled) plus the
+/// configured CA bundle. When public key pins are configured the presented
+/// chain must still validate, and additionally one of its certificates must
+/// carry a pinned key.
+pub fn client_config(config: &Config) -> Result<ClientConfig, String> {
+    let mut roots = RootCertStore::empty();
+    if config.public_roots {
+        roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        }));
+    }
+    if let Some(bundle) = &config.ca_bundle {
+        for cert in read_certs(bundle)? {
+            roots
+                .add(&cert)
+                .map_err(|err| format!("invalid CA in {}: {}", bundle.display(), err))?;
+        }
+    }
+    if roots.is_empty() {
+        return Err("no trust anchors configured".to_string());
+    }
+
+    let pins = config
+        .pinned_pubkeys
+        .iter()
+        .map(|pin| parse_pin(pin))
+        .collect::<Result<Vec<_>, _>>()?;
+    let verifier = PinningVerifier {
+        inner: WebPkiVerifier::new(roots, None),
+        pins,
+    };
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(verifier));
+
+    match (&config.client_cert, &config.client_key) {
+        (Some(cert), Some(key)) => builder
+            .with_single_cert(read_certs(cert)?, read_key(key)?)
+            .map_err(|err| format!("invalid client certificate: {}", err)),
+        _ => Ok(builder.with_no_client_auth()),
+    }
+}
+
+/// Returns the `sha256//<base64>` pin of a DER encoded certificate's public key.
+pub fn spki_pin(cert_der: &[u8]) -> Option<String> {
+    spki(cert_der).map(|spki| format!("sha256//{}", base64::encode(Sha256::digest(spki))))
+}
+
+struct PinningVerifier {
+    inner: WebPkiVerifier,
+    pins: Vec<Vec<u8>>,
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+        if self.pins.is_empty() {
+            return Ok(verified);
+        }
+
+        let pinned = std::iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(|cert| spki(&cert.0))
+            .any(|spki| {
+                let digest = Sha256::digest(spki);
+                self.pins.iter().any(|pin| pin[..] == digest[..])
+            });
+        match pinned {
+            true => Ok(verified),
+            false => Err(rustls::Error::General(
+                "server public key does not match any pin".to_string(),
+            )),
+        }
+    }
+}
+
+fn parse_pin(pin: &str) -> Result<Vec<u8>, String> {
+    let digest = pin
+        .strip_prefix("sha256//")
+        .and_then(|encoded| base64::decode(encoded).ok())
+        .filter(|digest| digest.len() == 32);
+    digest.ok_or_else(|| format!("invalid public key pin {}", pin))
+}
+
+fn read_certs(path: &Path) -> Result<Vec<Certificate>, String> {
+    let file = fs::File::open(path)
+        .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .map_err(|err| format!("failed to parse {}: {}", path.display(), err))?;
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn read_key(path: &Path) -> Result<PrivateKey, String> {
+    let file = fs::File::open(path)
+        .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+    let mut reader = BufReader::new(file);
+    loop {
+        match rustls_pemfile::read_one(&mut reader) {
+            Ok(Some(rustls_pemfile::Item::PKCS8Key(key)))
+            | Ok(Some(rustls_pemfile::Item::RSAKey(key)))
+            | Ok(Some(rustls_pemfile::Item::ECKey(key))) => return Ok(PrivateKey(key)),
+            Ok(Some(_)) => continue,
+            Ok(None) => return Err(format!("no private key in {}", path.display())),
+            Err(err) => return Err(format!("failed to parse {}: {}", path.display(), err)),
+        }
+    }
+}
+
+/// One DER element split off the front of a byte string.
+struct DerElement<'a> {
+    /// The whole element, header included.
+    element: &'a [u8],
+    contents: &'a [u8],
+    rest: &'a [u8],
+}
+
+fn der_element(input: &[u8]) -> Option<DerElement<'_>> {
+    let first = *input.get(1)? as usize;
+    let (len, header) = match first {
+        0..=0x7f => (first, 2),
+        0x81..=0x84 => {
+            let octets = first & 0x7f;
+            let len = input
+                .get(2..2 + octets)?
+                .iter()
+                .fold(0usize, |len, b| (len << 8) | *b as usize);
+            (len, 2 + octets)
+        }
+        _ => return None,
+    };
+    let end = header.checked_add(len)?;
+    let element = input.get(..end)?;
+    Some(DerElement {
+        element,
+        contents: &element[header..],
+        rest: &input[end..],
+    })
+}
+
+/// Extracts the `subjectPublicKeyInfo` element of an X.509 certificate.
+fn spki(cert_der: &[u8]) -> Option<&[u8]> {
+    let certificate = der_element(cert_der)?.contents;
+    let mut tbs = der_element(certificate)?.contents;
+
+    // Optional explicit version
+    if tbs.first() == Some(&0xa0) {
+        tbs = der_element(tbs)?.rest;
+    }
+    // serialNumber, signature, issuer, validity, subject
+    for _ in 0..5 {
+        tbs = der_element(tbs)?.rest;
+    }
+    der_element(tbs).map(|spki| spki.element)
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use common::{passwd_json, MockServer};
+use nss_nya::client::{sign_request, ApiClient, NetworkReqResponse};
+use nss_nya::config::{Auth, Config};
+
+fn client(url: &str, auth: Auth) -> ApiClient {
+    ApiClient::new(Config {
+        endpoints: vec![url.to_string()],
+        auth,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn bearer_token_is_sent() {
+    let server = MockServer::start(|request| match request.headers.get("authorization") {
+        Some(value) if value == "Bearer s3cret" => (200, passwd_json("alice", 1000)),
+        _ => (401, "{}".to_string()),
+    });
+
+    let client = client(&server.url, Auth::Bearer("s3cret".to_string()));
+    assert!(matches!(
+        client.request("getpwnam", &[("name", "alice".to_string())]),
+        NetworkReqResponse::Success(_)
+    ));
+}
+
+#[test]
+fn rejected_token_is_an_error() {
+    let server = MockServer::start(|_| (401, "{}".to_string()));
+
+    let client = client(&server.url, Auth::Bearer("wrong".to_string()));
+    assert!(matches!(
+        client.request("getpwnam", &[("name", "alice".to_string())]),
+        NetworkReqResponse::Error(_)
+    ));
+}
+
+#[test]
+fn no_auth_headers_by_default() {
+    let server = MockServer::start(|_| (200, passwd_json("alice", 1000)));
+
+    client(&server.url, Auth::None).request("getpwuid", &[("uid", "1000".to_string())]);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(!requests[0].headers.contains_key("authorization"));
+    assert!(!requests[0].headers.contains_key("x-nya-signature"));
+}
+
+#[test]
+fn hmac_signature_covers_path_timestamp_and_nonce() {
+    let server = MockServer::start(|request| {
+        let header = |name: &str| request.headers.get(name).cloned().unwrap_or_default();
+        let expected = sign_request(
+            "hmac-secret",
+            &request.method,
+            &request.url,
+            &header("x-nya-timestamp"),
+            &header("x-nya-nonce"),
+        );
+        match header("x-nya-key-id") == "host-1" && header("x-nya-signature") == expected {
+            true => (200, passwd_json("a&b", 1001)),
+            false => (401, "{}".to_string()),
+        }
+    });
+
+    let client = client(
+        &server.url,
+        Auth::Hmac {
+            key_id: "host-1".to_string(),
+            secret: "hmac-secret".to_string(),
+        },
+    );
+    assert!(matches!(
+        client.request("getpwnam", &[("name", "a&b".to_string())]),
+        NetworkReqResponse::Success(_)
+    ));
+    assert!(matches!(
+        client.request("getpwnam", &[("name", "a&b".to_string())]),
+        NetworkReqResponse::Success(_)
+    ));
+
+    let requests = server.requests();
+    assert_eq!(requests[0].url, "/passwd?name=a%26b");
+    assert_ne!(
+        requests[0].headers["x-nya-nonce"],
+        requests[1].headers["x-nya-nonce"]
+    );
+}
+
+#[test]
+fn hmac_signature_is_stable() {
+    assert_eq!(
+        sign_request("key", "GET", "/passwd?uid=1", "1700000000", "00"),
+        sign_request("key", "GET", "/passwd?uid=1", "1700000000", "00")
+    );
+    assert_ne!(
+        sign_request("key", "GET", "/passwd?uid=1", "1700000000", "00"),
+        sign_request("key", "GET", "/passwd?uid=2", "1700000000", "00")
+    );
+}
+
+#[test]
+fn secrets_are_read_from_config_values() {
+    let values = nss_nya::config::parse_config(
+        "# directory credentials\nNSS_HTTP_API_AUTH=hmac\nNSS_HTTP_API_HMAC_KEY_ID=\"host-1\"\nNSS_HTTP_API_HMAC_SECRET=abc\n",
+    );
+    let config = Config::from_lookup(|key| values.get(key).cloned());
+    assert_eq!(
+        config.auth,
+        Auth::Hmac {
+            key_id: "host-1".to_string(),
+            secret: "abc".to_string()
+        }
+    );
+}
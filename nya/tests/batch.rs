@@ -0,0 +1,191 @@
+mod common;
+
+use std::{sync::Arc, thread, time::Duration};
+
+use common::{passwd_json, MockServer, Recorded};
+use nss_nya::caller::Caller;
+use nss_nya::client::sign_request_with_body;
+use nss_nya::config::{Auth, Config};
+use nss_nya::protocol::NetworkReqResponse;
+use nss_nya::resolver::Resolver;
+use serde_json::Value;
+
+/// Answers single and batch passwd lookups for uids 1000 to 1999.
+fn directory(request: &Recorded) -> (u16, String) {
+    let exists = |uid: u64| (1000..2000).contains(&uid);
+    if request.url == "/passwd/batch" {
+        let body: Value = serde_json::from_str(&request.body).unwrap();
+        let entries = body["uid"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(Value::as_u64)
+            .filter(|uid| exists(*uid))
+            .map(|uid| passwd_json(&format!("user{}", uid), uid as u32))
+            .collect::<Vec<_>>();
+        return (200, format!("[{}]", entries.join(",")));
+    }
+    match request
+        .url
+        .strip_prefix("/passwd?uid=")
+        .and_then(|uid| uid.parse().ok())
+    {
+        Some(uid) if exists(uid) => (200, passwd_json(&format!("user{}", uid), uid as u32)),
+        _ => (404, "{}".to_string()),
+    }
+}
+
+fn resolver(server: &MockServer, window: Duration) -> Resolver {
+    Resolver::new(Config {
+        endpoints: vec![server.url.clone()],
+        batch_window: window,
+        batch_max: 20,
+        ..Default::default()
+    })
+}
+
+fn getpwuid(resolver: &Resolver, uid: u32) -> NetworkReqResponse {
+    resolver.resolve(&Caller::current(), "getpwuid", &[("uid", uid.to_string())])
+}
+
+fn name(response: NetworkReqResponse) -> String {
+    match response {
+        NetworkReqResponse::Success(entry) => entry["name"].as_str().unwrap().to_string(),
+        other => panic!("unexpected response {:?}", other),
+    }
+}
+
+#[test]
+fn concurrent_misses_share_a_request() {
+    let server = MockServer::start(directory);
+    let resolver = Arc::new(resolver(&server, Duration::from_millis(200)));
+
+    let lookups = (1000..1016)
+        .map(|uid| {
+            let resolver = resolver.clone();
+            thread::spawn(move || (uid, getpwuid(&resolver, uid)))
+        })
+        .collect::<Vec<_>>();
+    for lookup in lookups {
+        let (uid, response) = lookup.join().unwrap();
+        assert_eq!(name(response), format!("user{}", uid));
+    }
+
+    let requests = server.requests();
+    assert!(requests.len() <= 4, "{} requests", requests.len());
+    assert!(requests.iter().any(|request| request.method == "POST"));
+}
+
+#[test]
+fn prefetch_fills_the_cache() {
+    let server = MockServer::start(directory);
+    let resolver = resolver(&server, Duration::ZERO);
+    let uids = (1990..2040).map(|uid| uid.to_string()).collect::<Vec<_>>();
+
+    resolver.prefetch(&Caller::current(), "getpwuid", "uid", &uids);
+    assert_eq!(server.requests().len(), 3);
+
+    for uid in 1990..2040 {
+        match uid < 2000 {
+            true => assert_eq!(name(getpwuid(&resolver, uid)), format!("user{}", uid)),
+            false => assert_eq!(getpwuid(&resolver, uid), NetworkReqResponse::NotFound),
+        }
+    }
+    // Cached values are not asked for again
+    resolver.prefetch(&Caller::current(), "getpwuid", "uid", &uids);
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[test]
+fn batch_requests_post_the_keys() {
+    let server = MockServer::start(directory);
+    let resolver = resolver(&server, Duration::ZERO);
+    let uids = vec!["1000".to_string(), "1001".to_string()];
+
+    resolver.prefetch(&Caller::current(), "getpwuid", "uid", &uids);
+
+    let request = &server.requests()[0];
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.url, "/passwd/batch");
+    assert_eq!(request.headers["content-type"], "application/json");
+    assert_eq!(request.body, r#"{"uid":[1000,1001]}"#);
+}
+
+#[test]
+fn missing_batch_endpoint_falls_back_to_single_lookups() {
+    let server = MockServer::start(|request| match request.url.as_str() {
+        "/passwd/batch" => (404, "{}".to_string()),
+        _ => directory(request),
+    });
+    let resolver = resolver(&server, Duration::ZERO);
+    let uids = vec!["1000".to_string(), "1001".to_string()];
+
+    resolver.prefetch(&Caller::current(), "getpwuid", "uid", &uids);
+    assert_eq!(server.requests().len(), 3);
+    assert_eq!(name(getpwuid(&resolver, 1001)), "user1001");
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[test]
+fn batching_is_off_by_default() {
+    let server = MockServer::start(directory);
+    let resolver = resolver(&server, Duration::ZERO);
+
+    for uid in 1000..1005 {
+        assert_eq!(name(getpwuid(&resolver, uid)), format!("user{}", uid));
+    }
+    let requests = server.requests();
+    assert_eq!(requests.len(), 5);
+    assert!(requests.iter().all(|request| request.method == "GET"));
+}
+
+#[test]
+fn hmac_signature_covers_the_body() {
+    let server = MockServer::start(|request| {
+        let header = |name: &str| request.headers.get(name).cloned().unwrap_or_default();
+        let expected = sign_request_with_body(
+            "hmac-secret",
+            &request.method,
+            &request.url,
+            &header("x-nya-timestamp"),
+            &header("x-nya-nonce"),
+            request.body.as_bytes(),
+        );
+        match header("x-nya-signature") == expected {
+            true => directory(request),
+            false => (401, "{}".to_string()),
+        }
+    });
+    let resolver = Resolver::new(Config {
+        endpoints: vec![server.url.clone()],
+        auth: Auth::Hmac {
+            key_id: "host-1".to_string(),
+            secret: "hmac-secret".to_string(),
+        },
+        ..Default::default()
+    });
+    let uids = vec!["1000".to_string(), "1001".to_string()];
+
+    resolver.prefetch(&Caller::current(), "getpwuid", "uid", &uids);
+    assert_eq!(server.requests().len(), 1);
+    assert_ne!(
+        sign_request_with_body("k", "POST", "/passwd/batch", "1", "00", b"{}"),
+        sign_request_with_body("k", "POST", "/passwd/batch", "1", "00", b"[]")
+    );
+}
+
+#[test]
+fn batching_is_configurable() {
+    let config = Config::from_lookup(|key| match key {
+        "NSS_HTTP_API_BATCH_WINDOW_MS" => Some("5".to_string()),
+        "NSS_HTTP_API_BATCH_MAX" => Some("50".to_string()),
+        "NSS_HTTP_API_URL_PASSWD_BATCH" => Some("{base}/v2/users:batch".to_string()),
+        _ => None,
+    });
+    assert_eq!(config.batch_window, Duration::from_millis(5));
+    assert_eq!(config.batch_max, 50);
+    assert_eq!(
+        config.url_templates["passwd_batch"],
+        "{base}/v2/users:batch"
+    );
+}
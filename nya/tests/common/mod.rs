@@ -0,0 +1,108 @@
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use tiny_http::{Header, Response, Server};
+
+/// A request as seen by the mock server.
+#[derive(Clone, Debug)]
+pub struct Recorded {
+    pub method: String,
+    pub url: String,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+/// Minimal HTTP server answering every request through `handler`.
+pub struct MockServer {
+    pub url: String,
+    pub requests: Arc<Mutex<Vec<Recorded>>>,
+}
+
+impl MockServer {
+    pub fn start<F>(handler: F) -> MockServer
+    where
+        F: Fn(&Recorded) -> (u16, String) + Send + 'static,
+    {
+        MockServer::start_with_headers(move |request| {
+            let (status, body) = handler(request);
+            (status, Vec::new(), body)
+        })
+    }
+
+    /// Like `start`, but `handler` also returns extra response headers.
+    pub fn start_with_headers<F>(handler: F) -> MockServer
+    where
+        F: Fn(&Recorded) -> (u16, Vec<(&'static str, String)>, String) + Send + 'static,
+    {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for mut request in server.incoming_requests() {
+                let mut body = String::new();
+                let _ = request.as_reader().read_to_string(&mut body);
+                let entry = Recorded {
+                    method: request.method().to_string(),
+                    url: request.url().to_string(),
+                    headers: request
+                        .headers()
+                        .iter()
+                        .map(|h| (h.field.to_string().to_lowercase(), h.value.to_string()))
+                        .collect(),
+                    body,
+                };
+                let (status, headers, body) = handler(&entry);
+                recorded.lock().unwrap().push(entry);
+
+                let mut response = Response::from_string(body)
+                    .with_status_code(status)
+                    .with_header(
+                        Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
+                    );
+                for (name, value) in headers {
+                    response
+                        .add_header(Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap());
+                }
+                let _ = request.respond(response);
+            }
+        });
+
+        MockServer { url, requests }
+    }
+
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+pub fn passwd_json(name: &str, uid: u32) -> String {
+    format!(
+        r#"{{"name":"{name}","passwd":"x","uid":{uid},"gid":{uid},"gecos":"","dir":"/home/{name}","shell":"/bin/sh"}}"#
+    )
+}
+
+/// Writes `contents` to a config file only this user can read and points
+/// `NSS_HTTP_API_CONFIG` at it, for tests that load the module's global
+/// configuration.
+pub fn use_config(name: &str, contents: &str) {
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::{fs::OpenOptions, io::Write};
+
+    let path = std::env::temp_dir().join(format!("nya-{}-{}.conf", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)
+        .unwrap();
+    file.write_all(contents.as_bytes()).unwrap();
+    std::env::set_var("NSS_HTTP_API_CONFIG", &path);
+}
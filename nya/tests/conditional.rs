@@ -0,0 +1,191 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use common::{passwd_json, MockServer};
+use nss_nya::caller::Caller;
+use nss_nya::client::{ApiClient, NetworkReqResponse};
+use nss_nya::config::Config;
+use serde_json::Value;
+
+fn client(server: &MockServer) -> ApiClient {
+    ApiClient::new(Config {
+        endpoints: vec![server.url.clone()],
+        ..Default::default()
+    })
+}
+
+fn entries(response: NetworkReqResponse) -> Vec<String> {
+    match response {
+        NetworkReqResponse::Success(Value::Array(entries)) => entries
+            .iter()
+            .map(|entry| entry["name"].as_str().unwrap().to_string())
+            .collect(),
+        other => panic!("unexpected response {:?}", other),
+    }
+}
+
+#[test]
+fn unchanged_enumeration_is_served_from_etag() {
+    let server = MockServer::start_with_headers(|request| {
+        match request.headers.get("if-none-match").map(String::as_str) {
+            Some("\"v1\"") => (304, vec![("ETag", "\"v1\"".to_string())], String::new()),
+            _ => (
+                200,
+                vec![("ETag", "\"v1\"".to_string())],
+                format!("[{}]", passwd_json("alice", 1000)),
+            ),
+        }
+    });
+    let client = client(&server);
+
+    assert_eq!(entries(client.request("getpwent", &[])), vec!["alice"]);
+    assert_eq!(entries(client.request("getpwent", &[])), vec!["alice"]);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].headers.get("if-none-match"), None);
+    assert_eq!(
+        requests[1].headers.get("if-none-match").map(String::as_str),
+        Some("\"v1\"")
+    );
+}
+
+#[test]
+fn validators_are_not_shared_between_callers() {
+    let server = MockServer::start_with_headers(|request| {
+        match request.headers.get("if-none-match").map(String::as_str) {
+            Some("\"v1\"") => (304, vec![("ETag", "\"v1\"".to_string())], String::new()),
+            _ => {
+                let name = match request.headers["x-uid"].as_str() {
+                    "1000" => "alice",
+                    _ => "bob",
+                };
+                (
+                    200,
+                    vec![("ETag", "\"v1\"".to_string())],
+                    format!("[{}]", passwd_json(name, 1000)),
+                )
+            }
+        }
+    });
+    let client = client(&server);
+    let getpwent = |uid| client.request_as(&Caller::from_peer(4242, uid, uid), "getpwent", &[]);
+
+    assert_eq!(entries(getpwent(1000)), vec!["alice"]);
+    assert_eq!(entries(getpwent(1001)), vec!["bob"]);
+    assert_eq!(entries(getpwent(1000)), vec!["alice"]);
+
+    let requests = server.requests();
+    assert_eq!(requests[1].headers.get("if-none-match"), None);
+    assert!(requests[2].headers.contains_key("if-none-match"));
+}
+
+#[test]
+fn last_modified_is_sent_back() {
+    let date = "Wed, 21 Oct 2026 07:28:00 GMT";
+    let server = MockServer::start_with_headers(move |request| {
+        match request.headers.get("if-modified-since") {
+            Some(since) if since == date => (304, Vec::new(), String::new()),
+            _ => (
+                200,
+                vec![("Last-Modified", date.to_string())],
+                r#"[{"name":"staff","passwd":"x","gid":1000,"members":[]}]"#.to_string(),
+            ),
+        }
+    });
+    let client = client(&server);
+
+    assert_eq!(entries(client.request("getgrent", &[])), vec!["staff"]);
+    assert_eq!(entries(client.request("getgrent", &[])), vec!["staff"]);
+    let requests = server.requests();
+    assert_eq!(
+        requests[1]
+            .headers
+            .get("if-modified-since")
+            .map(String::as_str),
+        Some(date)
+    );
+}
+
+#[test]
+fn changed_enumeration_replaces_the_stored_copy() {
+    let version = Arc::new(AtomicUsize::new(0));
+    let served = version.clone();
+    let server = MockServer::start_with_headers(move |request| {
+        let current = format!("\"v{}\"", served.load(Ordering::SeqCst));
+        if request.headers.get("if-none-match") == Some(&current) {
+            return (304, Vec::new(), String::new());
+        }
+        let name = match served.load(Ordering::SeqCst) {
+            0 => "alice",
+            _ => "bob",
+        };
+        (
+            200,
+            vec![("ETag", current)],
+            format!("[{}]", passwd_json(name, 1000)),
+        )
+    });
+    let client = client(&server);
+
+    assert_eq!(entries(client.request("getpwent", &[])), vec!["alice"]);
+    version.store(1, Ordering::SeqCst);
+    assert_eq!(entries(client.request("getpwent", &[])), vec!["bob"]);
+    assert_eq!(entries(client.request("getpwent", &[])), vec!["bob"]);
+
+    let requests = server.requests();
+    assert_eq!(
+        requests[2].headers.get("if-none-match").map(String::as_str),
+        Some("\"v1\"")
+    );
+}
+
+#[test]
+fn responses_without_validators_are_not_revalidated() {
+    let count = Arc::new(AtomicUsize::new(0));
+    let seen = count.clone();
+    let server = MockServer::start_with_headers(move |_| {
+        let headers = match seen.fetch_add(1, Ordering::SeqCst) {
+            0 => vec![("ETag", "\"v1\"".to_string())],
+            _ => Vec::new(),
+        };
+        (200, headers, format!("[{}]", passwd_json("alice", 1000)))
+    });
+    let client = client(&server);
+
+    for _ in 0..3 {
+        client.request("getpwent", &[]);
+    }
+    let requests = server.requests();
+    assert!(requests[1].headers.contains_key("if-none-match"));
+    assert!(!requests[2].headers.contains_key("if-none-match"));
+}
+
+#[test]
+fn single_lookups_are_never_conditional() {
+    let server = MockServer::start_with_headers(|_| {
+        (
+            200,
+            vec![("ETag", "\"v1\"".to_string())],
+            passwd_json("alice", 1000),
+        )
+    });
+    let client = client(&server);
+
+    client.request("getpwnam", &[("name", "alice".to_string())]);
+    client.request("getpwnam", &[("name", "alice".to_string())]);
+    assert!(!server.requests()[1].headers.contains_key("if-none-match"));
+}
+
+#[test]
+fn unexpected_not_modified_is_an_error() {
+    let server = MockServer::start(|_| (304, String::new()));
+    let client = client(&server);
+
+    assert!(matches!(
+        client.request("getpwent", &[]),
+        NetworkReqResponse::Error(_)
+    ));
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use std::{path::PathBuf, time::Duration};
+
+use nss_nya::config::{Config, EndpointOrder};
+use nss_nya::logging::Level;
+
+/// An environment that tries to set every key worth setting.
+fn hostile(key: &str) -> Option<String> {
+    let value = match key {
+        "NSS_HTTP_API_CONFIG" => "/nonexistent/nss_nya.conf",
+        "NSS_HTTP_API_ENDPOINT" => "https://attacker.example",
+        "NSS_HTTP_API_DAEMON_SOCKET" => "/tmp/attacker.sock",
+        "NSS_HTTP_API_CA_BUNDLE" => "/tmp/attacker.pem",
+        "NSS_HTTP_API_PUBLIC_ROOTS" => "false",
+        "NSS_HTTP_API_PINNED_PUBKEYS" => "sha256//AAAA",
+        "NSS_HTTP_API_URL_GETPWNAM" => "https://attacker.example/{name}",
+        "NSS_HTTP_API_UID_RANGES" => "0-65535",
+        "NSS_HTTP_API_RESERVED_NAMES" => "",
+        "NSS_HTTP_API_SHADOW_UIDS" => "1000",
+        "NSS_HTTP_API_SHADOW_EXECUTABLES" => "/tmp/attacker",
+        "NSS_HTTP_API_LOG_FILE" => "/etc/shadow",
+        "NSS_HTTP_API_SNAPSHOT_DIR" => "/tmp/attacker",
+        "NSS_HTTP_API_AUTH" => "bearer",
+        "NSS_HTTP_API_TOKEN" => "stolen",
+        "NSS_HTTP_API_LOG_LEVEL" => "debug",
+        "NSS_HTTP_API_TIMEOUT_MS" => "1",
+        "NSS_HTTP_API_ENDPOINT_ORDER" => "random",
+        _ => return None,
+    };
+    Some(value.to_string())
+}
+
+fn assert_defaults_kept(config: &Config) {
+    let defaults = Config::default();
+    assert!(config.endpoints.is_empty());
+    assert_eq!(config.daemon_socket, defaults.daemon_socket);
+    assert_eq!(config.ca_bundle, None);
+    assert!(config.public_roots);
+    assert!(config.pinned_pubkeys.is_empty());
+    assert!(config.url_templates.is_empty());
+    assert_eq!(config.uid_ranges, defaults.uid_ranges);
+    assert_eq!(config.reserved_names, defaults.reserved_names);
+    assert_eq!(config.shadow_uids, defaults.shadow_uids);
+    assert!(config.shadow_executables.is_empty());
+    assert_eq!(config.log_file, None);
+    assert_eq!(config.snapshot_dir, None);
+    assert_eq!(config.auth, defaults.auth);
+}
+
+#[test]
+fn secure_processes_ignore_the_environment() {
+    let config = Config::load_from(hostile, true);
+    assert_defaults_kept(&config);
+    assert_eq!(config.log_level, Config::default().log_level);
+    assert_eq!(config.request_timeout, Config::default().request_timeout);
+    assert_eq!(config.endpoint_order, EndpointOrder::Ordered);
+}
+
+#[test]
+fn the_environment_only_tunes() {
+    let config = Config::load_from(hostile, false);
+    assert_defaults_kept(&config);
+    assert_eq!(config.log_level, Some(Level::Debug));
+    assert_eq!(config.request_timeout, Duration::from_millis(1));
+    assert_eq!(config.endpoint_order, EndpointOrder::Random);
+}
+
+#[test]
+fn the_config_file_sets_everything() {
+    common::use_config(
+        "load",
+        "NSS_HTTP_API_ENDPOINT=https://nss.example\n\
+         NSS_HTTP_API_UID_RANGES=5000-5999\n\
+         NSS_HTTP_API_LOG_FILE=/var/log/nya.log\n\
+         NSS_HTTP_API_TIMEOUT_MS=2000\n",
+    );
+    let path = std::env::var("NSS_HTTP_API_CONFIG").unwrap();
+    let env = |key: &str| match key {
+        "NSS_HTTP_API_CONFIG" => Some(path.clone()),
+        "NSS_HTTP_API_ENDPOINT" => Some("https://attacker.example".to_string()),
+        "NSS_HTTP_API_TIMEOUT_MS" => Some("1".to_string()),
+        _ => None,
+    };
+
+    let config = Config::load_from(env, false);
+    assert_eq!(config.endpoints, vec!["https://nss.example".to_string()]);
+    // Policy only counts when the file is owned by root
+    let uid_ranges = match unsafe { libc::geteuid() } {
+        0 => vec![(5000, 5999)],
+        _ => Config::default().uid_ranges,
+    };
+    assert_eq!(config.uid_ranges, uid_ranges);
+    assert_eq!(config.log_file, Some(PathBuf::from("/var/log/nya.log")));
+    assert_eq!(config.request_timeout, Duration::from_millis(2000));
+
+    // Only the root-owned default file counts once the process is secure
+    let config = Config::load_from(env, true);
+    assert_ne!(config.endpoints, vec!["https://nss.example".to_string()]);
+}
@@ -0,0 +1,407 @@
+mod common;
+
+use std::{
+    fs,
+    io::{Read, Write},
+    os::unix::{
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    path::PathBuf,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{passwd_json, MockServer};
+use nss_nya::caller::Caller;
+use nss_nya::config::Config;
+use nss_nya::daemon::Daemon;
+use nss_nya::protocol::{self, NetworkReqResponse, Request};
+
+fn config(server: &MockServer) -> Config {
+    Config {
+        endpoints: vec![server.url.clone()],
+        ..Default::default()
+    }
+}
+
+fn socket_path(name: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("nya-daemon-{}-{}.sock", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn caller(euid: u32) -> Caller {
+    Caller {
+        uid: euid,
+        euid,
+        gid: euid,
+        pid: 4242,
+        ppid: 1,
+        cap_eff: 0,
+        exe: None,
+    }
+}
+
+fn lookup(op: &str, key: &str, value: &str) -> Request {
+    Request {
+        op: op.to_string(),
+        params: vec![(key.to_string(), value.to_string())],
+    }
+}
+
+#[test]
+fn lookups_round_trip_over_the_socket() {
+    let server = MockServer::start(|_| (200, passwd_json("alice", 1000)));
+    let path = socket_path("round-trip");
+    let listener = UnixListener::bind(&path).unwrap();
+    let daemon = Arc::new(Daemon::new(config(&server)));
+    thread::spawn(move || daemon.serve(listener));
+
+    let response = protocol::request(
+        &path,
+        Duration::from_secs(5),
+        "getpwnam",
+        &[("name", "alice".to_string())],
+    );
+    let _ = fs::remove_file(&path);
+
+    match response {
+        NetworkReqResponse::Success(entry) => assert_eq!(entry["name"], "alice"),
+        other => panic!("unexpected response {:?}", other),
+    }
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].url, "/passwd?name=alice");
+    // The daemon forwards the ids of the process on the other end of the socket
+    assert_eq!(requests[0].headers["x-pid"], std::process::id().to_string());
+}
+
+#[test]
+fn missing_daemon_is_unavailable() {
+    let path = socket_path("missing");
+    let response = protocol::request(&path, Duration::from_secs(1), "getpwent", &[]);
+    assert_eq!(response, NetworkReqResponse::Unavail);
+}
+
+#[test]
+fn silent_daemon_times_out() {
+    let path = socket_path("silent");
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        let _held = listener.accept();
+        thread::sleep(Duration::from_secs(5));
+    });
+
+    let started = Instant::now();
+    let response = protocol::request(&path, Duration::from_millis(200), "getpwent", &[]);
+    let _ = fs::remove_file(&path);
+
+    assert_eq!(response, NetworkReqResponse::TimeOut);
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn answers_are_cached() {
+    let server = MockServer::start(|request| match request.url.as_str() {
+        "/passwd?name=alice" => (200, passwd_json("alice", 1000)),
+        _ => (404, String::new()),
+    });
+    let daemon = Daemon::new(config(&server));
+
+    for _ in 0..3 {
+        assert!(matches!(
+            daemon.handle(&caller(1000), &lookup("getpwnam", "name", "alice")),
+            NetworkReqResponse::Success(_)
+        ));
+        assert_eq!(
+            daemon.handle(&caller(1000), &lookup("getpwnam", "name", "bob")),
+            NetworkReqResponse::NotFound
+        );
+    }
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn host_ttls_are_capped_by_the_cache_ttl() {
+    let server = MockServer::start(|_| {
+        (
+            200,
+            r#"{"name":"pinned.internal","addresses":["10.0.0.1"],"ttl":4294967295}"#.to_string(),
+        )
+    });
+    let daemon = Daemon::new(Config {
+        cache_ttl: Duration::from_millis(200),
+        ..config(&server)
+    });
+
+    let lookup = Request {
+        op: "gethostbyname".to_string(),
+        params: vec![
+            ("name".to_string(), "pinned.internal".to_string()),
+            ("family".to_string(), "ipv4".to_string()),
+        ],
+    };
+    for _ in 0..2 {
+        assert!(matches!(
+            daemon.handle(&caller(1000), &lookup),
+            NetworkReqResponse::Success(_)
+        ));
+    }
+    assert_eq!(server.requests().len(), 1);
+
+    thread::sleep(Duration::from_millis(300));
+    daemon.handle(&caller(1000), &lookup);
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn answers_are_not_shared_between_callers() {
+    let server = MockServer::start(|_| (200, passwd_json("alice", 1000)));
+    let daemon = Daemon::new(config(&server));
+
+    for euid in [1000, 1000, 1001, 0] {
+        assert!(matches!(
+            daemon.handle(&caller(euid), &lookup("getpwnam", "name", "alice")),
+            NetworkReqResponse::Success(_)
+        ));
+    }
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[test]
+fn peers_are_described_by_their_credentials_only() {
+    // Whatever pid 1 is running, none of it is attributed to the peer
+    let caller = Caller::from_peer(1, 1000, 1000);
+    assert_eq!(caller.uid, 1000);
+    assert_eq!(caller.euid, 1000);
+    assert_eq!(caller.gid, 1000);
+    assert_eq!(caller.cap_eff, 0);
+    assert_eq!(caller.exe, None);
+}
+
+#[test]
+fn connections_are_capped() {
+    let server = MockServer::start(|_| (200, passwd_json("alice", 1000)));
+    let path = socket_path("capped");
+    let listener = UnixListener::bind(&path).unwrap();
+    let daemon = Arc::new(Daemon::new(Config {
+        daemon_max_connections: 1,
+        deadline: Duration::from_millis(500),
+        ..config(&server)
+    }));
+    thread::spawn(move || daemon.serve(listener));
+
+    let lookup =
+        |timeout| protocol::request(&path, timeout, "getpwnam", &[("name", "alice".to_string())]);
+    // A silent client holds the only slot
+    let held = UnixStream::connect(&path).unwrap();
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(
+        lookup(Duration::from_millis(300)),
+        NetworkReqResponse::TimeOut
+    );
+
+    drop(held);
+    let response = lookup(Duration::from_secs(5));
+    let _ = fs::remove_file(&path);
+    assert!(matches!(response, NetworkReqResponse::Success(_)));
+}
+
+#[test]
+fn slow_peers_do_not_keep_their_connection() {
+    let server = MockServer::start(|_| (200, passwd_json("alice", 1000)));
+    let path = socket_path("slow");
+    let listener = UnixListener::bind(&path).unwrap();
+    let daemon = Arc::new(Daemon::new(Config {
+        deadline: Duration::from_millis(100),
+        ..config(&server)
+    }));
+    thread::spawn(move || daemon.serve(listener));
+
+    // One byte at a time, each well within a per-read timeout
+    let mut stream = UnixStream::connect(&path).unwrap();
+    let mut writer = stream.try_clone().unwrap();
+    thread::spawn(move || {
+        let _ = writer.write_all(&64u32.to_be_bytes());
+        for _ in 0..64 {
+            thread::sleep(Duration::from_millis(300));
+            if writer.write_all(b" ").is_err() {
+                break;
+            }
+        }
+    });
+
+    let started = Instant::now();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    let closed = matches!(stream.read(&mut [0; 1]), Ok(0) | Err(_));
+    let _ = fs::remove_file(&path);
+    assert!(closed);
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+#[test]
+fn connections_are_capped_per_uid() {
+    let server = MockServer::start(|_| (200, passwd_json("alice", 1000)));
+    let path = socket_path("per-uid");
+    let listener = UnixListener::bind(&path).unwrap();
+    let daemon = Arc::new(Daemon::new(Config {
+        daemon_max_connections_per_uid: 2,
+        ..config(&server)
+    }));
+    thread::spawn(move || daemon.serve(listener));
+
+    let held = [
+        UnixStream::connect(&path).unwrap(),
+        UnixStream::connect(&path).unwrap(),
+    ];
+    thread::sleep(Duration::from_millis(100));
+    // The third connection of the same uid is closed unanswered
+    let mut refused = UnixStream::connect(&path).unwrap();
+    refused
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    assert_eq!(refused.read(&mut [0; 1]).unwrap(), 0);
+
+    drop(held);
+    thread::sleep(Duration::from_millis(100));
+    let response = protocol::request(
+        &path,
+        Duration::from_secs(5),
+        "getpwnam",
+        &[("name", "alice".to_string())],
+    );
+    let _ = fs::remove_file(&path);
+    assert!(matches!(response, NetworkReqResponse::Success(_)));
+}
+
+#[test]
+fn frames_are_read_as_they_arrive() {
+    let mut frame = Vec::new();
+    protocol::write_frame(&mut frame, &lookup("getpwnam", "name", "alice")).unwrap();
+    let request: Request = protocol::read_frame(&mut frame.as_slice()).unwrap();
+    assert_eq!(request, lookup("getpwnam", "name", "alice"));
+
+    // A length close to the limit is not trusted with an allocation
+    let mut truncated = ((16 << 20) as u32).to_be_bytes().to_vec();
+    truncated.extend_from_slice(b"{}");
+    let err = protocol::read_frame::<_, Request>(&mut truncated.as_slice()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn full_backlogs_time_out() {
+    let path = socket_path("backlog");
+    let listener = UnixListener::bind(&path).unwrap();
+    // Never accepted, and a backlog of zero only queues the first connection
+    assert_eq!(unsafe { libc::listen(listener.as_raw_fd(), 0) }, 0);
+    let _queued = UnixStream::connect(&path).unwrap();
+
+    let started = Instant::now();
+    let response = protocol::request(&path, Duration::from_millis(200), "getpwent", &[]);
+    let _ = fs::remove_file(&path);
+
+    assert_eq!(response, NetworkReqResponse::TimeOut);
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn errors_are_not_cached() {
+    let server = MockServer::start(|_| (503, String::new()));
+    let daemon = Daemon::new(config(&server));
+
+    for _ in 0..2 {
+        assert_eq!(
+            daemon.handle(&caller(1000), &lookup("getpwnam", "name", "alice")),
+            NetworkReqResponse::Unavail
+        );
+    }
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn shadow_requires_root_peer() {
+    let server = MockServer::start(|_| {
+        (
+            200,
+            r#"{"name":"alice","passwd":"!","last_change":0,"change_min_days":0,"change_max_days":0,"change_warn_days":0,"change_inactive_days":0,"expire_date":0,"reserved":0}"#
+                .to_string(),
+        )
+    });
+    let daemon = Daemon::new(config(&server));
+
+    assert_eq!(
+        daemon.handle(&caller(1000), &lookup("getspnam", "name", "alice")),
+        NetworkReqResponse::NotFound
+    );
+    assert!(server.requests().is_empty());
+
+    assert!(matches!(
+        daemon.handle(&caller(0), &lookup("getspnam", "name", "alice")),
+        NetworkReqResponse::Success(_)
+    ));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn shadow_is_gated_on_peer_credentials() {
+    let server = MockServer::start(|_| {
+        (
+            200,
+            r#"{"name":"alice","passwd":"!","last_change":0}"#.to_string(),
+        )
+    });
+    let daemon = Daemon::new(Config {
+        shadow_uids: vec![990],
+        ..config(&server)
+    });
+    let pid = std::process::id() as i32;
+
+    // This process may well be root with every capability, which must not
+    // rub off on a peer claiming its pid
+    assert_eq!(
+        daemon.handle(
+            &Caller::from_peer(pid, 1000, 1000),
+            &lookup("getspnam", "name", "alice")
+        ),
+        NetworkReqResponse::NotFound
+    );
+    assert!(matches!(
+        daemon.handle(
+            &Caller::from_peer(pid, 990, 990),
+            &lookup("getspnam", "name", "alice")
+        ),
+        NetworkReqResponse::Success(_)
+    ));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn daemon_socket_is_configurable() {
+    let config = Config::from_lookup(|key| match key {
+        "NSS_HTTP_API_DAEMON_SOCKET" => Some("/tmp/nya.sock".to_string()),
+        "NSS_HTTP_API_CACHE_TTL" => Some("120".to_string()),
+        "NSS_HTTP_API_CACHE_NEGATIVE_TTL" => Some("0".to_string()),
+        "NSS_HTTP_API_SNAPSHOT_SYNC_INTERVAL" => Some("600".to_string()),
+        "NSS_HTTP_API_DAEMON_SOCKET_MODE" => Some("0666".to_string()),
+        "NSS_HTTP_API_DAEMON_SOCKET_GROUP" => Some("nya".to_string()),
+        "NSS_HTTP_API_DAEMON_MAX_CONNECTIONS" => Some("8".to_string()),
+        "NSS_HTTP_API_DAEMON_MAX_CONNECTIONS_PER_UID" => Some("2".to_string()),
+        _ => None,
+    });
+    assert_eq!(config.daemon_socket, Some(PathBuf::from("/tmp/nya.sock")));
+    assert_eq!(config.daemon_socket_mode, 0o666);
+    assert_eq!(config.daemon_socket_group.as_deref(), Some("nya"));
+    assert_eq!(config.daemon_max_connections, 8);
+    assert_eq!(config.daemon_max_connections_per_uid, 2);
+    assert_eq!(config.cache_ttl, Duration::from_secs(120));
+    assert_eq!(config.cache_negative_ttl, Duration::ZERO);
+    assert_eq!(
+        config.snapshot_sync_interval,
+        Some(Duration::from_secs(600))
+    );
+}
@@ -0,0 +1,42 @@
+use std::path::PathBuf;
+
+use libnss::build::Exports;
+use libnss::database::Database;
+use libnss::loader::Module;
+
+/// libnss_nya.so, which cargo builds next to the test binaries.
+fn library() -> PathBuf {
+    let mut path = std::env::current_exe().unwrap();
+    path.pop();
+    if path.ends_with("deps") {
+        path.pop();
+    }
+    path.join("libnss_nya.so")
+}
+
+#[test]
+fn only_nss_functions_are_exported() {
+    let exports = Exports::scan(concat!(env!("CARGO_MANIFEST_DIR"), "/src")).unwrap();
+    assert_eq!(exports.name(), "nya");
+    assert_eq!(
+        exports.databases(),
+        &[
+            Database::Passwd,
+            Database::Group,
+            Database::Shadow,
+            Database::Hosts,
+            Database::Initgroups
+        ]
+    );
+
+    assert!(exports.symbols().contains(&"_nss_nya_init".to_string()));
+
+    let check = exports.check(library()).unwrap();
+    assert!(check.is_ok(), "{:?}", check);
+
+    // glibc finds every function it looks up for the registered databases
+    let module = Module::open(library(), "nya").unwrap();
+    for database in exports.databases() {
+        assert!(module.missing(*database).is_empty(), "{:?}", database);
+    }
+}
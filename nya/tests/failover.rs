@@ -0,0 +1,167 @@
+mod common;
+
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{passwd_json, MockServer};
+use nss_nya::client::{ApiClient, NetworkReqResponse};
+use nss_nya::config::{Config, EndpointOrder};
+
+/// Returns a URL nothing listens on.
+fn closed_endpoint() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    format!("http://{}", listener.local_addr().unwrap())
+}
+
+fn lookup(client: &ApiClient) -> NetworkReqResponse {
+    client.request("getpwuid", &[("uid", "1000".to_string())])
+}
+
+#[test]
+fn fails_over_to_next_endpoint() {
+    let server = MockServer::start(|_| (200, passwd_json("alice", 1000)));
+    let client = ApiClient::new(Config {
+        endpoints: vec![closed_endpoint(), server.url.clone()],
+        ..Default::default()
+    });
+
+    assert!(matches!(lookup(&client), NetworkReqResponse::Success(_)));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn random_order_reaches_healthy_endpoint() {
+    let server = MockServer::start(|_| (200, passwd_json("alice", 1000)));
+    let client = ApiClient::new(Config {
+        endpoints: vec![closed_endpoint(), server.url.clone(), closed_endpoint()],
+        endpoint_order: EndpointOrder::Random,
+        failure_threshold: 100,
+        ..Default::default()
+    });
+
+    for _ in 0..10 {
+        assert!(matches!(lookup(&client), NetworkReqResponse::Success(_)));
+    }
+}
+
+#[test]
+fn not_found_does_not_fail_over() {
+    let first = MockServer::start(|_| (404, "{}".to_string()));
+    let second = MockServer::start(|_| (200, passwd_json("alice", 1000)));
+    let client = ApiClient::new(Config {
+        endpoints: vec![first.url.clone(), second.url.clone()],
+        ..Default::default()
+    });
+
+    assert!(matches!(lookup(&client), NetworkReqResponse::NotFound));
+    assert!(second.requests().is_empty());
+}
+
+#[test]
+fn open_circuit_returns_unavail_without_requests() {
+    let server = MockServer::start(|_| (503, "{}".to_string()));
+    let client = ApiClient::new(Config {
+        endpoints: vec![server.url.clone()],
+        failure_threshold: 2,
+        circuit_cooldown: 3600,
+        ..Default::default()
+    });
+
+    assert!(matches!(lookup(&client), NetworkReqResponse::Unavail));
+    assert!(matches!(lookup(&client), NetworkReqResponse::Unavail));
+    assert_eq!(server.requests().len(), 2);
+
+    assert!(matches!(lookup(&client), NetworkReqResponse::Unavail));
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn half_open_probe_closes_circuit() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let server = MockServer::start(move |_| match counter.fetch_add(1, Ordering::SeqCst) {
+        0 => (503, "{}".to_string()),
+        _ => (200, passwd_json("alice", 1000)),
+    });
+    let client = ApiClient::new(Config {
+        endpoints: vec![server.url.clone()],
+        failure_threshold: 1,
+        circuit_cooldown: 0,
+        ..Default::default()
+    });
+
+    assert!(matches!(lookup(&client), NetworkReqResponse::Unavail));
+    assert!(matches!(lookup(&client), NetworkReqResponse::Success(_)));
+    assert!(matches!(lookup(&client), NetworkReqResponse::Success(_)));
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+}
+
+fn slow_server() -> MockServer {
+    MockServer::start(|_| {
+        thread::sleep(Duration::from_millis(1500));
+        (200, passwd_json("alice", 1000))
+    })
+}
+
+#[test]
+fn slow_endpoint_times_out_and_fails_over() {
+    let slow = slow_server();
+    let fast = MockServer::start(|_| (200, passwd_json("alice", 1000)));
+    let client = ApiClient::new(Config {
+        endpoints: vec![slow.url.clone(), fast.url.clone()],
+        request_timeout: Duration::from_millis(200),
+        ..Default::default()
+    });
+
+    let started = Instant::now();
+    assert!(matches!(lookup(&client), NetworkReqResponse::Success(_)));
+    assert!(started.elapsed() < Duration::from_millis(1000));
+}
+
+#[test]
+fn operation_timeout_overrides_database_timeout() {
+    let config = Config {
+        request_timeouts: [
+            ("passwd".to_string(), Duration::from_millis(300)),
+            ("getpwent".to_string(), Duration::from_millis(2000)),
+        ]
+        .into_iter()
+        .collect(),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        config.request_timeout_for("getpwent"),
+        Duration::from_millis(2000)
+    );
+    assert_eq!(
+        config.request_timeout_for("getpwnam"),
+        Duration::from_millis(300)
+    );
+    assert_eq!(
+        config.request_timeout_for("getgrnam"),
+        config.request_timeout
+    );
+}
+
+#[test]
+fn deadline_bounds_the_failover_loop() {
+    let first = slow_server();
+    let second = slow_server();
+    let third = slow_server();
+    let client = ApiClient::new(Config {
+        endpoints: vec![first.url.clone(), second.url.clone(), third.url.clone()],
+        request_timeout: Duration::from_millis(300),
+        deadline: Duration::from_millis(450),
+        failure_threshold: 100,
+        ..Default::default()
+    });
+
+    let started = Instant::now();
+    assert!(matches!(lookup(&client), NetworkReqResponse::TimeOut));
+    assert!(started.elapsed() < Duration::from_millis(900));
+    assert!(third.requests().is_empty());
+}
@@ -0,0 +1,264 @@
+mod common;
+
+use std::{
+    ffi::{CStr, CString},
+    net::{Ipv4Addr, Ipv6Addr},
+    ptr,
+    sync::OnceLock,
+};
+
+use common::MockServer;
+use libc::{c_char, c_int, size_t, socklen_t};
+use libnss::host::CHost;
+
+// Links the module so the hook symbols below resolve
+extern crate nss_nya;
+
+extern "C" {
+    fn _nss_nya_gethostbyname3_r(
+        name: *const c_char,
+        family: c_int,
+        result: *mut CHost,
+        buf: *mut c_char,
+        buflen: size_t,
+        errnop: *mut c_int,
+        h_errnop: *mut c_int,
+        ttlp: *mut i32,
+        canonp: *mut *const c_char,
+    ) -> c_int;
+    fn _nss_nya_gethostbyaddr_r(
+        addr: *const c_char,
+        len: socklen_t,
+        format: c_int,
+        result: *mut CHost,
+        buf: *mut c_char,
+        buflen: size_t,
+        errnop: *mut c_int,
+        h_errnop: *mut c_int,
+    ) -> c_int;
+}
+
+const NSS_STATUS_UNAVAIL: c_int = -1;
+const NSS_STATUS_NOTFOUND: c_int = 0;
+const NSS_STATUS_SUCCESS: c_int = 1;
+
+const WEB: &str = r#"{"name":"web.internal","aliases":["www.internal"],"addresses":["10.0.0.5","10.0.0.6","fd00::5"],"ttl":300}"#;
+
+/// The module reads its configuration once per process, so every test talks
+/// to the same server.
+fn server() -> &'static MockServer {
+    static SERVER: OnceLock<MockServer> = OnceLock::new();
+    SERVER.get_or_init(|| {
+        let server = MockServer::start(|request| match request.url.as_str() {
+            "/hosts?name=web.internal&family=ipv4"
+            | "/hosts?name=web.internal&family=ipv6"
+            | "/hosts?addr=10.0.0.6"
+            | "/hosts?addr=fd00%3A%3A5" => (200, WEB.to_string()),
+            "/hosts?name=v6only.internal&family=ipv4"
+            | "/hosts?name=v6only.internal&family=ipv6" => (
+                200,
+                r#"{"name":"v6only.internal","addresses":["fd00::7"],"ttl":60}"#.to_string(),
+            ),
+            "/hosts?name=volatile.internal&family=ipv4" => (
+                200,
+                r#"{"name":"volatile.internal","addresses":["10.0.0.9"],"ttl":0}"#.to_string(),
+            ),
+            _ => (404, String::new()),
+        });
+        common::use_config("hosts", &format!("NSS_HTTP_API_ENDPOINT={}\n", server.url));
+        server
+    })
+}
+
+struct Lookup {
+    status: c_int,
+    h_errno: c_int,
+    ttl: i32,
+    canon: Option<String>,
+    name: Option<String>,
+    aliases: Vec<String>,
+    family: c_int,
+    addresses: Vec<Vec<u8>>,
+}
+
+unsafe fn decode(status: c_int, host: &CHost) -> (Option<String>, Vec<String>, Vec<Vec<u8>>) {
+    if status != NSS_STATUS_SUCCESS {
+        return (None, vec![], vec![]);
+    }
+    let name = CStr::from_ptr(host.name).to_string_lossy().into_owned();
+    let mut aliases = vec![];
+    let mut alias = host.h_aliases;
+    while !(*alias).is_null() {
+        aliases.push(CStr::from_ptr(*alias).to_string_lossy().into_owned());
+        alias = alias.offset(1);
+    }
+    let mut addresses = vec![];
+    let mut addr = host.h_addr_list;
+    while !(*addr).is_null() {
+        addresses
+            .push(std::slice::from_raw_parts(*addr as *const u8, host.h_length as usize).to_vec());
+        addr = addr.offset(1);
+    }
+    (Some(name), aliases, addresses)
+}
+
+fn by_name(name: &str, family: c_int) -> Lookup {
+    server();
+    let name = CString::new(name).unwrap();
+    // Garbage in the buffer must not leak into the result
+    let mut buf = vec![0xffu8 as c_char; 1024];
+    let mut host: CHost = unsafe { std::mem::zeroed() };
+    let (mut errno, mut h_errno, mut ttl) = (0, 0, -1);
+    let mut canon: *const c_char = ptr::null();
+    unsafe {
+        let status = _nss_nya_gethostbyname3_r(
+            name.as_ptr(),
+            family,
+            &mut host,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut errno,
+            &mut h_errno,
+            &mut ttl,
+            &mut canon,
+        );
+        let (name, aliases, addresses) = decode(status, &host);
+        Lookup {
+            status,
+            h_errno,
+            ttl,
+            canon: (!canon.is_null()).then(|| CStr::from_ptr(canon).to_string_lossy().into_owned()),
+            name,
+            aliases,
+            family: host.h_addrtype,
+            addresses,
+        }
+    }
+}
+
+fn by_addr(addr: &[u8], format: c_int) -> Lookup {
+    server();
+    let mut buf = vec![0xffu8 as c_char; 1024];
+    let mut host: CHost = unsafe { std::mem::zeroed() };
+    let (mut errno, mut h_errno) = (0, 0);
+    unsafe {
+        let status = _nss_nya_gethostbyaddr_r(
+            addr.as_ptr() as *const c_char,
+            addr.len() as socklen_t,
+            format,
+            &mut host,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut errno,
+            &mut h_errno,
+        );
+        let (name, aliases, addresses) = decode(status, &host);
+        Lookup {
+            status,
+            h_errno,
+            ttl: 0,
+            canon: None,
+            name,
+            aliases,
+            family: host.h_addrtype,
+            addresses,
+        }
+    }
+}
+
+#[test]
+fn name_resolves_per_family() {
+    let v4 = by_name("web.internal", libc::AF_INET);
+    assert_eq!(v4.status, NSS_STATUS_SUCCESS);
+    assert_eq!(v4.h_errno, 0);
+    assert_eq!(v4.name.as_deref(), Some("web.internal"));
+    assert_eq!(v4.canon.as_deref(), Some("web.internal"));
+    assert_eq!(v4.aliases, vec!["www.internal"]);
+    assert_eq!(v4.family, libc::AF_INET);
+    assert_eq!(
+        v4.addresses,
+        vec![
+            Ipv4Addr::new(10, 0, 0, 5).octets().to_vec(),
+            Ipv4Addr::new(10, 0, 0, 6).octets().to_vec()
+        ]
+    );
+
+    let v6 = by_name("web.internal", libc::AF_INET6);
+    assert_eq!(v6.status, NSS_STATUS_SUCCESS);
+    assert_eq!(v6.family, libc::AF_INET6);
+    assert_eq!(
+        v6.addresses,
+        vec!["fd00::5".parse::<Ipv6Addr>().unwrap().octets().to_vec()]
+    );
+}
+
+#[test]
+fn ttl_is_reported() {
+    assert_eq!(by_name("web.internal", libc::AF_INET).ttl, 300);
+    assert_eq!(by_name("v6only.internal", libc::AF_INET6).ttl, 60);
+}
+
+#[test]
+fn unspecified_family_falls_back_to_ipv6() {
+    let lookup = by_name("v6only.internal", libc::AF_UNSPEC);
+    assert_eq!(lookup.status, NSS_STATUS_SUCCESS);
+    assert_eq!(lookup.family, libc::AF_INET6);
+}
+
+#[test]
+fn unknown_name_is_not_found() {
+    let lookup = by_name("missing.internal", libc::AF_INET);
+    assert_eq!(lookup.status, NSS_STATUS_NOTFOUND);
+    assert_eq!(lookup.h_errno, 4 /* NO_DATA */);
+}
+
+#[test]
+fn reverse_lookups_resolve_both_families() {
+    let v4 = by_addr(&Ipv4Addr::new(10, 0, 0, 6).octets(), libc::AF_INET);
+    assert_eq!(v4.status, NSS_STATUS_SUCCESS);
+    assert_eq!(v4.name.as_deref(), Some("web.internal"));
+    assert_eq!(v4.family, libc::AF_INET);
+
+    let v6 = by_addr(
+        &"fd00::5".parse::<Ipv6Addr>().unwrap().octets(),
+        libc::AF_INET6,
+    );
+    assert_eq!(v6.status, NSS_STATUS_SUCCESS);
+    assert_eq!(v6.family, libc::AF_INET6);
+
+    let missing = by_addr(&Ipv4Addr::new(10, 9, 9, 9).octets(), libc::AF_INET);
+    assert_eq!(missing.status, NSS_STATUS_NOTFOUND);
+}
+
+#[test]
+fn api_host_is_never_resolved_through_the_api() {
+    let host = server()
+        .url
+        .trim_start_matches("http://")
+        .split(':')
+        .next()
+        .unwrap()
+        .to_string();
+    let before = server().requests().len();
+    let lookup = by_name(&host, libc::AF_INET);
+    assert_eq!(lookup.status, NSS_STATUS_UNAVAIL);
+    assert!(server().requests()[before..]
+        .iter()
+        .all(|r| !r.url.contains(&host)));
+}
+
+#[test]
+fn ttl_bounds_the_cache() {
+    let count = || {
+        server()
+            .requests()
+            .iter()
+            .filter(|r| r.url == "/hosts?name=volatile.internal&family=ipv4")
+            .count()
+    };
+    let before = count();
+    by_name("volatile.internal", libc::AF_INET);
+    by_name("volatile.internal", libc::AF_INET);
+    // A TTL of zero means the answer must not be cached at all
+    assert_eq!(count() - before, 2);
+}
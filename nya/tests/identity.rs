@@ -0,0 +1,154 @@
+mod common;
+
+use std::path::PathBuf;
+
+use common::{passwd_json, MockServer};
+use nss_nya::caller::Caller;
+use nss_nya::client::ApiClient;
+use nss_nya::config::Config;
+use nss_nya::identity::{self, Host};
+
+fn lookup(server: &MockServer, fields: Option<Vec<String>>) {
+    let mut config = Config {
+        endpoints: vec![server.url.clone()],
+        ..Default::default()
+    };
+    if let Some(fields) = fields {
+        config.identity_headers = fields;
+    }
+    ApiClient::new(config).request("getpwnam", &[("name", "alice".to_string())]);
+}
+
+#[test]
+fn every_identity_header_is_sent_by_default() {
+    let server = MockServer::start(|_| (200, passwd_json("alice", 1000)));
+    lookup(&server, None);
+
+    let headers = &server.requests()[0].headers;
+    let host = Host::current();
+    let exe = std::env::current_exe().unwrap();
+    assert_eq!(headers["x-uid"], unsafe { libc::getuid() }.to_string());
+    assert_eq!(headers["x-pid"], std::process::id().to_string());
+    assert_eq!(headers.get("x-hostname"), host.hostname.as_ref());
+    assert_eq!(headers.get("x-machine-id"), host.machine_id.as_ref());
+    assert_eq!(
+        headers["x-exe-name"],
+        exe.file_name().unwrap().to_string_lossy()
+    );
+    assert_eq!(headers["x-exe-path"], exe.to_string_lossy());
+    assert_eq!(headers["x-nss-op"], "getpwnam");
+    assert_eq!(headers["x-nya-version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(headers["x-libnss-version"], libnss::VERSION);
+}
+
+#[test]
+fn identity_headers_follow_each_caller() {
+    let server = MockServer::start(|_| (200, passwd_json("alice", 1000)));
+    let client = ApiClient::new(Config {
+        endpoints: vec![server.url.clone()],
+        ..Default::default()
+    });
+    for uid in [1000, 1001] {
+        client.request_as(
+            &Caller::from_peer(4242, uid, uid),
+            "getpwnam",
+            &[("name", "alice".to_string())],
+        );
+    }
+
+    let uids = server
+        .requests()
+        .iter()
+        .map(|request| request.headers["x-uid"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(uids, ["1000", "1001"]);
+}
+
+#[test]
+fn the_raw_machine_id_is_never_sent() {
+    let server = MockServer::start(|_| (200, passwd_json("alice", 1000)));
+    lookup(&server, None);
+
+    let headers = &server.requests()[0].headers;
+    if let Ok(raw) = std::fs::read_to_string("/etc/machine-id") {
+        let raw = raw.trim();
+        assert!(headers.values().all(|value| !value.contains(raw)));
+        assert_eq!(
+            headers.get("x-machine-id"),
+            identity::app_specific_id(raw).as_ref()
+        );
+    }
+}
+
+#[test]
+fn machine_ids_are_app_specific() {
+    assert_eq!(
+        identity::app_specific_id("0123456789abcdef0123456789abcdef").as_deref(),
+        Some("254ce5f627df4bfe8bfcca0d3b8b4616")
+    );
+    assert_eq!(identity::app_specific_id("not-a-machine-id"), None);
+    assert_eq!(identity::app_specific_id(""), None);
+}
+
+#[test]
+fn only_configured_headers_are_sent() {
+    let server = MockServer::start(|_| (200, passwd_json("alice", 1000)));
+    lookup(&server, Some(vec!["op".to_string(), "uid".to_string()]));
+
+    let headers = &server.requests()[0].headers;
+    assert_eq!(headers["x-nss-op"], "getpwnam");
+    assert!(headers.contains_key("x-uid"));
+    for absent in ["x-pid", "x-hostname", "x-machine-id", "x-exe-path"] {
+        assert!(!headers.contains_key(absent), "{} was sent", absent);
+    }
+}
+
+#[test]
+fn unknown_values_are_left_out() {
+    let caller = Caller {
+        uid: 1000,
+        euid: 1000,
+        gid: 1000,
+        pid: 4242,
+        ppid: 1,
+        cap_eff: 0,
+        exe: None,
+    };
+    let fields = vec![
+        "hostname".to_string(),
+        "exe".to_string(),
+        "ppid".to_string(),
+    ];
+    assert_eq!(
+        identity::headers(&fields, &Host::default(), &caller, "getgrnam"),
+        vec![("X-PPID", "1".to_string())]
+    );
+
+    let caller = Caller {
+        exe: Some(PathBuf::from("/usr/sbin/sshd")),
+        ..caller
+    };
+    assert_eq!(
+        identity::headers(&fields[1..2], &Host::default(), &caller, "getgrnam"),
+        vec![
+            ("X-Exe-Name", "sshd".to_string()),
+            ("X-Exe-Path", "/usr/sbin/sshd".to_string())
+        ]
+    );
+}
+
+#[test]
+fn identity_headers_are_configurable() {
+    let config = Config::from_lookup(|key| match key {
+        "NSS_HTTP_API_IDENTITY_HEADERS" => Some("Hostname, machine-id,bogus".to_string()),
+        _ => None,
+    });
+    assert_eq!(config.identity_headers, vec!["hostname", "machine-id"]);
+
+    let config = Config::from_lookup(|key| match key {
+        "NSS_HTTP_API_IDENTITY_HEADERS" => Some(String::new()),
+        _ => None,
+    });
+    assert!(config.identity_headers.is_empty());
+    assert_eq!(Config::default().identity_headers, identity::FIELDS);
+}
@@ -0,0 +1,148 @@
+mod common;
+
+use std::{ffi::CString, sync::OnceLock};
+
+use common::MockServer;
+use libc::{c_char, c_int, c_long, gid_t};
+
+// Links the module so the hook symbols below resolve
+extern crate nss_nya;
+
+extern "C" {
+    fn _nss_nya_initgroups_dyn(
+        name: *const c_char,
+        skipgroup: gid_t,
+        start: *mut c_long,
+        size: *mut c_long,
+        groupsp: *mut *mut gid_t,
+        limit: c_long,
+        errnop: *mut c_int,
+    ) -> c_int;
+}
+
+const NSS_STATUS_NOTFOUND: c_int = 0;
+const NSS_STATUS_SUCCESS: c_int = 1;
+
+fn group_json(name: &str, gid: u32, members: &[&str]) -> String {
+    let members = members
+        .iter()
+        .map(|m| format!("\"{}\"", m))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(r#"{{"name":"{name}","passwd":"x","gid":{gid},"members":[{members}]}}"#)
+}
+
+/// The module reads its configuration once per process, so every test talks
+/// to the same server.
+fn server() -> &'static MockServer {
+    static SERVER: OnceLock<MockServer> = OnceLock::new();
+    SERVER.get_or_init(|| {
+        let server = MockServer::start(|request| match request.url.as_str() {
+            "/initgroups?name=alice" => (
+                200,
+                format!(
+                    "[{},{},{},{},{}]",
+                    group_json("alice", 201000, &[]),
+                    group_json("nya-devs", 200010, &["alice"]),
+                    // Rejected by the default gid policy
+                    group_json("wheel", 10, &["alice"]),
+                    group_json("nya-media", 200029, &["alice", "bob"]),
+                    group_json("nya-builders", 200999, &["alice"]),
+                ),
+            ),
+            // Valid JSON, but not a list of groups
+            "/initgroups?name=broken" => (200, r#"[{"name":"broken","gid":"x"}]"#.to_string()),
+            _ => (404, String::new()),
+        });
+        common::use_config(
+            "initgroups",
+            &format!("NSS_HTTP_API_ENDPOINT={}\n", server.url),
+        );
+        server
+    })
+}
+
+/// Calls the module the way glibc's `getgrouplist` does: the primary group
+/// is already in the array and is passed as `skipgroup`.
+fn getgrouplist(user: &str, group: gid_t, limit: c_long) -> (c_int, Vec<gid_t>) {
+    server();
+    let name = CString::new(user).unwrap();
+    let mut start: c_long = 1;
+    let mut size: c_long = 1;
+    let mut errno: c_int = 0;
+    unsafe {
+        let mut groups = libc::malloc(std::mem::size_of::<gid_t>()) as *mut gid_t;
+        *groups = group;
+        let status = _nss_nya_initgroups_dyn(
+            name.as_ptr(),
+            group,
+            &mut start,
+            &mut size,
+            &mut groups,
+            limit,
+            &mut errno,
+        );
+        assert!(start <= size);
+        let list = std::slice::from_raw_parts(groups, start as usize).to_vec();
+        libc::free(groups as *mut libc::c_void);
+        (status, list)
+    }
+}
+
+#[test]
+fn supplementary_groups_are_returned() {
+    let (status, groups) = getgrouplist("alice", 201000, -1);
+    assert_eq!(status, NSS_STATUS_SUCCESS);
+    assert_eq!(groups, vec![201000, 200010, 200029, 200999]);
+}
+
+#[test]
+fn skipgroup_is_not_repeated() {
+    let (status, groups) = getgrouplist("alice", 200029, -1);
+    assert_eq!(status, NSS_STATUS_SUCCESS);
+    assert_eq!(groups, vec![200029, 201000, 200010, 200999]);
+}
+
+#[test]
+fn limit_caps_the_list() {
+    let (status, groups) = getgrouplist("alice", 201000, 3);
+    assert_eq!(status, NSS_STATUS_SUCCESS);
+    assert_eq!(groups, vec![201000, 200010, 200029]);
+
+    // Already at the limit: nothing is added and nothing underflows
+    let (status, groups) = getgrouplist("alice", 201000, 1);
+    assert_eq!(status, NSS_STATUS_SUCCESS);
+    assert_eq!(groups, vec![201000]);
+}
+
+#[test]
+fn unknown_user_is_not_found() {
+    let (status, groups) = getgrouplist("mallory", 100, -1);
+    assert_eq!(status, NSS_STATUS_NOTFOUND);
+    assert_eq!(groups, vec![100]);
+}
+
+#[test]
+fn invalid_answers_are_not_found() {
+    let (status, groups) = getgrouplist("broken", 100, -1);
+    assert_eq!(status, NSS_STATUS_NOTFOUND);
+    assert_eq!(groups, vec![100]);
+}
+
+#[test]
+fn one_request_per_lookup() {
+    let before = server()
+        .requests()
+        .iter()
+        .filter(|r| r.url == "/initgroups?name=alice")
+        .count();
+    getgrouplist("alice", 201000, -1);
+    let after = server()
+        .requests()
+        .iter()
+        .filter(|r| r.url == "/initgroups?name=alice")
+        .count();
+    // Served by a single call instead of an enumeration of every group
+    assert!(after - before <= 1);
+    assert!(server().requests().iter().all(|r| r.url != "/group"));
+}
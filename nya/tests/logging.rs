@@ -0,0 +1,151 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use nss_nya::config::Config;
+use nss_nya::logging::{format_record, redact_entry, redact_header, Level, Logger, Lookup};
+use serde_json::json;
+
+fn log_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("nya-log-{}-{}.log", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn records_are_key_value_pairs() {
+    assert_eq!(
+        format_record(Level::Warn, &[("op", &"getpwnam"), ("key", &"alice")]),
+        "level=warn op=getpwnam key=alice"
+    );
+    assert_eq!(
+        format_record(
+            Level::Info,
+            &[("msg", &"two words"), ("key", &"a\"b"), ("empty", &"")]
+        ),
+        r#"level=info msg="two words" key="a\"b" empty="""#
+    );
+    // A value cannot forge extra fields or records
+    assert_eq!(
+        format_record(Level::Debug, &[("key", &"x\nlevel=error")]),
+        r#"level=debug key="x\nlevel=error""#
+    );
+}
+
+#[test]
+fn lookups_are_written_to_the_configured_file() {
+    let path = log_file("lookup");
+    let logger = Logger::new(Some(Level::Debug), Some(path.clone()));
+    logger.lookup(&Lookup {
+        op: "getpwnam",
+        key: "alice",
+        endpoint: "https://nya.example",
+        latency: Duration::from_millis(42),
+        outcome: "success",
+    });
+
+    let contents = fs::read_to_string(&path).unwrap();
+    let _ = fs::remove_file(&path);
+    assert!(contents.ends_with(
+        "level=debug op=getpwnam key=alice endpoint=https://nya.example latency_ms=42 outcome=success\n"
+    ));
+    assert!(contents.contains(&format!("nss_nya[{}]", std::process::id())));
+}
+
+#[test]
+fn levels_filter_records() {
+    let path = log_file("levels");
+    let logger = Logger::new(Some(Level::Warn), Some(path.clone()));
+    logger.lookup(&Lookup {
+        op: "getpwnam",
+        key: "alice",
+        endpoint: "https://nya.example",
+        latency: Duration::from_millis(1),
+        outcome: "success",
+    });
+    logger.lookup(&Lookup {
+        op: "getpwnam",
+        key: "bob",
+        endpoint: "https://nya.example",
+        latency: Duration::from_millis(1),
+        outcome: "timeout",
+    });
+    let disabled = Logger::new(None, Some(path.clone()));
+    disabled.log(Level::Error, &[("msg", &"dropped")]);
+
+    let contents = fs::read_to_string(&path).unwrap();
+    let _ = fs::remove_file(&path);
+    assert_eq!(contents.lines().count(), 1);
+    assert!(contents.contains("level=warn op=getpwnam key=bob"));
+}
+
+#[test]
+fn secrets_are_redacted() {
+    assert_eq!(
+        redact_header("Authorization", "Bearer s3cret"),
+        "<redacted>"
+    );
+    assert_eq!(redact_header("X-Nya-Signature", "abcdef"), "<redacted>");
+    assert_eq!(redact_header("X-Nya-Key-Id", "key-1"), "key-1");
+
+    let shadow = json!([{"name": "alice", "passwd": "$6$salt$hash", "last_change": 19000}]);
+    assert_eq!(
+        redact_entry(&shadow),
+        json!([{"name": "alice", "passwd": "<redacted>", "last_change": 19000}])
+    );
+}
+
+#[test]
+fn log_files_are_only_created_when_allowed() {
+    let path = log_file("create");
+    let logger = Logger::new(Some(Level::Warn), Some(path.clone())).create(false);
+    logger.log(Level::Error, &[("msg", &"first")]);
+    assert!(!path.exists());
+
+    fs::write(&path, "").unwrap();
+    logger.log(Level::Error, &[("msg", &"second")]);
+    let contents = fs::read_to_string(&path).unwrap();
+    assert!(contents.ends_with("level=error msg=second\n"));
+
+    // Symlinks are never followed, wherever they point
+    let link = log_file("create-link");
+    std::os::unix::fs::symlink(&path, &link).unwrap();
+    Logger::new(Some(Level::Warn), Some(link.clone())).log(Level::Error, &[("msg", &"third")]);
+    assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+
+    let _ = fs::remove_file(&link);
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn log_files_never_come_from_the_environment() {
+    let env = |key: &str| match key {
+        "NSS_HTTP_API_CONFIG" => Some("/nonexistent/nss_nya.conf".to_string()),
+        "NSS_HTTP_API_LOG_FILE" => Some("/etc/shadow".to_string()),
+        _ => None,
+    };
+    assert_eq!(Config::load_from(env, false).log_file, None);
+    assert_eq!(Config::load_from(env, true).log_file, None);
+}
+
+#[test]
+fn log_settings_are_configurable() {
+    let config = Config::from_lookup(|key| match key {
+        "NSS_HTTP_API_LOG_LEVEL" => Some("info".to_string()),
+        "NSS_HTTP_API_LOG_FILE" => Some("/var/log/nss_nya.log".to_string()),
+        _ => None,
+    });
+    assert_eq!(config.log_level, Some(Level::Info));
+    assert_eq!(config.log_file, Some(PathBuf::from("/var/log/nss_nya.log")));
+
+    let config = Config::from_lookup(|key| match key {
+        "NSS_HTTP_API_DEBUG" => Some("true".to_string()),
+        _ => None,
+    });
+    assert_eq!(config.log_level, Some(Level::Debug));
+
+    let config = Config::from_lookup(|key| match key {
+        "NSS_HTTP_API_LOG_LEVEL" => Some("off".to_string()),
+        _ => None,
+    });
+    assert_eq!(config.log_level, None);
+    assert_eq!(Config::default().log_level, Some(Level::Warn));
+}
@@ -0,0 +1,214 @@
+use std::{fs, path::PathBuf};
+
+use libnss::{group::Group, passwd::Passwd, shadow::Shadow};
+use nss_nya::config::Config;
+use nss_nya::policy::Policy;
+
+struct LocalFiles {
+    passwd: PathBuf,
+    group: PathBuf,
+}
+
+impl LocalFiles {
+    fn new(name: &str) -> LocalFiles {
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let files = LocalFiles {
+            passwd: dir.join(format!("nya-policy-{}-{}.passwd", name, id)),
+            group: dir.join(format!("nya-policy-{}-{}.group", name, id)),
+        };
+        fs::write(
+            &files.passwd,
+            "root:x:0:0:root:/root:/bin/bash\nsshd:x:74:74::/var/empty/sshd:/sbin/nologin\nlocal:x:1500:1500::/home/local:/bin/bash\n",
+        )
+        .unwrap();
+        fs::write(&files.group, "wheel:x:10:local\nlocal:x:1500:\n").unwrap();
+        files
+    }
+
+    fn config(&self) -> Config {
+        Config {
+            local_passwd: self.passwd.clone(),
+            local_group: self.group.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Drop for LocalFiles {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.passwd);
+        let _ = fs::remove_file(&self.group);
+    }
+}
+
+fn passwd(name: &str, uid: u32) -> Passwd {
+    Passwd {
+        name: name.to_string(),
+        passwd: "x".to_string(),
+        uid,
+        gid: uid,
+        gecos: String::new(),
+        dir: format!("/home/{}", name),
+        shell: "/bin/bash".to_string(),
+    }
+}
+
+fn group(name: &str, gid: u32) -> Group {
+    Group {
+        name: name.to_string(),
+        passwd: "x".to_string(),
+        gid,
+        members: vec![],
+    }
+}
+
+fn shadow(name: &str) -> Shadow {
+    Shadow::new(name, "!")
+        .last_change(0)
+        .change_min_days(0)
+        .change_max_days(99999)
+        .change_warn_days(7)
+}
+
+#[test]
+fn regular_users_pass() {
+    let files = LocalFiles::new("regular");
+    let policy = Policy::new(&files.config());
+    assert_eq!(policy.check_passwd(&passwd("alice", 2000)), Ok(()));
+    assert_eq!(policy.check_group(&group("alice", 2000)), Ok(()));
+    assert_eq!(policy.check_shadow(&shadow("alice")), Ok(()));
+}
+
+#[test]
+fn ids_outside_the_ranges_are_rejected() {
+    let files = LocalFiles::new("ranges");
+    let policy = Policy::new(&files.config());
+    assert!(policy.check_passwd(&passwd("evil", 0)).is_err());
+    assert!(policy.check_passwd(&passwd("daemonish", 999)).is_err());
+    assert!(policy.check_passwd(&passwd("nogroup", u32::MAX)).is_err());
+    assert!(policy.check_group(&group("wheelish", 10)).is_err());
+
+    let mut primary_root = passwd("alice", 2000);
+    primary_root.gid = 0;
+    assert!(policy.check_passwd(&primary_root).is_err());
+
+    let policy = Policy::new(&Config {
+        uid_ranges: vec![(5000, 5999)],
+        gid_ranges: vec![(5000, 5999), (7000, 7000)],
+        ..files.config()
+    });
+    assert!(policy.check_passwd(&passwd("alice", 2000)).is_err());
+    assert_eq!(policy.check_passwd(&passwd("alice", 5000)), Ok(()));
+    assert_eq!(policy.check_group(&group("ops", 7000)), Ok(()));
+}
+
+#[test]
+fn reserved_names_are_rejected() {
+    let files = LocalFiles::new("reserved");
+    let policy = Policy::new(&Config {
+        reserved_names: vec!["admin".to_string()],
+        ..files.config()
+    });
+    assert!(policy.check_passwd(&passwd("admin", 2000)).is_err());
+    assert!(policy.check_group(&group("admin", 2000)).is_err());
+    assert!(policy.check_shadow(&shadow("admin")).is_err());
+}
+
+#[test]
+fn local_accounts_cannot_be_shadowed() {
+    let files = LocalFiles::new("local");
+    let policy = Policy::new(&Config {
+        uid_ranges: vec![(0, u32::MAX)],
+        gid_ranges: vec![(0, u32::MAX)],
+        reserved_names: vec![],
+        ..files.config()
+    });
+
+    // By name, even with a harmless uid
+    assert!(policy.check_passwd(&passwd("sshd", 2000)).is_err());
+    assert!(policy.check_shadow(&shadow("root")).is_err());
+    assert!(policy.check_group(&group("wheel", 2000)).is_err());
+    // By id, even with a new name
+    assert!(policy.check_passwd(&passwd("alice", 1500)).is_err());
+    assert!(policy.check_group(&group("admins", 10)).is_err());
+}
+
+#[test]
+fn local_files_are_reread_when_they_change() {
+    let files = LocalFiles::new("reread");
+    let policy = Policy::new(&files.config());
+    assert_eq!(policy.check_passwd(&passwd("alice", 2000)), Ok(()));
+
+    // Make sure the modification time moves even on coarse filesystems
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+    fs::write(&files.passwd, "alice:x:3000:3000::/home/alice:/bin/bash\n").unwrap();
+    assert!(policy.check_passwd(&passwd("alice", 2000)).is_err());
+}
+
+#[test]
+fn shells_and_homes_are_restricted() {
+    let files = LocalFiles::new("shells");
+    let policy = Policy::new(&Config {
+        allowed_shells: vec!["/bin/bash".to_string(), "/bin/zsh".to_string()],
+        home_prefixes: vec!["/home".to_string(), "/srv/users/".to_string()],
+        ..files.config()
+    });
+    assert_eq!(policy.check_passwd(&passwd("alice", 2000)), Ok(()));
+
+    let mut user = passwd("alice", 2000);
+    user.shell = "/usr/bin/python3".to_string();
+    assert!(policy.check_passwd(&user).is_err());
+
+    for (dir, allowed) in [
+        ("/srv/users/alice", true),
+        ("/homes/alice", false),
+        ("/home/../etc", false),
+        ("home/alice", false),
+        ("/root", false),
+    ] {
+        let mut user = passwd("alice", 2000);
+        user.dir = dir.to_string();
+        assert_eq!(policy.check_passwd(&user).is_ok(), allowed, "{}", dir);
+    }
+}
+
+#[test]
+fn policy_is_configurable() {
+    let config = Config::from_lookup(|key| match key {
+        "NSS_HTTP_API_UID_RANGES" => Some("10000-19999, 30000".to_string()),
+        "NSS_HTTP_API_GID_RANGES" => Some("10000-x".to_string()),
+        "NSS_HTTP_API_RESERVED_NAMES" => Some("root,admin".to_string()),
+        "NSS_HTTP_API_ALLOWED_SHELLS" => Some("/bin/bash,/bin/sh".to_string()),
+        "NSS_HTTP_API_HOME_PREFIXES" => Some("/home".to_string()),
+        _ => None,
+    });
+    assert_eq!(config.uid_ranges, vec![(10000, 19999), (30000, 30000)]);
+    // A typo must not widen the policy
+    assert_eq!(config.gid_ranges, vec![]);
+    assert_eq!(config.reserved_names, vec!["root", "admin"]);
+    assert_eq!(config.allowed_shells, vec!["/bin/bash", "/bin/sh"]);
+    assert_eq!(config.home_prefixes, vec!["/home"]);
+}
+
+#[test]
+fn policy_never_comes_from_the_environment() {
+    let env = |key: &str| match key {
+        "NSS_HTTP_API_CONFIG" => Some("/nonexistent/nss_nya.conf".to_string()),
+        "NSS_HTTP_API_UID_RANGES" => Some("0-4294967294".to_string()),
+        "NSS_HTTP_API_GID_RANGES" => Some("0-4294967294".to_string()),
+        "NSS_HTTP_API_RESERVED_NAMES" => Some("".to_string()),
+        "NSS_HTTP_API_ALLOWED_SHELLS" => Some("/tmp/shell".to_string()),
+        "NSS_HTTP_API_HOME_PREFIXES" => Some("/".to_string()),
+        _ => None,
+    };
+    let defaults = Config::default();
+    for secure in [true, false] {
+        let config = Config::load_from(env, secure);
+        assert_eq!(config.uid_ranges, defaults.uid_ranges);
+        assert_eq!(config.gid_ranges, defaults.gid_ranges);
+        assert_eq!(config.reserved_names, defaults.reserved_names);
+        assert!(config.allowed_shells.is_empty());
+        assert!(config.home_prefixes.is_empty());
+    }
+}
@@ -0,0 +1,170 @@
+use std::path::PathBuf;
+
+use nss_nya::caller::Caller;
+use nss_nya::config::Config;
+use nss_nya::privilege::{capability, ShadowAccess};
+
+const CAP_DAC_OVERRIDE: u64 = 1 << 1;
+const CAP_DAC_READ_SEARCH: u64 = 1 << 2;
+const CAP_NET_BIND_SERVICE: u64 = 1 << 10;
+
+fn caller(euid: u32, cap_eff: u64, exe: &str) -> Caller {
+    Caller {
+        uid: euid,
+        euid,
+        gid: euid,
+        pid: 4242,
+        ppid: 1,
+        cap_eff,
+        exe: Some(PathBuf::from(exe)),
+    }
+}
+
+fn access(config: Config) -> ShadowAccess {
+    ShadowAccess::new(&config)
+}
+
+#[test]
+fn root_is_allowed() {
+    let access = access(Config::default());
+    assert_eq!(access.check(&caller(0, 0, "/usr/sbin/sshd")), Ok(()));
+}
+
+#[test]
+fn unprivileged_callers_are_refused() {
+    let access = access(Config::default());
+    assert!(access.check(&caller(1000, 0, "/usr/bin/id")).is_err());
+    // An unrelated capability does not help
+    assert!(access
+        .check(&caller(1000, CAP_NET_BIND_SERVICE, "/usr/bin/id"))
+        .is_err());
+}
+
+#[test]
+fn setuid_bit_alone_is_not_enough() {
+    // A setuid binary owned by someone else runs with that user's euid
+    let access = access(Config::default());
+    assert!(access
+        .check(&caller(1001, 0, "/usr/bin/setuid-tool"))
+        .is_err());
+}
+
+#[test]
+fn file_capabilities_are_allowed() {
+    let access = access(Config::default());
+    assert_eq!(
+        access.check(&caller(1000, CAP_DAC_READ_SEARCH, "/usr/bin/checker")),
+        Ok(())
+    );
+    assert_eq!(
+        access.check(&caller(1000, CAP_DAC_OVERRIDE, "/usr/bin/checker")),
+        Ok(())
+    );
+}
+
+#[test]
+fn uids_and_capabilities_are_configurable() {
+    let access = access(Config {
+        shadow_uids: vec![0, 990],
+        shadow_capabilities: vec!["CAP_DAC_READ_SEARCH".to_string()],
+        ..Default::default()
+    });
+    assert_eq!(access.check(&caller(990, 0, "/usr/bin/checker")), Ok(()));
+    assert!(access
+        .check(&caller(1000, CAP_DAC_OVERRIDE, "/usr/bin/checker"))
+        .is_err());
+
+    let access = self::access(Config {
+        shadow_uids: vec![],
+        shadow_capabilities: vec![],
+        ..Default::default()
+    });
+    assert!(access
+        .check(&caller(0, u64::MAX, "/usr/sbin/sshd"))
+        .is_err());
+}
+
+#[test]
+fn executable_allowlist_restricts_privileged_callers() {
+    let access = access(Config {
+        shadow_executables: vec!["/usr/sbin/sshd".to_string(), "login".to_string()],
+        ..Default::default()
+    });
+    assert_eq!(access.check(&caller(0, 0, "/usr/sbin/sshd")), Ok(()));
+    assert_eq!(access.check(&caller(0, 0, "/bin/login")), Ok(()));
+    assert!(access.check(&caller(0, 0, "/usr/bin/python3")).is_err());
+    // Paths must match exactly, names only match the file name
+    assert!(access.check(&caller(0, 0, "/tmp/usr/sbin/sshd")).is_err());
+    assert!(access
+        .check(&caller(0, 0, "/usr/bin/login-helper"))
+        .is_err());
+
+    let mut unknown = caller(0, 0, "/usr/sbin/sshd");
+    unknown.exe = None;
+    assert!(access.check(&unknown).is_err());
+}
+
+#[test]
+fn executable_allowlist_never_grants_access() {
+    let access = access(Config {
+        shadow_executables: vec!["sshd".to_string()],
+        ..Default::default()
+    });
+    assert!(access
+        .check(&caller(1000, 0, "/home/mallory/sshd"))
+        .is_err());
+}
+
+#[test]
+fn capabilities_are_parsed_by_name_or_number() {
+    assert_eq!(capability("CAP_DAC_READ_SEARCH"), Some(2));
+    assert_eq!(capability("dac_override"), Some(1));
+    assert_eq!(capability("21"), Some(21));
+    assert_eq!(capability("CAP_MADE_UP"), None);
+    assert_eq!(capability("64"), None);
+}
+
+#[test]
+fn shadow_access_is_configurable() {
+    let config = Config::from_lookup(|key| match key {
+        "NSS_HTTP_API_SHADOW_UIDS" => Some("0, 990".to_string()),
+        "NSS_HTTP_API_SHADOW_CAPABILITIES" => Some("CAP_DAC_READ_SEARCH".to_string()),
+        "NSS_HTTP_API_SHADOW_EXECUTABLES" => Some("/usr/sbin/sshd,login".to_string()),
+        _ => None,
+    });
+    assert_eq!(config.shadow_uids, vec![0, 990]);
+    assert_eq!(config.shadow_capabilities, vec!["CAP_DAC_READ_SEARCH"]);
+    assert_eq!(config.shadow_executables, vec!["/usr/sbin/sshd", "login"]);
+
+    let config = Config::from_lookup(|key| match key {
+        "NSS_HTTP_API_SHADOW_UIDS" => Some("zero".to_string()),
+        _ => None,
+    });
+    assert_eq!(config.shadow_uids, Vec::<u32>::new());
+}
+
+#[test]
+fn shadow_access_never_comes_from_the_environment() {
+    let env = |key: &str| match key {
+        "NSS_HTTP_API_CONFIG" => Some("/nonexistent/nss_nya.conf".to_string()),
+        "NSS_HTTP_API_SHADOW_UIDS" => Some("0,1000".to_string()),
+        "NSS_HTTP_API_SHADOW_CAPABILITIES" => Some("CAP_NET_BIND_SERVICE".to_string()),
+        "NSS_HTTP_API_SNAPSHOT_SHADOW" => Some("true".to_string()),
+        _ => None,
+    };
+    let defaults = Config::default();
+    for secure in [true, false] {
+        let config = Config::load_from(env, secure);
+        assert_eq!(config.shadow_uids, defaults.shadow_uids);
+        assert_eq!(config.shadow_capabilities, defaults.shadow_capabilities);
+        assert!(!config.snapshot_shadow);
+    }
+}
+
+#[test]
+fn current_process_is_described() {
+    let caller = Caller::current();
+    assert_eq!(caller.pid as u32, std::process::id());
+    assert_eq!(caller.exe, std::env::current_exe().ok());
+    assert_eq!(caller.euid, unsafe { libc::geteuid() });
+}
@@ -0,0 +1,301 @@
+use std::{
+    fs,
+    os::unix::prelude::{MetadataExt, PermissionsExt},
+    path::PathBuf,
+    time::Duration,
+};
+
+use nss_nya::client::NetworkReqResponse;
+use nss_nya::config::Config;
+use nss_nya::snapshot::Snapshot;
+use serde_json::json;
+
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        let dir =
+            std::env::temp_dir().join(format!("nya-snapshot-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        TempDir(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn euid() -> u32 {
+    unsafe { libc::geteuid() }
+}
+
+fn snapshot(dir: &TempDir, shadow: bool) -> Snapshot {
+    Snapshot::new(&Config {
+        snapshot_dir: Some(dir.0.clone()),
+        snapshot_shadow: shadow,
+        ..Default::default()
+    })
+    .unwrap()
+    .owned_by(euid())
+}
+
+fn alice() -> serde_json::Value {
+    json!({"name": "alice", "passwd": "x", "uid": 1000, "gid": 1000, "gecos": "", "dir": "/home/alice", "shell": "/bin/sh"})
+}
+
+#[test]
+fn single_lookups_are_replayed() {
+    let dir = TempDir::new("single");
+    let snapshot = snapshot(&dir, false);
+
+    snapshot.record(
+        "getpwnam",
+        &[("name", "alice".to_string())],
+        &NetworkReqResponse::Success(alice()),
+    );
+
+    assert_eq!(
+        snapshot.lookup("getpwuid", &[("uid", "1000".to_string())]),
+        Some(alice())
+    );
+    assert_eq!(
+        snapshot.lookup("getpwnam", &[("name", "alice".to_string())]),
+        Some(alice())
+    );
+    assert_eq!(
+        snapshot.lookup("getpwnam", &[("name", "bob".to_string())]),
+        None
+    );
+    assert_eq!(snapshot.lookup("getpwent", &[]), Some(json!([alice()])));
+}
+
+#[test]
+fn enumeration_replaces_and_not_found_removes() {
+    let dir = TempDir::new("enum");
+    let snapshot = snapshot(&dir, false);
+    let bob = json!({"name": "bob", "passwd": "x", "gid": 2000, "members": []});
+
+    snapshot.record(
+        "getgrent",
+        &[],
+        &NetworkReqResponse::Success(
+            json!([{"name": "staff", "passwd": "x", "gid": 50, "members": []}, bob]),
+        ),
+    );
+    snapshot.record(
+        "getgrnam",
+        &[("name", "staff".to_string())],
+        &NetworkReqResponse::NotFound,
+    );
+
+    assert_eq!(snapshot.lookup("getgrent", &[]), Some(json!([bob])));
+    assert_eq!(
+        snapshot.lookup("getgrgid", &[("gid", "50".to_string())]),
+        None
+    );
+}
+
+#[test]
+fn shadow_is_only_kept_when_enabled() {
+    let dir = TempDir::new("shadow");
+    let entry = json!({"name": "alice", "passwd": "$6$hash"});
+    let params = [("name", "alice".to_string())];
+
+    let without = snapshot(&dir, false);
+    without.record(
+        "getspnam",
+        &params,
+        &NetworkReqResponse::Success(entry.clone()),
+    );
+    assert!(!dir.0.join("shadow.json").exists());
+
+    let with = snapshot(&dir, true);
+    with.record(
+        "getspnam",
+        &params,
+        &NetworkReqResponse::Success(entry.clone()),
+    );
+    assert_eq!(with.lookup("getspnam", &params), Some(entry));
+
+    let mode = fs::metadata(dir.0.join("shadow.json")).unwrap().mode();
+    assert_eq!(mode & 0o077, 0);
+}
+
+#[test]
+fn corrupted_snapshot_is_ignored() {
+    let dir = TempDir::new("corrupt");
+    let snapshot = snapshot(&dir, false);
+    snapshot.record(
+        "getpwent",
+        &[],
+        &NetworkReqResponse::Success(json!([alice()])),
+    );
+
+    let path = dir.0.join("passwd.json");
+    let tampered = fs::read_to_string(&path)
+        .unwrap()
+        .replace("/bin/sh", "/bin/xx");
+    fs::write(&path, tampered).unwrap();
+
+    assert_eq!(snapshot.lookup("getpwent", &[]), None);
+}
+
+#[test]
+fn untrusted_directories_are_ignored() {
+    let dir = TempDir::new("untrusted");
+    let writer = snapshot(&dir, false);
+    writer.record(
+        "getpwent",
+        &[],
+        &NetworkReqResponse::Success(json!([alice()])),
+    );
+
+    // Someone else's snapshot is never trusted
+    let other = Snapshot::new(&Config {
+        snapshot_dir: Some(dir.0.clone()),
+        ..Default::default()
+    })
+    .unwrap()
+    .owned_by(euid() + 1);
+    assert_eq!(other.lookup("getpwent", &[]), None);
+
+    // Nor one anybody could have written to
+    fs::set_permissions(&dir.0, fs::Permissions::from_mode(0o777)).unwrap();
+    assert_eq!(writer.lookup("getpwent", &[]), None);
+    writer.record(
+        "getpwnam",
+        &[("name", "alice".to_string())],
+        &NetworkReqResponse::NotFound,
+    );
+
+    fs::set_permissions(&dir.0, fs::Permissions::from_mode(0o755)).unwrap();
+    assert_eq!(writer.lookup("getpwent", &[]), Some(json!([alice()])));
+}
+
+#[test]
+fn only_the_owner_records() {
+    let dir = TempDir::new("owner");
+    let reader = Snapshot::new(&Config {
+        snapshot_dir: Some(dir.0.clone()),
+        ..Default::default()
+    })
+    .unwrap()
+    .owned_by(euid() + 1);
+    reader.record(
+        "getpwent",
+        &[],
+        &NetworkReqResponse::Success(json!([alice()])),
+    );
+    // Not even the lock file is tried
+    assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 0);
+}
+
+#[test]
+fn symlinks_are_not_followed() {
+    let dir = TempDir::new("symlinks");
+    let elsewhere = TempDir::new("symlinks-target");
+    snapshot(&elsewhere, false).record(
+        "getpwent",
+        &[],
+        &NetworkReqResponse::Success(json!([alice()])),
+    );
+
+    let snapshot = snapshot(&dir, false);
+    std::os::unix::fs::symlink(elsewhere.0.join("passwd.json"), dir.0.join("passwd.json")).unwrap();
+    assert_eq!(snapshot.lookup("getpwent", &[]), None);
+
+    let target = elsewhere.0.join("target");
+    std::os::unix::fs::symlink(&target, dir.0.join(".lock")).unwrap();
+    snapshot.record("getgrent", &[], &NetworkReqResponse::Success(json!([])));
+    assert!(!target.exists());
+    assert!(!dir.0.join("group.json").exists());
+}
+
+#[test]
+fn stale_entries_are_not_served() {
+    let dir = TempDir::new("stale");
+    let writer = snapshot(&dir, false);
+    writer.record(
+        "getpwent",
+        &[],
+        &NetworkReqResponse::Success(json!([alice()])),
+    );
+
+    let reader = Snapshot::new(&Config {
+        snapshot_dir: Some(dir.0.clone()),
+        snapshot_max_age: Duration::from_secs(0),
+        ..Default::default()
+    })
+    .unwrap()
+    .owned_by(euid());
+    std::thread::sleep(Duration::from_millis(1100));
+
+    assert_eq!(reader.lookup("getpwent", &[]), None);
+}
+
+#[test]
+fn memberships_are_derived_from_groups() {
+    let dir = TempDir::new("initgroups");
+    let snapshot = snapshot(&dir, false);
+    let wheel = json!({"name": "wheel", "passwd": "x", "gid": 10, "members": ["alice"]});
+    let audio = json!({"name": "audio", "passwd": "x", "gid": 29, "members": ["bob"]});
+    snapshot.record(
+        "getgrent",
+        &[],
+        &NetworkReqResponse::Success(json!([wheel, audio])),
+    );
+
+    // Membership answers never overwrite group entries
+    snapshot.record(
+        "initgroups",
+        &[("name", "alice".to_string())],
+        &NetworkReqResponse::Success(json!([audio])),
+    );
+
+    assert_eq!(
+        snapshot.lookup("initgroups", &[("name", "alice".to_string())]),
+        Some(json!([wheel]))
+    );
+    assert_eq!(
+        snapshot.lookup("initgroups", &[("name", "carol".to_string())]),
+        None
+    );
+    assert_eq!(
+        snapshot.lookup("getgrent", &[]),
+        Some(json!([wheel, audio]))
+    );
+}
+
+#[test]
+fn changes_touch_the_nscd_trigger() {
+    let dir = TempDir::new("trigger");
+    let snapshot = snapshot(&dir, false);
+    let trigger = dir.0.join("passwd.changed");
+    let paths = snapshot
+        .triggers()
+        .iter()
+        .map(|trigger| trigger.path().to_path_buf())
+        .collect::<Vec<_>>();
+    assert_eq!(paths, vec![trigger.clone(), dir.0.join("group.changed")]);
+
+    let lookup = [("name", "alice".to_string())];
+    snapshot.record("getpwnam", &lookup, &NetworkReqResponse::Success(alice()));
+    assert!(trigger.exists());
+
+    // Unchanged entries leave the trigger alone, even when rewritten
+    fs::remove_file(&trigger).unwrap();
+    snapshot.record(
+        "getpwent",
+        &[],
+        &NetworkReqResponse::Success(json!([alice()])),
+    );
+    assert!(!trigger.exists());
+
+    snapshot.record("getpwnam", &lookup, &NetworkReqResponse::NotFound);
+    assert!(trigger.exists());
+    assert!(!dir.0.join("group.changed").exists());
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::{passwd_json, MockServer};
+use nss_nya::client::{ApiClient, NetworkReqResponse};
+use nss_nya::config::Config;
+
+fn client(url: &str, templates: &[(&str, &str)]) -> ApiClient {
+    ApiClient::new(Config {
+        endpoints: vec![format!("{}/", url)],
+        url_templates: templates
+            .iter()
+            .map(|(op, template)| (op.to_string(), template.to_string()))
+            .collect::<HashMap<_, _>>(),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn parameters_are_percent_encoded() {
+    let server = MockServer::start(|_| (200, passwd_json("alice", 1000)));
+
+    let name = "a b/c?d&e=f#g%h+ü~._-";
+    client(&server.url, &[]).request("getpwnam", &[("name", name.to_string())]);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(
+        requests[0].url,
+        "/passwd?name=a%20b%2Fc%3Fd%26e%3Df%23g%25h%2B%C3%BC~._-"
+    );
+}
+
+#[test]
+fn path_style_templates() {
+    let server = MockServer::start(|_| (200, passwd_json("alice", 1000)));
+
+    let client = client(
+        &server.url,
+        &[
+            ("getpwnam", "{base}/users/{name}"),
+            ("getpwuid", "{base}/users/by-uid/{uid}/entry.json"),
+        ],
+    );
+    client.request("getpwnam", &[("name", "../admin".to_string())]);
+    client.request("getpwuid", &[("uid", "1000".to_string())]);
+    // Operations without a template of their own keep the default one
+    client.request("getgrnam", &[("name", "staff".to_string())]);
+
+    let urls: Vec<String> = server.requests().into_iter().map(|r| r.url).collect();
+    assert_eq!(
+        urls,
+        [
+            "/users/..%2Fadmin",
+            "/users/by-uid/1000/entry.json",
+            "/group?name=staff",
+        ]
+    );
+}
+
+#[test]
+fn unknown_placeholders_are_not_sent() {
+    let server = MockServer::start(|_| (200, passwd_json("alice", 1000)));
+
+    let client = client(&server.url, &[("getpwnam", "{base}/users/{login}")]);
+    assert!(matches!(
+        client.request("getpwnam", &[("name", "alice".to_string())]),
+        NetworkReqResponse::Error(_)
+    ));
+    assert!(server.requests().is_empty());
+}
@@ -0,0 +1,236 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    net::TcpListener,
+    path::PathBuf,
+    sync::Arc,
+    thread,
+};
+
+use nss_nya::client::{ApiClient, NetworkReqResponse};
+use nss_nya::config::Config;
+use nss_nya::tls::spki_pin;
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa,
+};
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls::{PrivateKey, RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+
+const BODY: &str = r#"{"name":"alice","passwd":"x","uid":1000,"gid":1000,"gecos":"","dir":"/home/alice","shell":"/bin/sh"}"#;
+
+struct Pki {
+    dir: PathBuf,
+    ca: Certificate,
+    server: Certificate,
+}
+
+impl Pki {
+    fn new(name: &str) -> Pki {
+        let dir = std::env::temp_dir().join(format!("nya-tls-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut ca = CertificateParams::new(vec![]);
+        ca.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca.distinguished_name
+            .push(DnType::CommonName, "nya test CA");
+        let ca = Certificate::from_params(ca).unwrap();
+        let server =
+            Certificate::from_params(CertificateParams::new(vec!["localhost".to_string()]))
+                .unwrap();
+
+        fs::write(dir.join("ca.pem"), ca.serialize_pem().unwrap()).unwrap();
+        Pki { dir, ca, server }
+    }
+
+    fn ca_bundle(&self) -> PathBuf {
+        self.dir.join("ca.pem")
+    }
+
+    fn server_der(&self) -> Vec<u8> {
+        self.server.serialize_der_with_signer(&self.ca).unwrap()
+    }
+
+    /// Issues a client certificate and returns its (cert, key) paths.
+    fn client_identity(&self) -> (PathBuf, PathBuf) {
+        let mut params = CertificateParams::new(vec!["client".to_string()]);
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        let client = Certificate::from_params(params).unwrap();
+
+        let (cert, key) = (self.dir.join("client.pem"), self.dir.join("client.key"));
+        fs::write(&cert, client.serialize_pem_with_signer(&self.ca).unwrap()).unwrap();
+        fs::write(&key, client.serialize_private_key_pem()).unwrap();
+        (cert, key)
+    }
+
+    /// Serves `BODY` over TLS, optionally requiring a client certificate.
+    fn serve(&self, require_client_cert: bool) -> String {
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = match require_client_cert {
+            true => {
+                let mut roots = RootCertStore::empty();
+                roots
+                    .add(&rustls::Certificate(self.ca.serialize_der().unwrap()))
+                    .unwrap();
+                builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots))
+            }
+            false => builder.with_no_client_auth(),
+        };
+        let config = Arc::new(
+            builder
+                .with_single_cert(
+                    vec![
+                        rustls::Certificate(self.server_der()),
+                        rustls::Certificate(self.ca.serialize_der().unwrap()),
+                    ],
+                    PrivateKey(self.server.serialize_private_key_der()),
+                )
+                .unwrap(),
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let connection = ServerConnection::new(config.clone()).unwrap();
+                let mut tls = StreamOwned::new(connection, stream);
+                let mut request = [0u8; 4096];
+                if tls.read(&mut request).is_err() {
+                    continue;
+                }
+                let _ = write!(
+                    tls,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    BODY.len(),
+                    BODY
+                );
+                let _ = tls.flush();
+            }
+        });
+
+        format!("https://localhost:{}", port)
+    }
+}
+
+impl Drop for Pki {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn lookup(config: Config) -> NetworkReqResponse {
+    ApiClient::new(config).request("getpwnam", &[("name", "alice".to_string())])
+}
+
+#[test]
+fn private_ca_requires_bundle() {
+    let pki = Pki::new("bundle");
+    let url = pki.serve(false);
+
+    assert!(matches!(
+        lookup(Config {
+            endpoints: vec![url.clone()],
+            ..Default::default()
+        }),
+        NetworkReqResponse::Unavail
+    ));
+    assert!(matches!(
+        lookup(Config {
+            endpoints: vec![url],
+            ca_bundle: Some(pki.ca_bundle()),
+            public_roots: false,
+            ..Default::default()
+        }),
+        NetworkReqResponse::Success(_)
+    ));
+}
+
+#[test]
+fn tls_is_set_up_once_per_client() {
+    let pki = Pki::new("once");
+    let url = pki.serve(false);
+    let client = ApiClient::new(Config {
+        endpoints: vec![url],
+        ca_bundle: Some(pki.ca_bundle()),
+        public_roots: false,
+        ..Default::default()
+    });
+    let lookup = || client.request("getpwnam", &[("name", "alice".to_string())]);
+
+    assert!(matches!(lookup(), NetworkReqResponse::Success(_)));
+    // Later lookups do not read the bundle again
+    fs::remove_file(pki.ca_bundle()).unwrap();
+    assert!(matches!(lookup(), NetworkReqResponse::Success(_)));
+}
+
+#[test]
+fn pinned_key_must_match() {
+    let pki = Pki::new("pin");
+    let url = pki.serve(false);
+    let pin = spki_pin(&pki.server_der()).unwrap();
+    let other = spki_pin(&pki.ca.serialize_der().unwrap()).unwrap();
+
+    let config = |pins: Vec<String>| Config {
+        endpoints: vec![url.clone()],
+        ca_bundle: Some(pki.ca_bundle()),
+        pinned_pubkeys: pins,
+        ..Default::default()
+    };
+
+    assert!(matches!(
+        lookup(config(vec![pin.clone()])),
+        NetworkReqResponse::Success(_)
+    ));
+    assert!(matches!(
+        lookup(config(vec![
+            "sha256//AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_string()
+        ])),
+        NetworkReqResponse::Unavail
+    ));
+    // Pinning a key from the presented chain accepts every certificate it signs
+    assert!(matches!(
+        lookup(config(vec![other])),
+        NetworkReqResponse::Success(_)
+    ));
+}
+
+#[test]
+fn invalid_pin_fails_closed() {
+    let pki = Pki::new("badpin");
+    let url = pki.serve(false);
+
+    assert!(matches!(
+        lookup(Config {
+            endpoints: vec![url],
+            ca_bundle: Some(pki.ca_bundle()),
+            pinned_pubkeys: vec!["md5//nope".to_string()],
+            ..Default::default()
+        }),
+        NetworkReqResponse::Error(_)
+    ));
+}
+
+#[test]
+fn mutual_tls_presents_client_certificate() {
+    let pki = Pki::new("mtls");
+    let url = pki.serve(true);
+    let (cert, key) = pki.client_identity();
+
+    assert!(matches!(
+        lookup(Config {
+            endpoints: vec![url.clone()],
+            ca_bundle: Some(pki.ca_bundle()),
+            ..Default::default()
+        }),
+        NetworkReqResponse::Unavail
+    ));
+    assert!(matches!(
+        lookup(Config {
+            endpoints: vec![url],
+            ca_bundle: Some(pki.ca_bundle()),
+            client_cert: Some(cert),
+            client_key: Some(key),
+            ..Default::default()
+        }),
+        NetworkReqResponse::Success(_)
+    ));
+}